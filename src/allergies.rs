@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::load_config;
+use crate::database::{load_database, save_database, Medication, MedicationDatabase};
+use crate::interactions::drug_terms;
+
+/// Small built-in class mapping, always loaded (see `data/drug_classes.csv`)
+const BUNDLED_DRUG_CLASSES: &str = include_str!("../data/drug_classes.csv");
+
+/// A recorded allergy or contraindication, e.g. "penicillin" or "sulfa"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Allergy {
+    /// Lowercase drug, ingredient or drug class name
+    pub name: String,
+    /// What happened, e.g. "hives"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reaction: Option<String>,
+}
+
+impl Allergy {
+    pub fn describe(&self) -> String {
+        match &self.reaction {
+            Some(reaction) => format!("{} ({})", self.name, reaction),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Drug class name -> member drugs, all lowercase
+pub type DrugClasses = BTreeMap<String, Vec<String>>;
+
+/// Parse a class mapping in CSV form: one `class,drug` pair per line.
+///
+/// Blank lines, `#` comments and a `class,drug` header are skipped.
+pub fn parse_classes_csv(contents: &str) -> Result<DrugClasses, String> {
+    let mut classes = DrugClasses::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((class, drug)) = line.split_once(',') else {
+            return Err(format!("line {}: expected class,drug", number + 1));
+        };
+        let class = class.trim().to_lowercase();
+        let drug = drug.trim().to_lowercase();
+        if class.is_empty() || drug.is_empty() {
+            return Err(format!("line {}: expected class,drug", number + 1));
+        }
+        if class == "class" && drug == "drug" {
+            continue; // header
+        }
+
+        classes.entry(class).or_default().push(drug);
+    }
+
+    Ok(classes)
+}
+
+/// Parse a class mapping in JSON form: `{"penicillin": ["amoxicillin", ...]}`
+pub fn parse_classes_json(contents: &str) -> Result<DrugClasses, String> {
+    let raw: DrugClasses = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    Ok(raw
+        .into_iter()
+        .map(|(class, drugs)| {
+            let drugs = drugs.iter().map(|d| d.trim().to_lowercase()).collect();
+            (class.trim().to_lowercase(), drugs)
+        })
+        .collect())
+}
+
+/// Reads a user-supplied mapping; `.json` files are JSON, anything else CSV
+fn read_classes_file(path: &Path) -> Result<DrugClasses, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        parse_classes_json(&contents)
+    } else {
+        parse_classes_csv(&contents)
+    }
+}
+
+/// The bundled mapping merged with the user's `drug_classes_file`, if configured
+pub fn load_drug_classes() -> DrugClasses {
+    let mut classes = parse_classes_csv(BUNDLED_DRUG_CLASSES).unwrap_or_default();
+
+    if let Some(path) = load_config().drug_classes_file {
+        match read_classes_file(&path) {
+            Ok(extra) => {
+                for (class, drugs) in extra {
+                    classes.entry(class).or_default().extend(drugs);
+                }
+            }
+            Err(e) => eprintln!(
+                "Warning: Failed to read drug classes file {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    classes
+}
+
+/// Explains why `med` may conflict with each of the recorded allergies.
+///
+/// A medication matches an allergy when its name (or a word in it) or one of
+/// its ingredients:
+/// - is the allergy itself,
+/// - belongs to the allergy's drug class ("amoxicillin" for "penicillin"), or
+/// - shares a drug class with the allergy ("ampicillin" for "amoxicillin").
+pub fn allergy_conflicts(
+    med: &Medication,
+    allergies: &[Allergy],
+    classes: &DrugClasses,
+) -> Vec<String> {
+    let terms = drug_terms(med);
+    let mut conflicts = Vec::new();
+
+    for allergy in allergies {
+        let allergen = allergy.name.as_str();
+
+        if terms.iter().any(|t| t == allergen) {
+            conflicts.push(format!("matches your allergy: {}", allergy.describe()));
+            continue;
+        }
+
+        if let Some(member) = classes
+            .get(allergen)
+            .and_then(|members| members.iter().find(|m| terms.contains(m)))
+        {
+            conflicts.push(format!(
+                "contains {}, which is a {} (allergy: {})",
+                member,
+                allergen,
+                allergy.describe()
+            ));
+            continue;
+        }
+
+        let related = classes.iter().find_map(|(class, members)| {
+            if !members.iter().any(|m| m == allergen) {
+                return None;
+            }
+            members
+                .iter()
+                .chain(std::iter::once(class))
+                .find(|m| terms.contains(m))
+                .map(|m| (class, m))
+        });
+        if let Some((class, member)) = related {
+            conflicts.push(format!(
+                "contains {}, in the same class ({}) as your allergy: {}",
+                member,
+                class,
+                allergy.describe()
+            ));
+        }
+    }
+
+    conflicts
+}
+
+/// Warns about allergy conflicts for `med` and asks whether to go ahead.
+///
+/// Returns true if there is no conflict, `yes` is set, or the user confirms.
+pub fn confirm_allergy_conflicts(med: &Medication, db: &MedicationDatabase, yes: bool) -> bool {
+    if db.allergies.is_empty() {
+        return true;
+    }
+
+    let conflicts = allergy_conflicts(med, &db.allergies, &load_drug_classes());
+    if conflicts.is_empty() {
+        return true;
+    }
+
+    println!("Warning: '{}' may conflict with your allergies:", med.name);
+    for conflict in &conflicts {
+        println!("  - {}", conflict);
+    }
+
+    if yes {
+        println!("  Continuing because --yes was given.");
+        return true;
+    }
+    crate::prompt::confirm("Continue anyway?")
+}
+
+/// Records a new allergy and warns about active medications that match it
+pub fn add_allergy(name: String, reaction: Option<String>) {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        eprintln!("Error: Allergy name cannot be empty!");
+        return;
+    }
+
+    let mut db = load_database();
+    if db.allergies.iter().any(|a| a.name == name) {
+        eprintln!("Error: Allergy '{}' is already recorded", name);
+        return;
+    }
+
+    let allergy = Allergy {
+        name,
+        reaction: reaction.filter(|r| !r.trim().is_empty()),
+    };
+    println!("Recorded allergy: {}", allergy.describe());
+    db.allergies.push(allergy);
+    save_database(&db);
+
+    let classes = load_drug_classes();
+    let latest = &db.allergies[db.allergies.len() - 1..];
+    for med in &db.medications {
+        for conflict in allergy_conflicts(med, latest, &classes) {
+            println!("  Warning: {} (#{}) {}", med.name, med.id, conflict);
+        }
+    }
+}
+
+/// Removes a recorded allergy
+pub fn remove_allergy(name: String) {
+    let name = name.trim().to_lowercase();
+    let mut db = load_database();
+
+    let Some(index) = db.allergies.iter().position(|a| a.name == name) else {
+        eprintln!("Error: No allergy '{}' recorded", name);
+        return;
+    };
+
+    db.allergies.remove(index);
+    save_database(&db);
+    println!("Removed allergy: {}", name);
+}
+
+/// Lists recorded allergies and any active medications that conflict
+pub fn list_allergies() {
+    let db = load_database();
+
+    if db.allergies.is_empty() {
+        println!("No allergies recorded.");
+        println!("Add one with: pharm allergy add <name> [--reaction <reaction>]");
+        return;
+    }
+
+    println!("\nAllergies:");
+    println!("{}", "=".repeat(60));
+    for allergy in &db.allergies {
+        println!("  {}", allergy.describe());
+    }
+
+    let classes = load_drug_classes();
+    let mut warned = false;
+    for med in &db.medications {
+        for conflict in allergy_conflicts(med, &db.allergies, &classes) {
+            if !warned {
+                println!("\nActive medications that may conflict:");
+                warned = true;
+            }
+            println!("  {} (#{}) {}", med.name, med.id, conflict);
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingredients::Ingredient;
+
+    fn med(name: &str, ingredients: &[&str]) -> Medication {
+        Medication {
+            name: name.to_string(),
+            ingredients: ingredients
+                .iter()
+                .map(|n| Ingredient {
+                    name: n.to_string(),
+                    amount_mg: 1.0,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn allergy(name: &str) -> Allergy {
+        Allergy {
+            name: name.to_string(),
+            reaction: None,
+        }
+    }
+
+    fn classes() -> DrugClasses {
+        parse_classes_csv(
+            "class,drug\npenicillin,amoxicillin\npenicillin,ampicillin\nsulfa,bactrim",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bundled_classes_parse() {
+        let classes = parse_classes_csv(BUNDLED_DRUG_CLASSES).unwrap();
+        assert!(classes["penicillin"].contains(&"amoxicillin".to_string()));
+        assert!(classes.contains_key("sulfa"));
+    }
+
+    #[test]
+    fn test_parse_classes() {
+        assert_eq!(classes()["penicillin"], vec!["amoxicillin", "ampicillin"]);
+        assert!(parse_classes_csv("just-one-field").is_err());
+
+        let json = parse_classes_json(r#"{"Penicillin": ["Amoxicillin"]}"#).unwrap();
+        assert_eq!(json["penicillin"], vec!["amoxicillin"]);
+    }
+
+    #[test]
+    fn test_direct_match() {
+        let conflicts = allergy_conflicts(
+            &med("Sulfasalazine", &[]),
+            &[allergy("sulfasalazine")],
+            &classes(),
+        );
+        assert_eq!(conflicts.len(), 1);
+
+        // By ingredient
+        let augmentin = med("Augmentin", &["amoxicillin", "clavulanate"]);
+        assert_eq!(
+            allergy_conflicts(&augmentin, &[allergy("amoxicillin")], &classes()).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_class_matches() {
+        // Allergy to the class matches a member
+        let amoxil = med("Amoxicillin 500", &[]);
+        let conflicts = allergy_conflicts(&amoxil, &[allergy("penicillin")], &classes());
+        assert!(conflicts[0].contains("which is a penicillin"));
+
+        // Allergy to a member matches the rest of its class
+        let ampicillin = med("Ampicillin", &[]);
+        let conflicts = allergy_conflicts(&ampicillin, &[allergy("amoxicillin")], &classes());
+        assert!(conflicts[0].contains("same class (penicillin)"));
+
+        // Unrelated medication
+        let tylenol = med("Tylenol", &["acetaminophen"]);
+        assert!(allergy_conflicts(
+            &tylenol,
+            &[allergy("penicillin"), allergy("sulfa")],
+            &classes()
+        )
+        .is_empty());
+    }
+}
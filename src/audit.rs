@@ -0,0 +1,587 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::{get_data_file, Medication, MedicationDatabase};
+
+/// One field of a medication that changed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// One line of the audit log: everything that changed for one medication in
+/// one save.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub timestamp: String, // "2025-10-21 08:30:15"
+    pub command: String,   // Subcommand name, e.g. "edit"
+    #[serde(default)]
+    pub arguments: String, // Full command line, e.g. "edit Aspirin --dose 1000mg"
+    #[serde(default)]
+    pub medication_id: u32,
+    pub medication: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Subcommand currently running, set once by `main`.
+static COMMAND: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the command name recorded with every audit entry from this process.
+pub fn set_command(name: &str) {
+    *COMMAND.lock().unwrap() = Some(name.to_string());
+}
+
+/// The run of a command that saves currently belong to: 0 for the command
+/// the process was started with, a new number for each `with_command` call
+static OPERATION: AtomicU64 = AtomicU64::new(0);
+static NEXT_OPERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Runs `f` with a different command name recorded, e.g. "migrate" for
+/// automatic format upgrades made while loading the database. Its saves are
+/// a separate operation from everything else the process does (see
+/// `current_operation`).
+pub fn with_command<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let previous = COMMAND.lock().unwrap().replace(name.to_string());
+    let operation = NEXT_OPERATION.fetch_add(1, Ordering::SeqCst);
+    let previous_operation = OPERATION.swap(operation, Ordering::SeqCst);
+    let result = f();
+    OPERATION.store(previous_operation, Ordering::SeqCst);
+    *COMMAND.lock().unwrap() = previous;
+    result
+}
+
+/// Identifies the command run that is saving, so the journal undoes the
+/// saves of one run together - and never merges two `take` requests handled
+/// hours apart by the daemon.
+pub fn current_operation() -> u64 {
+    OPERATION.load(Ordering::SeqCst)
+}
+
+/// Returns the subcommand set by `set_command` ("pharm" if none was set).
+pub fn current_command() -> String {
+    COMMAND
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| "pharm".to_string())
+}
+
+/// Returns this process's command line (without the program name), quoting
+/// arguments that contain whitespace.
+pub fn current_arguments() -> String {
+    std::env::args()
+        .skip(1)
+        .map(|a| {
+            if a.is_empty() || a.contains(char::is_whitespace) {
+                format!("\"{}\"", a)
+            } else {
+                a
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Re-writes every audit log line in the database's current storage format.
+///
+/// Used by `pharm encrypt`/`pharm decrypt`. The entries themselves are kept
+/// exactly as they were; only their encoding changes.
+pub fn rewrite_audit_log() {
+    let file_path = get_audit_file();
+    let Ok(contents) = fs::read_to_string(&file_path) else {
+        return;
+    };
+
+    let mut lines = Vec::new();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let rewritten = if crate::crypto::is_encrypted(line) {
+            crate::crypto::decrypt_contents(line)
+        } else {
+            Ok(line.to_string())
+        }
+        .and_then(crate::crypto::seal_line);
+
+        match rewritten {
+            Ok(l) => lines.push(l),
+            Err(e) => {
+                eprintln!("Warning: Audit log left unchanged: {}", e);
+                return;
+            }
+        }
+    }
+
+    let temp_path = file_path.with_extension("jsonl.tmp");
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let written = options
+        .open(&temp_path)
+        .and_then(|mut f| f.write_all((lines.join("\n") + "\n").as_bytes()))
+        .and_then(|_| fs::rename(&temp_path, &file_path));
+
+    if let Err(e) = written {
+        eprintln!("Warning: Failed to rewrite audit log: {}", e);
+        let _ = fs::remove_file(&temp_path);
+    }
+}
+
+/// Returns the path to the audit log (`~/.pharm.audit.jsonl`).
+pub fn get_audit_file() -> PathBuf {
+    get_data_file().with_extension("audit.jsonl")
+}
+
+/// Appends one entry per changed medication to the audit log.
+///
+/// Called by `save_database` after every successful save, with the database
+/// as it was on disk before the save. Entries are encrypted (one envelope per
+/// line) when the database is encrypted.
+pub fn record(before: Option<&MedicationDatabase>, after: &MedicationDatabase) {
+    let empty = MedicationDatabase::default();
+    let changed = diff_databases(before.unwrap_or(&empty), after);
+    if changed.is_empty() {
+        return;
+    }
+
+    let command = current_command();
+    let arguments = current_arguments();
+    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = match options.open(get_audit_file()) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Warning: Failed to open audit log: {}", e);
+            return;
+        }
+    };
+
+    for (medication_id, medication, changes) in changed {
+        let entry = AuditEntry {
+            timestamp: timestamp.clone(),
+            command: command.clone(),
+            arguments: arguments.clone(),
+            medication_id,
+            medication,
+            changes,
+        };
+
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(crate::crypto::seal_line);
+
+        match line {
+            Ok(line) => {
+                if let Err(e) = writeln!(file, "{}", line) {
+                    eprintln!("Warning: Failed to write audit log: {}", e);
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Warning: Failed to record audit entry: {}", e),
+        }
+    }
+}
+
+/// Compares two databases and returns (id, name, changed fields) for every
+/// medication that changed.
+pub fn diff_databases(
+    before: &MedicationDatabase,
+    after: &MedicationDatabase,
+) -> Vec<(u32, String, Vec<FieldChange>)> {
+    fn index(db: &MedicationDatabase) -> BTreeMap<u32, (&'static str, &Medication)> {
+        let mut map = BTreeMap::new();
+        for med in &db.medications {
+            map.insert(med.id, ("active", med));
+        }
+        for med in &db.archived_medications {
+            map.entry(med.id).or_insert(("archived", med));
+        }
+        map
+    }
+
+    let old = index(before);
+    let new = index(after);
+
+    let mut ids: Vec<u32> = old.keys().chain(new.keys()).copied().collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut result = Vec::new();
+    for id in ids {
+        let before_med = old.get(&id);
+        let after_med = new.get(&id);
+        let changes = diff_medication(before_med.copied(), after_med.copied());
+        if !changes.is_empty() {
+            let name = after_med.or(before_med).map(|(_, m)| m.name.clone());
+            result.push((id, name.unwrap_or_default(), changes));
+        }
+    }
+
+    // Allergies aren't tied to a medication; they are logged under ID 0
+    if before.allergies != after.allergies {
+        let describe = |db: &MedicationDatabase| {
+            let list: Vec<String> = db.allergies.iter().map(|a| a.describe()).collect();
+            (!list.is_empty()).then(|| list.join(", "))
+        };
+        result.push((
+            0,
+            "allergies".to_string(),
+            vec![FieldChange {
+                field: "allergies".to_string(),
+                old: describe(before),
+                new: describe(after),
+            }],
+        ));
+    }
+    result
+}
+
+/// Field-level diff of one medication; `None` means it doesn't exist on that
+/// side. Fields are compared generically through their JSON form so new
+/// `Medication` fields are audited without extra code.
+fn diff_medication(
+    before: Option<(&str, &Medication)>,
+    after: Option<(&str, &Medication)>,
+) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+
+    let old_status = before.map(|(s, _)| s.to_string());
+    let new_status = after.map(|(s, _)| s.to_string());
+    if old_status != new_status {
+        changes.push(FieldChange {
+            field: "status".to_string(),
+            old: old_status,
+            new: new_status,
+        });
+    }
+
+    let to_map = |m: Option<(&str, &Medication)>| {
+        m.and_then(|(_, med)| serde_json::to_value(med).ok())
+            .and_then(|v| v.as_object().cloned())
+            .unwrap_or_default()
+    };
+    let old_fields = to_map(before);
+    let new_fields = to_map(after);
+
+    let mut fields: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    for field in fields {
+        if field == "history" {
+            continue;
+        }
+        let old = old_fields.get(field).and_then(display_value);
+        let new = new_fields.get(field).and_then(display_value);
+        if old != new {
+            changes.push(FieldChange {
+                field: field.clone(),
+                old,
+                new,
+            });
+        }
+    }
+
+    // History only ever grows or shrinks at the end, so report the records
+    // that were removed or appended rather than the whole list
+    let old_history = before.map(|(_, m)| m.history.as_slice()).unwrap_or(&[]);
+    let new_history = after.map(|(_, m)| m.history.as_slice()).unwrap_or(&[]);
+    let common = old_history
+        .iter()
+        .zip(new_history.iter())
+        .take_while(|(a, b)| a.timestamp == b.timestamp && a.dose == b.dose)
+        .count();
+
+    let describe = |records: &[crate::database::DoseRecord]| {
+        if records.is_empty() {
+            None
+        } else {
+            Some(
+                records
+                    .iter()
+                    .map(|r| format!("{} ({})", r.timestamp, r.dose))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            )
+        }
+    };
+
+    let removed = describe(&old_history[common..]);
+    let added = describe(&new_history[common..]);
+    if removed.is_some() || added.is_some() {
+        changes.push(FieldChange {
+            field: "history".to_string(),
+            old: removed,
+            new: added,
+        });
+    }
+
+    changes
+}
+
+/// Renders a JSON value for the log; null and empty strings count as "no value".
+fn display_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) if s.is_empty() => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Displays the audit log, newest first.
+///
+/// # Arguments
+/// * `medication` - Only show entries for this medication (ID or name)
+/// * `command` - Only show entries recorded by this subcommand (e.g. "edit")
+/// * `days` - Only show entries from the last N days
+/// * `limit` - Maximum number of entries to show (default: 50)
+pub fn show_log(
+    medication: Option<String>,
+    command: Option<String>,
+    days: Option<u32>,
+    limit: Option<usize>,
+) {
+    let file_path = get_audit_file();
+    let contents = match fs::read_to_string(&file_path) {
+        Ok(c) => c,
+        Err(_) => {
+            println!("No audit log found at {}", file_path.display());
+            return;
+        }
+    };
+
+    let cutoff =
+        days.map(|d| chrono::Local::now().naive_local() - chrono::Duration::days(d as i64));
+    let medication_id = medication
+        .as_ref()
+        .and_then(|m| m.trim().trim_start_matches('#').parse::<u32>().ok());
+    let medication = medication.map(|m| m.trim().to_lowercase());
+    let command = command.map(|c| c.to_lowercase());
+
+    let mut unreadable = 0;
+    let mut entries: Vec<AuditEntry> = Vec::new();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let json = if crate::crypto::is_encrypted(line) {
+            match crate::crypto::decrypt_contents(line) {
+                Ok(plaintext) => plaintext,
+                Err(_) => {
+                    unreadable += 1;
+                    continue;
+                }
+            }
+        } else {
+            line.to_string()
+        };
+
+        match serde_json::from_str::<AuditEntry>(&json) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => unreadable += 1,
+        }
+    }
+
+    let filtered: Vec<&AuditEntry> = entries
+        .iter()
+        .rev()
+        .filter(|e| {
+            medication.as_ref().is_none_or(|m| {
+                medication_id == Some(e.medication_id) || e.medication.to_lowercase() == *m
+            })
+        })
+        .filter(|e| {
+            command
+                .as_ref()
+                .is_none_or(|c| e.command.to_lowercase() == *c)
+        })
+        .filter(|e| {
+            cutoff.is_none_or(|cutoff| {
+                chrono::NaiveDateTime::parse_from_str(&e.timestamp, "%Y-%m-%d %H:%M:%S")
+                    .map(|t| t >= cutoff)
+                    .unwrap_or(true) // Include if we can't parse
+            })
+        })
+        .take(limit.unwrap_or(50))
+        .collect();
+
+    if unreadable > 0 {
+        eprintln!(
+            "Warning: Skipped {} unreadable audit log line(s)",
+            unreadable
+        );
+    }
+
+    if filtered.is_empty() {
+        println!("No matching audit log entries.");
+        return;
+    }
+
+    println!("\nAudit Log (newest first):");
+    println!("{}", "=".repeat(60));
+
+    for entry in filtered {
+        // ID 0 marks database-wide entries such as allergies
+        let subject = match entry.medication_id {
+            0 => entry.medication.clone(),
+            id => format!("{} (#{})", entry.medication, id),
+        };
+        println!("\n{}  {}  {}", entry.timestamp, entry.command, subject);
+        if !entry.arguments.is_empty() {
+            println!("  $ pharm {}", entry.arguments);
+        }
+        for change in &entry.changes {
+            println!(
+                "    {}: {} -> {}",
+                change.field,
+                change.old.as_deref().unwrap_or("(none)"),
+                change.new.as_deref().unwrap_or("(none)")
+            );
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DoseRecord;
+
+    #[test]
+    fn test_with_command_starts_an_operation() {
+        let first = with_command("take", current_operation);
+        let second = with_command("take", current_operation);
+        assert_ne!(first, 0);
+        assert_ne!(first, second);
+    }
+
+    fn med(name: &str, dose: &str) -> Medication {
+        Medication {
+            id: 1,
+            name: name.to_string(),
+            dose: dose.to_string(),
+            time_of_day: "8:00".to_string(),
+            medication_frequency: "daily".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn db(active: Vec<Medication>, archived: Vec<Medication>) -> MedicationDatabase {
+        MedicationDatabase {
+            medications: active,
+            archived_medications: archived,
+            ..Default::default()
+        }
+    }
+
+    fn change(field: &str, old: Option<&str>, new: Option<&str>) -> FieldChange {
+        FieldChange {
+            field: field.to_string(),
+            old: old.map(String::from),
+            new: new.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_no_changes() {
+        let a = db(vec![med("Aspirin", "500mg")], vec![]);
+        assert!(diff_databases(&a, &a.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_edit_field() {
+        let before = db(vec![med("Aspirin", "500mg")], vec![]);
+        let after = db(vec![med("Aspirin", "1000mg")], vec![]);
+        let diff = diff_databases(&before, &after);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].1, "Aspirin");
+        assert_eq!(
+            diff[0].2,
+            vec![change("dose", Some("500mg"), Some("1000mg"))]
+        );
+    }
+
+    #[test]
+    fn test_archive() {
+        let before = db(vec![med("Aspirin", "500mg")], vec![]);
+        let after = db(vec![], vec![med("Aspirin", "500mg")]);
+        let diff = diff_databases(&before, &after);
+        assert_eq!(
+            diff[0].2,
+            vec![change("status", Some("active"), Some("archived"))]
+        );
+    }
+
+    #[test]
+    fn test_history_pop_and_push() {
+        let record = DoseRecord {
+            timestamp: "08:30:15 - 2025/10/21".to_string(),
+            dose: "500mg".to_string(),
+            ..Default::default()
+        };
+        let before = db(vec![med("Aspirin", "500mg")], vec![]);
+        let mut taken = med("Aspirin", "500mg");
+        taken.history.push(record);
+        let after = db(vec![taken], vec![]);
+
+        let diff = diff_databases(&before, &after);
+        assert_eq!(
+            diff[0].2,
+            vec![change(
+                "history",
+                None,
+                Some("08:30:15 - 2025/10/21 (500mg)")
+            )]
+        );
+
+        // And the reverse (untake)
+        let diff = diff_databases(&after, &before);
+        assert_eq!(
+            diff[0].2,
+            vec![change(
+                "history",
+                Some("08:30:15 - 2025/10/21 (500mg)"),
+                None
+            )]
+        );
+    }
+
+    #[test]
+    fn test_rename_keeps_identity() {
+        let before = db(vec![med("Vitamin D", "1000IU")], vec![]);
+        let after = db(vec![med("Vitamin D3", "1000IU")], vec![]);
+        let diff = diff_databases(&before, &after);
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].0, 1);
+        assert_eq!(
+            diff[0].2,
+            vec![change("name", Some("Vitamin D"), Some("Vitamin D3"))]
+        );
+    }
+
+    #[test]
+    fn test_new_medication_lists_fields() {
+        let before = db(vec![], vec![]);
+        let after = db(vec![med("Aspirin", "500mg")], vec![]);
+        let diff = diff_databases(&before, &after);
+        let changes = &diff[0].2;
+        assert!(changes.contains(&change("status", None, Some("active"))));
+        assert!(changes.contains(&change("dose", None, Some("500mg"))));
+        // Unset values don't clutter the log
+        assert!(!changes.iter().any(|c| c.field == "notes"));
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use crate::config::load_config;
+use crate::database::{
+    get_data_file, load_database, read_database_file, save_database, MedicationDatabase,
+};
+
+/// Returns the directory holding rolling backups (`~/.pharm.backups/`).
+pub fn get_backup_dir() -> PathBuf {
+    get_data_file().with_extension("backups")
+}
+
+/// Copies the current database file into the backup directory.
+///
+/// Called by `save_database` right before the live file is replaced, so each
+/// backup holds the state *before* a change. Keeps at most `backup_count`
+/// backups (from the config), deleting the oldest ones first.
+pub fn create_backup(file_path: &Path) {
+    let keep = load_config().backup_count;
+    if keep == 0 || !file_path.exists() {
+        return;
+    }
+
+    let backup_dir = get_backup_dir();
+    if let Err(e) = fs::create_dir_all(&backup_dir) {
+        eprintln!("Warning: Failed to create backup directory: {}", e);
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        let _ = fs::set_permissions(&backup_dir, fs::Permissions::from_mode(0o700));
+    }
+
+    // Millisecond precision so that several saves in one command each keep
+    // their own backup
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    let backup_path = backup_dir.join(format!("pharm-{}.json", stamp));
+
+    if let Err(e) = fs::copy(file_path, &backup_path) {
+        eprintln!("Warning: Failed to create backup: {}", e);
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        let _ = fs::set_permissions(&backup_path, fs::Permissions::from_mode(0o600));
+    }
+
+    for old in backup_files().into_iter().skip(keep) {
+        if let Err(e) = fs::remove_file(&old) {
+            eprintln!("Warning: Failed to remove old backup: {}", e);
+        }
+    }
+}
+
+/// Returns all backup files, newest first.
+fn backup_files() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(get_backup_dir()) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| backup_stamp(p).is_some())
+        .collect();
+
+    // Timestamps are zero-padded, so name order is chronological order
+    files.sort();
+    files.reverse();
+    files
+}
+
+/// Extracts the timestamp part ("20251021-083015.250") from a backup file name.
+fn backup_stamp(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix("pharm-")?.strip_suffix(".json")
+}
+
+/// Formats a backup timestamp for display ("2025-10-21 08:30:15").
+fn display_stamp(stamp: &str) -> String {
+    chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S%.3f")
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| stamp.to_string())
+}
+
+/// Re-writes every backup in the database's current storage format.
+///
+/// Used by `pharm encrypt`/`pharm decrypt` so that old backups don't keep a
+/// plain-text copy of an encrypted database (or stay locked after decrypting).
+pub fn rewrite_backups() {
+    for path in backup_files() {
+        let Some(db) = read_database_file(&path) else {
+            eprintln!(
+                "Warning: Could not read backup {}, leaving it unchanged",
+                path.display()
+            );
+            continue;
+        };
+
+        let written = serde_json::to_string_pretty(&db)
+            .map_err(|e| e.to_string())
+            .and_then(crate::crypto::seal)
+            .and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string()));
+
+        if let Err(e) = written {
+            eprintln!(
+                "Warning: Failed to rewrite backup {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Lists available backups, newest first, with medication and dose counts.
+pub fn list_backups() {
+    let files = backup_files();
+    if files.is_empty() {
+        println!("No backups found in {}", get_backup_dir().display());
+        return;
+    }
+
+    println!("\nBackups (newest first):");
+    println!("{}", "=".repeat(60));
+
+    for (i, path) in files.iter().enumerate() {
+        let stamp = backup_stamp(path).unwrap_or_default();
+        match read_database_file(path) {
+            Some(db) => println!(
+                "  {:>3}  {}  {} active, {} archived, {} dose(s)",
+                i + 1,
+                display_stamp(stamp),
+                db.medications.len(),
+                db.archived_medications.len(),
+                total_doses(&db)
+            ),
+            None => println!("  {:>3}  {}  (unreadable)", i + 1, display_stamp(stamp)),
+        }
+    }
+
+    println!();
+    println!("Restore with: pharm backup restore <ID>");
+}
+
+/// Restores a backup over the live database after showing what would change.
+///
+/// `id` is either the number shown by `pharm backup list` (1 = newest) or the
+/// backup timestamp (e.g. "20251021-083015.250"). The current database is itself
+/// backed up by `save_database`, so a restore can be undone the same way.
+pub fn restore_backup(id: String, yes: bool) {
+    let files = backup_files();
+
+    let path = match id.trim().parse::<usize>() {
+        Ok(n) if n >= 1 && n <= files.len() => Some(files[n - 1].clone()),
+        _ => files
+            .iter()
+            .find(|p| backup_stamp(p) == Some(id.trim()))
+            .cloned(),
+    };
+
+    let Some(path) = path else {
+        eprintln!("Error: Backup '{}' not found!", id);
+        eprintln!("See available backups with: pharm backup list");
+        return;
+    };
+
+    let Some(backup) = read_database_file(&path) else {
+        eprintln!("Error: Backup {} cannot be parsed!", path.display());
+        return;
+    };
+
+    let current = load_database();
+    let diff = diff_databases(&current, &backup);
+
+    println!(
+        "Restoring backup from {}",
+        display_stamp(backup_stamp(&path).unwrap_or_default())
+    );
+    if diff.is_empty() {
+        println!(
+            "  Backup is identical to the current database (by medication, dose count and allergy)."
+        );
+    }
+    for name in &diff.added {
+        println!("  + {} (will be restored)", name);
+    }
+    for name in &diff.removed {
+        println!("  - {} (will be removed)", name);
+    }
+    for (name, now, then) in &diff.dose_changes {
+        println!("  ~ {}: {} dose(s) -> {} dose(s)", name, now, then);
+    }
+    for name in &diff.allergies_added {
+        println!("  + allergy: {} (will be restored)", name);
+    }
+    for name in &diff.allergies_removed {
+        println!("  - allergy: {} (will be removed)", name);
+    }
+
+    if !yes && !crate::prompt::confirm("Replace the current database with this backup?") {
+        println!("Restore cancelled.");
+        return;
+    }
+
+    save_database(&backup);
+    println!("Backup restored.");
+}
+
+fn total_doses(db: &MedicationDatabase) -> usize {
+    db.medications
+        .iter()
+        .chain(db.archived_medications.iter())
+        .map(|m| m.history.len())
+        .sum()
+}
+
+/// Summary of what restoring a backup would change.
+#[derive(Debug, Default, PartialEq)]
+pub struct BackupDiff {
+    /// Medications present in the backup but not in the current database
+    pub added: Vec<String>,
+    /// Medications present now that the backup doesn't have
+    pub removed: Vec<String>,
+    /// (name, current dose count, backup dose count) where the counts differ
+    pub dose_changes: Vec<(String, usize, usize)>,
+    /// Allergies recorded in the backup but not now
+    pub allergies_added: Vec<String>,
+    /// Allergies recorded now that the backup doesn't have
+    pub allergies_removed: Vec<String>,
+}
+
+impl BackupDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.dose_changes.is_empty()
+            && self.allergies_added.is_empty()
+            && self.allergies_removed.is_empty()
+    }
+}
+
+/// Compares two databases by medication name (case-insensitive), looking at
+/// active and archived medications together.
+pub fn diff_databases(current: &MedicationDatabase, backup: &MedicationDatabase) -> BackupDiff {
+    fn dose_counts(db: &MedicationDatabase) -> BTreeMap<String, (String, usize)> {
+        let mut counts = BTreeMap::new();
+        for med in db.medications.iter().chain(db.archived_medications.iter()) {
+            let entry = counts
+                .entry(med.name.to_lowercase())
+                .or_insert_with(|| (med.name.clone(), 0));
+            entry.1 += med.history.len();
+        }
+        counts
+    }
+
+    let now = dose_counts(current);
+    let then = dose_counts(backup);
+    let mut diff = BackupDiff::default();
+
+    for (key, (name, then_count)) in &then {
+        match now.get(key) {
+            None => diff.added.push(name.clone()),
+            Some((_, now_count)) if now_count != then_count => {
+                diff.dose_changes
+                    .push((name.clone(), *now_count, *then_count))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (key, (name, _)) in &now {
+        if !then.contains_key(key) {
+            diff.removed.push(name.clone());
+        }
+    }
+
+    let has_allergy =
+        |db: &MedicationDatabase, name: &str| db.allergies.iter().any(|a| a.name == name);
+    diff.allergies_added = backup
+        .allergies
+        .iter()
+        .filter(|allergy| !has_allergy(current, &allergy.name))
+        .map(|allergy| allergy.name.clone())
+        .collect();
+    diff.allergies_removed = current
+        .allergies
+        .iter()
+        .filter(|allergy| !has_allergy(backup, &allergy.name))
+        .map(|allergy| allergy.name.clone())
+        .collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allergies::Allergy;
+    use crate::database::{DoseRecord, Medication};
+
+    fn med(name: &str, doses: usize) -> Medication {
+        Medication {
+            name: name.to_string(),
+            dose: "10mg".to_string(),
+            history: (0..doses)
+                .map(|_| DoseRecord {
+                    timestamp: "08:00:00 - 2025/10/21".to_string(),
+                    dose: "10mg".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn db(active: Vec<Medication>, archived: Vec<Medication>) -> MedicationDatabase {
+        MedicationDatabase {
+            medications: active,
+            archived_medications: archived,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = db(vec![med("Aspirin", 3)], vec![]);
+        assert!(diff_databases(&a, &a.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_added_removed() {
+        let current = db(vec![med("Aspirin", 0), med("Ibuprofen", 0)], vec![]);
+        let backup = db(vec![med("Aspirin", 0)], vec![med("Metformin", 0)]);
+        let diff = diff_databases(&current, &backup);
+        assert_eq!(diff.added, vec!["Metformin".to_string()]);
+        assert_eq!(diff.removed, vec!["Ibuprofen".to_string()]);
+        assert!(diff.dose_changes.is_empty());
+    }
+
+    #[test]
+    fn test_diff_dose_counts() {
+        let current = db(vec![med("Aspirin", 5)], vec![]);
+        let backup = db(vec![med("aspirin", 3)], vec![]);
+        let diff = diff_databases(&current, &backup);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.dose_changes, vec![("aspirin".to_string(), 5, 3)]);
+    }
+
+    #[test]
+    fn test_diff_allergies() {
+        let allergy = |name: &str| Allergy {
+            name: name.to_string(),
+            reaction: None,
+        };
+        let current = MedicationDatabase {
+            allergies: vec![allergy("penicillin"), allergy("sulfa")],
+            ..db(vec![med("Aspirin", 0)], vec![])
+        };
+        let backup = MedicationDatabase {
+            allergies: vec![allergy("penicillin"), allergy("latex")],
+            ..db(vec![med("Aspirin", 0)], vec![])
+        };
+        let diff = diff_databases(&current, &backup);
+        assert_eq!(diff.allergies_added, vec!["latex".to_string()]);
+        assert_eq!(diff.allergies_removed, vec!["sulfa".to_string()]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_backup_stamp() {
+        assert_eq!(
+            backup_stamp(Path::new("/tmp/pharm-20251021-083015.250.json")),
+            Some("20251021-083015.250")
+        );
+        assert_eq!(backup_stamp(Path::new("/tmp/other.json")), None);
+        assert_eq!(display_stamp("20251021-083015.250"), "2025-10-21 08:30:15");
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::database::Medication;
+use crate::stock::format_units;
+use crate::time::parse_date;
+
+/// One bottle or lot of a medication with its own expiry date.
+///
+/// Batches describe part (or all) of a medication's `stock`. Units added
+/// without an expiry date are simply not covered by any batch.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    /// Lot number printed on the bottle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lot: Option<String>,
+    /// Units left in this batch
+    pub quantity: f64,
+    /// Expiry date (YYYY-MM-DD)
+    pub expires: String,
+}
+
+impl Batch {
+    pub fn expiry_date(&self) -> Option<NaiveDate> {
+        parse_date(&self.expires)
+    }
+
+    /// "30 exp 2026-01-31 (lot A123)"
+    pub fn describe(&self) -> String {
+        let mut text = format!("{} exp {}", format_units(self.quantity), self.expires);
+        if let Some(lot) = &self.lot {
+            text.push_str(&format!(" (lot {})", lot));
+        }
+        text
+    }
+}
+
+/// Orders batches oldest (earliest expiry) first
+pub fn sort_batches(batches: &mut [Batch]) {
+    batches.sort_by_key(|b| b.expiry_date().unwrap_or(NaiveDate::MAX));
+}
+
+/// Takes `units` from the batches, oldest first, dropping emptied batches.
+///
+/// Returns what was taken from each batch; anything beyond that came from
+/// supply without a batch.
+pub fn take_from_batches(batches: &mut Vec<Batch>, units: f64) -> Vec<Batch> {
+    sort_batches(batches);
+
+    let mut taken_from = Vec::new();
+    let mut remaining = units;
+    for batch in batches.iter_mut() {
+        if remaining <= 0.0 {
+            break;
+        }
+        let taken = batch.quantity.min(remaining);
+        batch.quantity -= taken;
+        remaining -= taken;
+        taken_from.push(Batch {
+            quantity: taken,
+            ..batch.clone()
+        });
+    }
+    batches.retain(|b| b.quantity > 0.0);
+
+    taken_from
+}
+
+/// Puts units taken by `take_from_batches` back into their batches,
+/// recreating batches that were used up
+pub fn return_to_batches(batches: &mut Vec<Batch>, taken_from: &[Batch]) {
+    for portion in taken_from {
+        match batches
+            .iter_mut()
+            .find(|batch| batch.expires == portion.expires && batch.lot == portion.lot)
+        {
+            Some(batch) => batch.quantity += portion.quantity,
+            None => batches.push(portion.clone()),
+        }
+    }
+    sort_batches(batches);
+}
+
+/// Shrinks the batches so they hold at most `total` units, assuming the
+/// oldest were used first. Used when the stock is re-counted.
+pub fn trim_batches(batches: &mut Vec<Batch>, total: f64) {
+    let held: f64 = batches.iter().map(|b| b.quantity).sum();
+    if held > total {
+        take_from_batches(batches, held - total);
+    }
+}
+
+/// Removes expired batches, returning them
+pub fn remove_expired(batches: &mut Vec<Batch>, today: NaiveDate) -> Vec<Batch> {
+    let (expired, kept) = batches
+        .drain(..)
+        .partition(|b| b.expiry_date().is_some_and(|date| date < today));
+    *batches = kept;
+    expired
+}
+
+/// "30 exp 2026-01-31 (lot A123), 60 exp 2026-06-30" for `list` and `stock`
+pub fn describe_batches(batches: &[Batch]) -> String {
+    let mut sorted = batches.to_vec();
+    sort_batches(&mut sorted);
+    sorted
+        .iter()
+        .map(Batch::describe)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Warnings about supply of `med` that has expired or expires within
+/// `warning_days`
+pub fn expiry_alerts(med: &Medication, today: NaiveDate, warning_days: u32) -> Vec<String> {
+    let mut batches = med.batches.clone();
+    sort_batches(&mut batches);
+
+    let mut alerts = Vec::new();
+    for batch in batches.iter().filter(|b| b.quantity > 0.0) {
+        let Some(expires) = batch.expiry_date() else {
+            continue;
+        };
+        let lot = match &batch.lot {
+            Some(lot) => format!(", lot {}", lot),
+            None => String::new(),
+        };
+
+        let days_left = (expires - today).num_days();
+        if days_left < 0 {
+            alerts.push(format!(
+                "{}: {} unit(s) expired on {}{}",
+                med.name,
+                format_units(batch.quantity),
+                batch.expires,
+                lot
+            ));
+        } else if days_left <= warning_days as i64 {
+            alerts.push(format!(
+                "{}: {} unit(s) expire in {} day(s) ({}{})",
+                med.name,
+                format_units(batch.quantity),
+                days_left,
+                batch.expires,
+                lot
+            ));
+        }
+    }
+
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(quantity: f64, expires: &str) -> Batch {
+        Batch {
+            lot: None,
+            quantity,
+            expires: expires.to_string(),
+        }
+    }
+
+    fn today() -> NaiveDate {
+        parse_date("2025-10-21").unwrap()
+    }
+
+    #[test]
+    fn test_take_oldest_first() {
+        let mut batches = vec![batch(30.0, "2026-06-30"), batch(2.0, "2026-01-31")];
+        let taken = take_from_batches(&mut batches, 3.0);
+        assert_eq!(
+            taken,
+            vec![batch(2.0, "2026-01-31"), batch(1.0, "2026-06-30")]
+        );
+        assert_eq!(batches, vec![batch(29.0, "2026-06-30")]);
+
+        // Back where they came from, including the used-up batch
+        return_to_batches(&mut batches, &taken);
+        assert_eq!(
+            batches,
+            vec![batch(2.0, "2026-01-31"), batch(30.0, "2026-06-30")]
+        );
+
+        // More than the batches hold
+        let taken = take_from_batches(&mut batches, 34.0);
+        assert_eq!(taken.iter().map(|b| b.quantity).sum::<f64>(), 32.0);
+        assert!(batches.is_empty());
+    }
+
+    #[test]
+    fn test_trim_batches() {
+        let mut batches = vec![batch(10.0, "2026-01-31"), batch(30.0, "2026-06-30")];
+        trim_batches(&mut batches, 35.0);
+        assert_eq!(
+            batches,
+            vec![batch(5.0, "2026-01-31"), batch(30.0, "2026-06-30")]
+        );
+
+        trim_batches(&mut batches, 50.0);
+        assert_eq!(batches.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut batches = vec![batch(10.0, "2025-10-01"), batch(30.0, "2026-06-30")];
+        let expired = remove_expired(&mut batches, today());
+        assert_eq!(expired, vec![batch(10.0, "2025-10-01")]);
+        assert_eq!(batches, vec![batch(30.0, "2026-06-30")]);
+    }
+
+    #[test]
+    fn test_expiry_alerts() {
+        let med = Medication {
+            name: "Amoxicillin".to_string(),
+            batches: vec![
+                batch(30.0, "2026-06-30"),
+                Batch {
+                    lot: Some("A123".to_string()),
+                    ..batch(10.0, "2025-10-31")
+                },
+                batch(5.0, "2025-10-01"),
+            ],
+            ..Default::default()
+        };
+
+        let alerts = expiry_alerts(&med, today(), 30);
+        assert_eq!(
+            alerts,
+            vec![
+                "Amoxicillin: 5 unit(s) expired on 2025-10-01",
+                "Amoxicillin: 10 unit(s) expire in 10 day(s) (2025-10-31, lot A123)",
+            ]
+        );
+        assert_eq!(expiry_alerts(&med, today(), 7).len(), 1);
+    }
+
+    #[test]
+    fn test_describe_batches() {
+        let batches = vec![
+            batch(60.0, "2026-06-30"),
+            Batch {
+                lot: Some("A123".to_string()),
+                ..batch(30.0, "2026-01-31")
+            },
+        ];
+        assert_eq!(
+            describe_batches(&batches),
+            "30 exp 2026-01-31 (lot A123), 60 exp 2026-06-30"
+        );
+    }
+}
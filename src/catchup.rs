@@ -0,0 +1,310 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::database::{get_data_file, Medication};
+use crate::interval::parse_interval_to_days;
+use crate::quiet::{Delivery, QuietHours};
+use crate::scheduler::describe_wait;
+use crate::time::{parse_dose_timestamp, parse_time};
+
+/// A gap this long between two passes of the daemon loop means it was not
+/// running (stopped, suspended, or the clock jumped forward)
+pub const CATCH_UP_GAP: Duration = Duration::minutes(5);
+
+/// How far back the daemon looks for doses missed while it was away
+pub const MAX_CATCH_UP_DAYS: i64 = 7;
+
+const HEARTBEAT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A scheduled dose that came due while the daemon was away and hasn't been
+/// taken
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissedDose {
+    pub med_id: u32,
+    pub name: String,
+    pub dose: String,
+    pub at: NaiveDateTime,
+}
+
+impl MissedDose {
+    /// A dose from an earlier day can no longer be taken; today's is only
+    /// overdue
+    pub fn is_past(&self, now: NaiveDateTime) -> bool {
+        self.at.date() < now.date()
+    }
+
+    /// "Metformin (500mg) due 08:00 Mon 20 Oct - missed"
+    pub fn describe(&self, now: NaiveDateTime) -> String {
+        if self.is_past(now) {
+            format!(
+                "{} ({}) due {} - missed",
+                self.name,
+                self.dose,
+                self.at.format("%H:%M %a %d %b")
+            )
+        } else {
+            format!(
+                "{} ({}) due {} - {}",
+                self.name,
+                self.dose,
+                self.at.format("%H:%M"),
+                describe_wait(self.at, now)
+            )
+        }
+    }
+}
+
+/// File the daemon writes the time of each pass to (`~/.pharm.heartbeat`), so
+/// the next start knows how long it was away
+pub fn get_heartbeat_file() -> PathBuf {
+    get_data_file().with_extension("heartbeat")
+}
+
+/// When the daemon last checked for reminders, if it has ever run
+pub fn last_heartbeat() -> Option<NaiveDateTime> {
+    let contents = fs::read_to_string(get_heartbeat_file()).ok()?;
+    NaiveDateTime::parse_from_str(contents.trim(), HEARTBEAT_FORMAT).ok()
+}
+
+pub fn save_heartbeat(now: NaiveDateTime) -> Result<(), String> {
+    let file_path = get_heartbeat_file();
+    fs::write(&file_path, now.format(HEARTBEAT_FORMAT).to_string())
+        .map_err(|e| format!("{}: {}", file_path.display(), e))
+}
+
+/// True if a dose was recorded at or after `from`
+pub fn taken_since(med: &Medication, from: NaiveDateTime) -> bool {
+    med.history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .any(|taken| from <= taken)
+}
+
+/// True if a dose was recorded at or after `from` and before `until`
+fn taken_between(med: &Medication, from: NaiveDateTime, until: NaiveDateTime) -> bool {
+    med.history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .any(|taken| from <= taken && taken < until)
+}
+
+/// The doses of `med` scheduled after `from` and up to `to` that were never
+/// taken.
+///
+/// A daily medication is due every day, and each day without a dose counts.
+/// A medication taken every few days stays due from its due date until it is
+/// taken, so it counts once. Doses taken early on the day they were due (or
+/// late, before the next one) count as taken.
+pub fn missed_doses(med: &Medication, from: NaiveDateTime, to: NaiveDateTime) -> Vec<MissedDose> {
+    let Some(interval_days) = parse_interval_to_days(&med.medication_frequency) else {
+        return Vec::new();
+    };
+    let Some(time) = parse_time(&med.time_of_day)
+        .and_then(|(hour, minute)| NaiveTime::from_hms_opt(hour, minute, 0))
+    else {
+        return Vec::new();
+    };
+    let from = from.max(to - Duration::days(MAX_CATCH_UP_DAYS));
+    let missed = |at: NaiveDateTime| MissedDose {
+        med_id: med.id,
+        name: med.name.clone(),
+        dose: med.dose.clone(),
+        at,
+    };
+
+    if interval_days == 1 {
+        return from
+            .date()
+            .iter_days()
+            .take_while(|day| *day <= to.date())
+            .map(|day| day.and_time(time))
+            .filter(|at| from < *at && *at <= to)
+            .filter(|at| {
+                let day = at.date().and_time(NaiveTime::MIN);
+                !taken_between(med, day, day + Duration::days(1))
+            })
+            .map(missed)
+            .collect();
+    }
+
+    // Due `interval_days` after the last dose taken before we went away
+    let last_taken = med
+        .history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .filter(|taken| *taken <= from)
+        .max()
+        .map(|taken| taken.date())
+        .or_else(|| NaiveDate::parse_from_str(&med.last_dose_date, "%Y-%m-%d").ok())
+        .filter(|last| *last <= from.date());
+    let due_day = last_taken.map_or(from.date(), |last| {
+        last + Duration::days(interval_days as i64)
+    });
+    let at = due_day.and_time(time);
+    if from < at && at <= to && !taken_between(med, due_day.and_time(NaiveTime::MIN), to) {
+        vec![missed(at)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Every dose missed between `from` and `to`, earliest first
+pub fn missed_while_away(
+    meds: &[Medication],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<MissedDose> {
+    let mut missed: Vec<MissedDose> = meds
+        .iter()
+        .flat_map(|med| missed_doses(med, from, to))
+        .collect();
+    missed.sort_by(|a, b| a.at.cmp(&b.at).then(a.name.cmp(&b.name)));
+    missed
+}
+
+/// How quiet hours let the "while you were away" notification through at
+/// `now`: like any alert, breaking through if one of the medications is
+/// critical
+pub fn catch_up_delivery(
+    missed: &[MissedDose],
+    meds: &[Medication],
+    quiet: Option<&QuietHours>,
+    now: NaiveDateTime,
+) -> Delivery {
+    let critical = missed
+        .iter()
+        .any(|dose| meds.iter().any(|med| med.id == dose.med_id && med.critical));
+    quiet.map_or(Delivery::Send, |quiet| quiet.delivery(critical, now))
+}
+
+/// The body of the "while you were away" notification
+pub fn summarize(missed: &[MissedDose], from: NaiveDateTime, now: NaiveDateTime) -> String {
+    let mut lines = vec![format!("Away since {}:", from.format("%H:%M %a %d %b"))];
+    lines.extend(missed.iter().map(|dose| dose.describe(now)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DoseRecord;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn med(freq: &str, taken: &[&str]) -> Medication {
+        Medication {
+            id: 1,
+            name: "Metformin".to_string(),
+            dose: "500mg".to_string(),
+            time_of_day: "08:00".to_string(),
+            medication_frequency: freq.to_string(),
+            history: taken
+                .iter()
+                .map(|timestamp| DoseRecord {
+                    timestamp: at(timestamp).format("%H:%M:%S - %Y/%m/%d").to_string(),
+                    dose: "500mg".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_daily_missed_doses() {
+        // Asleep from Sunday night to Tuesday noon; Monday's dose taken on a phone
+        let daily = med("daily", &["2025-10-19 08:05", "2025-10-20 09:30"]);
+        let missed = missed_doses(&daily, at("2025-10-19 22:00"), at("2025-10-21 12:00"));
+        assert_eq!(
+            missed.iter().map(|dose| dose.at).collect::<Vec<_>>(),
+            vec![at("2025-10-21 08:00")]
+        );
+
+        let missed = missed_doses(
+            &med("daily", &[]),
+            at("2025-10-19 22:00"),
+            at("2025-10-21 12:00"),
+        );
+        assert_eq!(missed.len(), 2);
+        assert!(missed[0].is_past(at("2025-10-21 12:00")));
+        assert!(!missed[1].is_past(at("2025-10-21 12:00")));
+
+        // Already due before we went away: the daemon reminded about it then
+        let missed = missed_doses(
+            &med("daily", &[]),
+            at("2025-10-21 09:00"),
+            at("2025-10-21 12:00"),
+        );
+        assert!(missed.is_empty());
+
+        // PRN medications are never missed
+        assert!(missed_doses(
+            &med("as needed", &[]),
+            at("2025-10-19 22:00"),
+            at("2025-10-21 12:00")
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_interval_missed_doses() {
+        // Every 3 days, last taken on the 17th: due on the 20th, counted once
+        let every_3 = med("every 3 days", &["2025-10-17 08:00"]);
+        let missed = missed_doses(&every_3, at("2025-10-18 22:00"), at("2025-10-22 12:00"));
+        assert_eq!(
+            missed.iter().map(|dose| dose.at).collect::<Vec<_>>(),
+            vec![at("2025-10-20 08:00")]
+        );
+
+        let taken_late = med("every 3 days", &["2025-10-17 08:00", "2025-10-21 10:00"]);
+        assert!(
+            missed_doses(&taken_late, at("2025-10-18 22:00"), at("2025-10-22 12:00")).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_catch_up_delivery() {
+        use crate::quiet::QuietMode;
+
+        // Back from suspend at 02:00, during quiet hours
+        let quiet = QuietHours {
+            start: "23:00".to_string(),
+            end: "06:30".to_string(),
+            mode: QuietMode::Defer,
+        };
+        let now = at("2025-10-21 02:00");
+        let mut meds = vec![med("daily", &[])];
+        let missed = missed_while_away(&meds, at("2025-10-19 22:00"), now);
+        assert_eq!(
+            catch_up_delivery(&missed, &meds, Some(&quiet), now),
+            Delivery::Defer
+        );
+        assert_eq!(
+            catch_up_delivery(&missed, &meds, Some(&quiet), at("2025-10-21 06:30")),
+            Delivery::Send
+        );
+        assert_eq!(catch_up_delivery(&missed, &meds, None, now), Delivery::Send);
+
+        meds[0].critical = true;
+        assert_eq!(
+            catch_up_delivery(&missed, &meds, Some(&quiet), now),
+            Delivery::Send
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let now = at("2025-10-21 12:00");
+        let missed = missed_while_away(&[med("daily", &[])], at("2025-10-19 22:00"), now);
+        assert_eq!(
+            summarize(&missed, at("2025-10-19 22:00"), now),
+            "Away since 22:00 Sun 19 Oct:\n\
+             Metformin (500mg) due 08:00 Mon 20 Oct - missed\n\
+             Metformin (500mg) due 08:00 - overdue by 4h"
+        );
+    }
+}
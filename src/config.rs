@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::endpoint::TakeEndpoint;
+use crate::notifier::NotifierConfig;
+use crate::quiet::QuietHours;
+
+/// User configuration for pharm.
+///
+/// Every field has a default, so the config file only needs to contain the
+/// settings the user wants to change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PharmConfig {
+    /// Number of rolling backups kept by `save_database` (0 disables backups)
+    pub backup_count: usize,
+    /// Number of operations `pharm undo` can reverse
+    pub undo_levels: usize,
+    /// File containing the passphrase for an encrypted database
+    pub key_file: Option<PathBuf>,
+    /// Maximum daily amount (mg) per active ingredient, summed across all
+    /// medications, e.g. `{"acetaminophen": 4000}`
+    pub ingredient_limits_mg: BTreeMap<String, f64>,
+    /// Extra drug interaction table (CSV or JSON) checked alongside the
+    /// bundled one
+    pub interactions_file: Option<PathBuf>,
+    /// Extra drug class mapping (CSV or JSON) used for allergy warnings
+    pub drug_classes_file: Option<PathBuf>,
+    /// The daemon sends a refill reminder once a day while a medication's
+    /// stock lasts fewer than this many days (0 disables refill reminders)
+    pub refill_threshold_days: u32,
+    /// Warn this many days before a prescription expires
+    pub prescription_warning_days: u32,
+    /// Warn this many days before a batch of supply expires
+    pub expiry_warning_days: u32,
+    /// Named notification backends, e.g.
+    /// `{"phone": {"type": "webhook", "url": "http://..."}}`
+    pub notifiers: BTreeMap<String, NotifierConfig>,
+    /// Notifiers the daemon uses for medications without their own list
+    /// ("desktop" and "terminal" are built in)
+    pub notify_via: Vec<String>,
+    /// The daemon sends a "missed dose" alert when a reminder has gone
+    /// unanswered this long (0 disables missed-dose alerts)
+    pub missed_dose_minutes: u32,
+    /// HTTP endpoint the daemon serves so push notifications can mark doses
+    /// as taken (off unless set)
+    pub take_endpoint: Option<TakeEndpoint>,
+    /// Minutes an unanswered reminder waits before a medication's caregivers
+    /// are alerted, unless the medication sets its own
+    pub escalation_minutes: u32,
+    /// Nightly window in which the daemon holds back (or quietens) reminders
+    /// for medications not flagged critical (off unless set)
+    pub quiet_hours: Option<QuietHours>,
+    /// Keep a record of the doses the daemon finds were missed while it was
+    /// away (shown by `pharm history`)
+    pub record_missed_doses: bool,
+}
+
+impl Default for PharmConfig {
+    fn default() -> Self {
+        PharmConfig {
+            backup_count: 10,
+            undo_levels: 50,
+            key_file: None,
+            ingredient_limits_mg: BTreeMap::new(),
+            interactions_file: None,
+            drug_classes_file: None,
+            refill_threshold_days: 7,
+            prescription_warning_days: 14,
+            expiry_warning_days: 30,
+            notifiers: BTreeMap::new(),
+            notify_via: vec!["desktop".to_string()],
+            missed_dose_minutes: 60,
+            take_endpoint: None,
+            escalation_minutes: 30,
+            quiet_hours: None,
+            record_missed_doses: false,
+        }
+    }
+}
+
+/// Returns the path to the configuration file (`~/.pharm.config.json`).
+pub fn get_config_file() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".pharm.config.json")
+}
+
+/// Loads the configuration from disk.
+///
+/// A missing file yields the defaults. An unreadable or invalid file is
+/// reported and the defaults are used instead, so a typo in the config never
+/// stops reminders from working.
+pub fn load_config() -> PharmConfig {
+    let file_path = get_config_file();
+    if !file_path.exists() {
+        return PharmConfig::default();
+    }
+
+    let contents = match fs::read_to_string(&file_path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Warning: Failed to read config file: {}", e);
+            return PharmConfig::default();
+        }
+    };
+
+    match serde_json::from_str::<PharmConfig>(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!(
+                "Warning: Invalid config file {}: {}",
+                file_path.display(),
+                e
+            );
+            eprintln!("Using default settings.");
+            PharmConfig::default()
+        }
+    }
+}
+
+/// Fields holding passwords and access tokens, hidden by `show_config`
+const SECRET_FIELDS: &[&str] = &["password", "token"];
+
+/// Replaces secrets anywhere in a serialized config with asterisks: the
+/// `SECRET_FIELDS`, and `Authorization` headers of webhook notifiers.
+fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let secret = SECRET_FIELDS.contains(&key.as_str())
+                    || key.eq_ignore_ascii_case("authorization");
+                if secret && field.is_string() {
+                    *field = serde_json::Value::String("********".to_string());
+                } else {
+                    redact_secrets(field);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Prints the config file location and the effective settings, with
+/// passwords and tokens hidden.
+pub fn show_config() {
+    let file_path = get_config_file();
+    let config = load_config();
+
+    println!("Config file: {}", file_path.display());
+    if !file_path.exists() {
+        println!("  (not found - using defaults)");
+    }
+
+    let shown = serde_json::to_value(&config).map(|mut value| {
+        redact_secrets(&mut value);
+        value
+    });
+    match shown.and_then(|value| serde_json::to_string_pretty(&value)) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Error: Failed to serialize config: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut config = serde_json::json!({
+            "notifiers": {
+                "mail": { "type": "email", "username": "me", "password": "hunter2" },
+                "phone": { "type": "ntfy", "topic": "pills", "token": "tk_abc" },
+                "hook": { "type": "webhook", "headers": { "Authorization": "Bearer xyz" } }
+            },
+            "take_endpoint": { "listen": "0.0.0.0:8765", "token": "s3cret" },
+            "undo_levels": 50
+        });
+        redact_secrets(&mut config);
+
+        let shown = config.to_string();
+        for secret in ["hunter2", "tk_abc", "xyz", "s3cret"] {
+            assert!(!shown.contains(secret), "{} shown", secret);
+        }
+        assert_eq!(config["notifiers"]["mail"]["password"], "********");
+        assert_eq!(config["notifiers"]["mail"]["username"], "me");
+        assert_eq!(config["take_endpoint"]["listen"], "0.0.0.0:8765");
+        assert_eq!(config["undo_levels"], 50);
+    }
+}
@@ -0,0 +1,384 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::get_data_file;
+
+/// How long either side waits for the other before giving up
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A request sent to the running daemon, one JSON object per line:
+/// `{"command": "status"}`, `{"command": "reload"}`, `{"command": "stop"}` or
+/// `{"command": "take", "id": 3}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum ControlRequest {
+    Status,
+    Reload,
+    Stop,
+    /// Mark a dose as taken, with the same checks as `pharm take`
+    Take {
+        id: u32,
+    },
+}
+
+/// The daemon's answer, also one JSON object per line
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "result", rename_all = "lowercase")]
+pub enum ControlResponse {
+    Status(DaemonStatus),
+    Ok { message: String },
+    Error { message: String },
+}
+
+/// What the daemon is doing, for `pharm daemon status`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    /// Local time the daemon started ("%Y-%m-%d %H:%M:%S")
+    pub started_at: String,
+    pub uptime_secs: u64,
+    /// False if the daemon fell back to re-reading the database every minute
+    pub watching_files: bool,
+    pub next_reminders: Vec<UpcomingReminder>,
+    /// Medications already reminded about today
+    pub notified_today: Vec<MedicationRef>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpcomingReminder {
+    /// Local time the dose is due ("%Y-%m-%d %H:%M:%S")
+    pub at: String,
+    pub med_id: u32,
+    pub name: String,
+    pub dose: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MedicationRef {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Returns the path to the daemon's control socket (`~/.pharm.sock`).
+pub fn get_socket_file() -> PathBuf {
+    get_data_file().with_extension("sock")
+}
+
+/// Returns the path to the daemon's PID/lock file (`~/.pharm.pid`).
+pub fn get_pid_file() -> PathBuf {
+    get_data_file().with_extension("pid")
+}
+
+/// Held by the running daemon; removes the PID file when dropped
+pub struct DaemonLock {
+    path: PathBuf,
+}
+
+impl Drop for DaemonLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the daemon lock by creating the PID file, so only one daemon runs
+/// at a time.
+///
+/// A PID file left behind by a daemon that was killed is replaced, as long as
+/// no daemon answers on the control socket.
+pub fn acquire_lock() -> Result<DaemonLock, String> {
+    let path = get_pid_file();
+
+    for _ in 0..2 {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut file) => {
+                writeln!(file, "{}", std::process::id()).map_err(|e| e.to_string())?;
+                return Ok(DaemonLock { path });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let pid = fs::read_to_string(&path).unwrap_or_default();
+                if is_daemon_running() {
+                    return Err(format!(
+                        "Another pharm daemon is already running (PID {})",
+                        pid.trim()
+                    ));
+                }
+                // Stale lock from a daemon that didn't shut down cleanly
+                fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    Err(format!("Could not create {}", path.display()))
+}
+
+/// Handles one connection: reads a request, forwards it to the daemon loop
+/// and writes back its reply
+#[cfg(unix)]
+fn handle_connection<T>(
+    stream: std::os::unix::net::UnixStream,
+    sender: &Sender<T>,
+    wrap: fn(ControlRequest, Sender<ControlResponse>) -> T,
+) {
+    let _ = stream.set_read_timeout(Some(CONTROL_TIMEOUT));
+    let mut line = String::new();
+    let mut reader = BufReader::new(&stream);
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+
+    let response = match serde_json::from_str::<ControlRequest>(&line) {
+        Ok(ControlRequest::Stop) => {
+            // Answer first: the daemon exits as soon as it sees the request
+            let stopped = ControlResponse::Ok {
+                message: format!("Daemon (PID {}) stopped", std::process::id()),
+            };
+            write_response(&stream, &stopped);
+            let (reply, _) = std::sync::mpsc::channel();
+            let _ = sender.send(wrap(ControlRequest::Stop, reply));
+            return;
+        }
+        Ok(request) => {
+            let (reply, answer) = std::sync::mpsc::channel();
+            if sender.send(wrap(request, reply)).is_err() {
+                ControlResponse::Error {
+                    message: "daemon is shutting down".to_string(),
+                }
+            } else {
+                answer
+                    .recv_timeout(CONTROL_TIMEOUT)
+                    .unwrap_or(ControlResponse::Error {
+                        message: "daemon did not answer".to_string(),
+                    })
+            }
+        }
+        Err(e) => ControlResponse::Error {
+            message: format!("invalid request: {}", e),
+        },
+    };
+
+    write_response(&stream, &response);
+}
+
+#[cfg(unix)]
+fn write_response(stream: &std::os::unix::net::UnixStream, response: &ControlResponse) {
+    if let Ok(json) = serde_json::to_string(response) {
+        let mut stream = stream;
+        let _ = writeln!(stream, "{}", json);
+    }
+}
+
+/// Listens on the control socket; removes the socket file when dropped
+pub struct ControlServer {
+    path: PathBuf,
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl ControlServer {
+    /// Starts answering requests on the control socket in a background
+    /// thread. Each request is passed to the daemon loop through `sender`
+    /// (wrapped by `wrap`) together with a channel for the reply.
+    ///
+    /// Call only while holding the `DaemonLock`, since a leftover socket file
+    /// is removed.
+    #[cfg(unix)]
+    pub fn start<T: Send + 'static>(
+        sender: Sender<T>,
+        wrap: fn(ControlRequest, Sender<ControlResponse>) -> T,
+    ) -> Result<ControlServer, String> {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixListener;
+
+        let path = get_socket_file();
+        let _ = fs::remove_file(&path);
+        let listener =
+            UnixListener::bind(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                handle_connection(stream, &sender, wrap);
+            }
+        });
+
+        Ok(ControlServer { path })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start<T: Send + 'static>(
+        _sender: Sender<T>,
+        _wrap: fn(ControlRequest, Sender<ControlResponse>) -> T,
+    ) -> Result<ControlServer, String> {
+        Err("control sockets are only supported on Unix".to_string())
+    }
+}
+
+/// Sends one request to the running daemon and waits for the answer
+#[cfg(unix)]
+pub fn send_request(request: &ControlRequest) -> Result<ControlResponse, String> {
+    use std::os::unix::net::UnixStream;
+
+    let stream = UnixStream::connect(get_socket_file()).map_err(|e| e.to_string())?;
+    let _ = stream.set_read_timeout(Some(CONTROL_TIMEOUT));
+
+    let json = serde_json::to_string(request).map_err(|e| e.to_string())?;
+    let mut writer = &stream;
+    writeln!(writer, "{}", json).map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&line).map_err(|e| format!("invalid response: {}", e))
+}
+
+#[cfg(not(unix))]
+pub fn send_request(_request: &ControlRequest) -> Result<ControlResponse, String> {
+    Err("control sockets are only supported on Unix".to_string())
+}
+
+/// True if a daemon answers on the control socket
+pub fn is_daemon_running() -> bool {
+    get_socket_file().exists() && send_request(&ControlRequest::Status).is_ok()
+}
+
+/// Sends `request`, printing a friendly message if no daemon is running
+fn request_or_report(request: ControlRequest) -> Option<ControlResponse> {
+    if !get_socket_file().exists() {
+        println!("The pharm daemon is not running.");
+        return None;
+    }
+    match send_request(&request) {
+        Ok(ControlResponse::Error { message }) => {
+            eprintln!("Error: {}", message);
+            None
+        }
+        Ok(response) => Some(response),
+        Err(e) => {
+            println!("The pharm daemon is not running ({}).", e);
+            None
+        }
+    }
+}
+
+/// `pharm daemon status`
+pub fn show_daemon_status() {
+    let Some(ControlResponse::Status(status)) = request_or_report(ControlRequest::Status) else {
+        return;
+    };
+
+    let now = chrono::Local::now().naive_local();
+    println!("Daemon running (PID {})", status.pid);
+    println!(
+        "  Started:  {} (up {})",
+        status.started_at,
+        crate::interval::format_minutes((status.uptime_secs / 60) as u32)
+    );
+    println!(
+        "  Watching: {}",
+        if status.watching_files {
+            "database and config files"
+        } else {
+            "no - re-reading the database every minute"
+        }
+    );
+
+    if status.notified_today.is_empty() {
+        println!("  Reminded today: none");
+    } else {
+        let names: Vec<String> = status
+            .notified_today
+            .iter()
+            .map(|med| format!("{} (#{})", med.name, med.id))
+            .collect();
+        println!("  Reminded today: {}", names.join(", "));
+    }
+
+    if status.next_reminders.is_empty() {
+        println!("  No upcoming reminders.");
+        return;
+    }
+    println!("  Next reminders:");
+    for reminder in &status.next_reminders {
+        let wait = chrono::NaiveDateTime::parse_from_str(&reminder.at, "%Y-%m-%d %H:%M:%S")
+            .map(|at| crate::scheduler::describe_wait(at, now))
+            .unwrap_or_default();
+        println!(
+            "    {}  {} (#{}) - {}  ({})",
+            reminder.at, reminder.name, reminder.med_id, reminder.dose, wait
+        );
+    }
+}
+
+/// `pharm daemon stop` / `pharm daemon reload`
+pub fn control_daemon(request: ControlRequest) {
+    if let Some(ControlResponse::Ok { message }) = request_or_report(request) {
+        println!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_format() {
+        assert_eq!(
+            serde_json::to_string(&ControlRequest::Reload).unwrap(),
+            r#"{"command":"reload"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "stop"}"#).unwrap(),
+            ControlRequest::Stop
+        );
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "explode"}"#).is_err());
+
+        let ok = ControlResponse::Ok {
+            message: "Stopping".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"result":"ok","message":"Stopping"}"#
+        );
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        let status = ControlResponse::Status(DaemonStatus {
+            pid: 42,
+            started_at: "2025-10-21 08:00:00".to_string(),
+            uptime_secs: 3600,
+            watching_files: true,
+            next_reminders: vec![UpcomingReminder {
+                at: "2025-10-21 21:00:00".to_string(),
+                med_id: 1,
+                name: "Aspirin".to_string(),
+                dose: "500mg".to_string(),
+            }],
+            notified_today: vec![MedicationRef {
+                id: 2,
+                name: "Vitamin D".to_string(),
+            }],
+        });
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.starts_with(r#"{"result":"status","pid":42"#));
+        assert_eq!(
+            serde_json::from_str::<ControlResponse>(&json).unwrap(),
+            status
+        );
+    }
+}
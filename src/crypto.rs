@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{get_data_file, load_database, save_database};
+
+/// On-disk format of an encrypted database (or backup).
+///
+/// The envelope is still JSON so the file can be recognised and inspected,
+/// but the medication data itself is only stored as ciphertext.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EncryptedFile {
+    /// Format version, also used to recognise encrypted files
+    pharm_encrypted: u32,
+    /// Key derivation function (always "argon2id")
+    kdf: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    /// AEAD cipher (always "xchacha20poly1305")
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// Passphrase and derived keys for the lifetime of the process.
+///
+/// The daemon unlocks once at startup and reuses this for every reload.
+struct Session {
+    passphrase: String,
+    /// Salt used when writing the database; `None` writes plain JSON
+    write_salt: Option<Vec<u8>>,
+    /// Derived keys by salt, since Argon2 is deliberately slow
+    keys: HashMap<Vec<u8>, [u8; 32]>,
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Set by `pharm decrypt`: the next saves are meant to replace the encrypted
+/// file with plain JSON.
+static WRITE_PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Key file given on the command line (e.g. `pharm daemon --key-file`).
+static KEY_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Uses the given key file instead of prompting for the passphrase.
+pub fn set_key_file(path: PathBuf) {
+    *KEY_FILE.lock().unwrap() = Some(path);
+}
+
+/// Returns true if the file contents are an encrypted pharm database.
+pub fn is_encrypted(contents: &str) -> bool {
+    parse_envelope(contents).is_some()
+}
+
+fn parse_envelope(contents: &str) -> Option<EncryptedFile> {
+    if !contents.contains("pharm_encrypted") {
+        return None;
+    }
+    serde_json::from_str::<EncryptedFile>(contents).ok()
+}
+
+/// Reads the passphrase from (in order) `PHARM_PASSPHRASE`, a key file
+/// (`--key-file`, `key_file` in the config, or `PHARM_KEY_FILE`), or an
+/// interactive prompt.
+///
+/// Returns the passphrase and whether it came from the prompt (so a typo can
+/// be retried).
+fn read_passphrase(prompt: &str) -> Result<(String, bool), String> {
+    if let Ok(pass) = std::env::var("PHARM_PASSPHRASE") {
+        return Ok((pass, false));
+    }
+
+    let key_file = KEY_FILE
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| crate::config::load_config().key_file)
+        .or_else(|| std::env::var_os("PHARM_KEY_FILE").map(PathBuf::from));
+
+    if let Some(path) = key_file {
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read key file {}: {}", path.display(), e))?;
+        let pass = contents.trim_end_matches(['\r', '\n']).to_string();
+        if pass.is_empty() {
+            return Err(format!("Key file {} is empty", path.display()));
+        }
+        return Ok((pass, false));
+    }
+
+    rpassword::prompt_password(prompt)
+        .map(|pass| (pass, true))
+        .map_err(|e| format!("Failed to read passphrase: {}", e))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], env: &EncryptedFile) -> Result<[u8; 32], String> {
+    let params = Params::new(env.m_cost, env.t_cost, env.p_cost, Some(32))
+        .map_err(|e| format!("Invalid key derivation parameters: {}", e))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = [0u8; 32];
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase` and `salt`.
+fn seal_with(passphrase: &str, salt: &[u8], plaintext: &str) -> Result<EncryptedFile, String> {
+    let defaults = Params::default();
+    let mut env = EncryptedFile {
+        pharm_encrypted: FORMAT_VERSION,
+        kdf: "argon2id".to_string(),
+        m_cost: defaults.m_cost(),
+        t_cost: defaults.t_cost(),
+        p_cost: defaults.p_cost(),
+        salt: to_hex(salt),
+        cipher: "xchacha20poly1305".to_string(),
+        nonce: String::new(),
+        ciphertext: String::new(),
+    };
+
+    let key = cached_key(passphrase, salt, &env)?;
+    seal_envelope(&key, &mut env, plaintext)?;
+    Ok(env)
+}
+
+fn seal_envelope(key: &[u8; 32], env: &mut EncryptedFile, plaintext: &str) -> Result<(), String> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    env.nonce = to_hex(&nonce);
+    env.ciphertext = to_hex(&ciphertext);
+    Ok(())
+}
+
+fn open_with(key: &[u8; 32], env: &EncryptedFile) -> Result<String, String> {
+    if env.kdf != "argon2id" || env.cipher != "xchacha20poly1305" {
+        return Err(format!(
+            "Unsupported encryption format ({} / {})",
+            env.kdf, env.cipher
+        ));
+    }
+
+    let nonce = from_hex(&env.nonce).ok_or("Malformed nonce")?;
+    if nonce.len() != 24 {
+        return Err("Malformed nonce".to_string());
+    }
+    let ciphertext = from_hex(&env.ciphertext).ok_or("Malformed ciphertext")?;
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Wrong passphrase or damaged file".to_string())?;
+
+    String::from_utf8(plaintext).map_err(|_| "Decrypted data is not valid UTF-8".to_string())
+}
+
+/// Returns the key for `salt`, deriving it once per process.
+fn cached_key(passphrase: &str, salt: &[u8], env: &EncryptedFile) -> Result<[u8; 32], String> {
+    let mut session = SESSION.lock().unwrap();
+    if let Some(s) = session.as_ref() {
+        if s.passphrase == passphrase {
+            if let Some(key) = s.keys.get(salt) {
+                return Ok(*key);
+            }
+        }
+    }
+
+    let key = derive_key(passphrase, salt, env)?;
+    if let Some(s) = session.as_mut() {
+        if s.passphrase == passphrase {
+            s.keys.insert(salt.to_vec(), key);
+        }
+    }
+    Ok(key)
+}
+
+/// Decrypts encrypted file contents, asking for the passphrase if this
+/// process doesn't know it yet. Interactive prompts get three attempts.
+pub fn decrypt_contents(contents: &str) -> Result<String, String> {
+    let env = parse_envelope(contents).ok_or("Not an encrypted pharm file")?;
+    let salt = from_hex(&env.salt).ok_or("Malformed salt")?;
+
+    let known = SESSION
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|s| s.passphrase.clone());
+    if let Some(passphrase) = known {
+        let key = cached_key(&passphrase, &salt, &env)?;
+        return open_with(&key, &env);
+    }
+
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let (passphrase, interactive) =
+            read_passphrase(&format!("Passphrase for {}: ", get_data_file().display()))?;
+        let key = derive_key(&passphrase, &salt, &env)?;
+
+        match open_with(&key, &env) {
+            Ok(plaintext) => {
+                let mut keys = HashMap::new();
+                keys.insert(salt, key);
+                *SESSION.lock().unwrap() = Some(Session {
+                    passphrase,
+                    write_salt: None,
+                    keys,
+                });
+                return Ok(plaintext);
+            }
+            Err(e) if interactive && attempts < 3 => eprintln!("{}. Try again.", e),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Decrypts the live database file and remembers to encrypt it again on save.
+pub fn unlock_database(contents: &str) -> Result<String, String> {
+    let plaintext = decrypt_contents(contents)?;
+    let salt = parse_envelope(contents).and_then(|env| from_hex(&env.salt));
+    if let Some(s) = SESSION.lock().unwrap().as_mut() {
+        s.write_salt = salt;
+    }
+    Ok(plaintext)
+}
+
+/// Encrypts `json` if the database is encrypted, otherwise returns it as is.
+///
+/// If nothing has been unlocked yet but the file on disk is encrypted, the
+/// passphrase is asked for and checked against that file first, so we never
+/// write plain text over an encrypted database or lock the user out with a
+/// mistyped passphrase.
+pub fn encode_for_disk(json: String, file_path: &Path) -> Result<String, String> {
+    let will_encrypt = SESSION
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|s| s.write_salt.is_some());
+    if !will_encrypt && !WRITE_PLAIN.load(Ordering::SeqCst) {
+        if let Ok(contents) = fs::read_to_string(file_path) {
+            if is_encrypted(&contents) {
+                unlock_database(&contents)?;
+            }
+        }
+    }
+
+    seal(json)
+}
+
+/// Encrypts `json` with the session's write key, if encryption is enabled.
+pub fn seal(json: String) -> Result<String, String> {
+    match seal_envelope_for_session(&json)? {
+        Some(env) => serde_json::to_string_pretty(&env).map_err(|e| e.to_string()),
+        None => Ok(json),
+    }
+}
+
+/// Like `seal`, but produces a single line (for JSON-lines files).
+pub fn seal_line(json: String) -> Result<String, String> {
+    match seal_envelope_for_session(&json)? {
+        Some(env) => serde_json::to_string(&env).map_err(|e| e.to_string()),
+        None => Ok(json),
+    }
+}
+
+fn seal_envelope_for_session(json: &str) -> Result<Option<EncryptedFile>, String> {
+    let (passphrase, salt) = {
+        let session = SESSION.lock().unwrap();
+        match session.as_ref() {
+            Some(Session {
+                passphrase,
+                write_salt: Some(salt),
+                ..
+            }) => (passphrase.clone(), salt.clone()),
+            _ => return Ok(None),
+        }
+    };
+
+    seal_with(&passphrase, &salt, json).map(Some)
+}
+
+/// Unlocks an encrypted database once, up front (used by the daemon so it
+/// never blocks on a prompt in the middle of its loop).
+///
+/// Exits the process if the passphrase is wrong.
+pub fn unlock_at_startup() {
+    let Ok(contents) = fs::read_to_string(get_data_file()) else {
+        return;
+    };
+    if !is_encrypted(&contents) {
+        return;
+    }
+
+    match unlock_database(&contents) {
+        Ok(_) => println!("Database unlocked."),
+        Err(e) => {
+            eprintln!("Error: Cannot unlock medication database: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Converts the database (and its backups) to the encrypted format.
+pub fn encrypt_database() {
+    let file_path = get_data_file();
+    if let Ok(contents) = fs::read_to_string(&file_path) {
+        if is_encrypted(&contents) {
+            println!("Database is already encrypted.");
+            return;
+        }
+    }
+
+    let db = load_database();
+
+    let passphrase = match read_passphrase("New passphrase: ") {
+        Ok((pass, false)) => pass,
+        Ok((pass, true)) => match rpassword::prompt_password("Repeat passphrase: ") {
+            Ok(repeat) if repeat == pass => pass,
+            Ok(_) => {
+                eprintln!("Error: Passphrases do not match!");
+                return;
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to read passphrase: {}", e);
+                return;
+            }
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    if passphrase.is_empty() {
+        eprintln!("Error: Passphrase cannot be empty!");
+        return;
+    }
+
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    *SESSION.lock().unwrap() = Some(Session {
+        passphrase,
+        write_salt: Some(salt),
+        keys: HashMap::new(),
+    });
+
+    save_database(&db);
+    crate::backup::rewrite_backups();
+    crate::audit::rewrite_audit_log();
+    crate::journal::save_journal(&crate::journal::load_journal());
+
+    println!("Encrypted medication database: {}", file_path.display());
+    println!("  Backups, the audit log and the undo journal were encrypted too.");
+    println!("  Keep your passphrase safe - there is no way to recover the data without it.");
+}
+
+/// Converts an encrypted database (and its backups) back to plain JSON.
+pub fn decrypt_database() {
+    let file_path = get_data_file();
+    let encrypted = fs::read_to_string(&file_path)
+        .map(|c| is_encrypted(&c))
+        .unwrap_or(false);
+    if !encrypted {
+        println!("Database is not encrypted.");
+        return;
+    }
+
+    let db = load_database();
+    if let Some(s) = SESSION.lock().unwrap().as_mut() {
+        s.write_salt = None;
+    }
+    WRITE_PLAIN.store(true, Ordering::SeqCst);
+
+    save_database(&db);
+    crate::backup::rewrite_backups();
+    crate::audit::rewrite_audit_log();
+    crate::journal::save_journal(&crate::journal::load_journal());
+
+    println!("Decrypted medication database: {}", file_path.display());
+    println!("  Data, backups, the audit log and the undo journal are plain JSON again.");
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bytes = vec![0u8, 1, 127, 128, 255];
+        assert_eq!(to_hex(&bytes), "00017f80ff");
+        assert_eq!(from_hex("00017f80ff"), Some(bytes));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let salt = [7u8; SALT_LEN];
+        let sealed = seal_with("correct horse", &salt, "{\"medications\":[]}").unwrap();
+        let sealed = serde_json::to_string(&sealed).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("medications"));
+
+        let env = parse_envelope(&sealed).unwrap();
+        let key = derive_key("correct horse", &salt, &env).unwrap();
+        assert_eq!(open_with(&key, &env).unwrap(), "{\"medications\":[]}");
+
+        let wrong = derive_key("battery staple", &salt, &env).unwrap();
+        assert!(open_with(&wrong, &env).is_err());
+    }
+
+    #[test]
+    fn test_plain_json_is_not_encrypted() {
+        assert!(!is_encrypted(
+            "{\"medications\":[],\"archived_medications\":[]}"
+        ));
+        assert!(!is_encrypted("[]"));
+        assert!(!is_encrypted("not json"));
+    }
+}
@@ -1,59 +1,616 @@
-use chrono::{Datelike, Local, NaiveDate};
-use notify_rust::{Notification, Urgency};
-use std::collections::HashSet;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Sender};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::database::{load_medications, reset_all_medications};
-use crate::interval::parse_interval_to_days;
-use crate::time::is_time_due;
+use crate::batches::expiry_alerts;
+use crate::catchup::{
+    catch_up_delivery, missed_while_away, save_heartbeat, summarize, taken_since, CATCH_UP_GAP,
+};
+use crate::config::PharmConfig;
+use crate::control::{
+    acquire_lock, ControlRequest, ControlResponse, ControlServer, DaemonStatus, MedicationRef,
+    UpcomingReminder,
+};
+use crate::database::{
+    load_medications, record_missed_doses, reset_all_medications, take_dose_by_id, Medication,
+};
+use crate::endpoint::TakeServer;
+use crate::escalation::Escalation;
+use crate::logging;
+use crate::notifier::{notifiers_for, send_alert, Alert, AlertKind, Urgency};
+use crate::prescription::prescription_alerts;
+use crate::quiet::{DeferredReminders, Delivery, QuietMode};
+use crate::scheduler::{describe_wait, Reminder, Schedule};
+use crate::watch::FileWatcher;
 
-/// Check if enough time has passed since last dose based on medication interval
-fn is_medication_due_by_interval(
-    last_dose_date: &str,
-    interval_str: &str,
-    today: &NaiveDate,
-) -> bool {
-    // Parse interval to days - None means PRN (as-needed)
-    let interval_days = match parse_interval_to_days(interval_str) {
-        Some(days) => days,
-        None => return true, // PRN medications can always be taken (no interval restriction)
+/// Longest the daemon sleeps without looking at the clock. Sleeps are
+/// measured on the monotonic clock, so this bounds how late a reminder can be
+/// after the wall clock jumps (manual change, NTP, timezone).
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A difference between wall-clock and monotonic time larger than this is
+/// reported as a clock jump
+const CLOCK_JUMP_TOLERANCE: chrono::Duration = chrono::Duration::seconds(30);
+
+/// How long to let a save finish after the first change event, so one save
+/// (temp file + rename + backup) only causes one reload
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Sends `alert` through the medication's notifiers and logs the outcome.
+/// Returns true if at least one notifier delivered it; false also when quiet
+/// hours hold it back, so it is tried again once they are over.
+fn deliver(alert: &Alert, med: &Medication, config: &PharmConfig) -> bool {
+    let alert = match quiet_delivery(med, config, Local::now().naive_local()) {
+        Delivery::Send => alert.clone(),
+        Delivery::Downgrade => Alert {
+            urgency: Urgency::Normal,
+            ..alert.clone()
+        },
+        Delivery::Defer => return false,
     };
 
-    // If never taken, it's due
-    if last_dose_date.is_empty() {
+    let (delivered, failed) = send_alert(&alert, notifiers_for(med, config), config);
+    for error in &failed {
+        logging::error(&format!(
+            "Failed to send {} notification for {}: {}",
+            alert.summary, med.name, error
+        ));
+    }
+    !delivered.is_empty()
+}
+
+/// How quiet hours let alerts about `med` through at `now`
+fn quiet_delivery(med: &Medication, config: &PharmConfig, now: NaiveDateTime) -> Delivery {
+    config
+        .quiet_hours
+        .as_ref()
+        .map_or(Delivery::Send, |quiet| quiet.delivery(med.critical, now))
+}
+
+fn dose_reminder(reminder: &Reminder, med: &Medication, now: NaiveDateTime) -> Alert {
+    let scheduled = if reminder.at.date() < now.date() {
+        // Held back by quiet hours past midnight
+        reminder.at.format("%H:%M %a %d %b").to_string()
+    } else {
+        reminder.time_of_day.clone()
+    };
+    Alert::new(
+        AlertKind::Dose,
+        med,
+        "MEDICATION REMINDER",
+        format!(
+            "Time to take: {} ({})\nScheduled for: {}",
+            reminder.name, reminder.dose, scheduled
+        ),
+    )
+    .scheduled_at(&reminder.time_of_day)
+}
+
+/// Sends a reminder for every dose in the schedule that has come due, at most
+/// once per medication per day, remembering when each was sent. Reminders
+/// quiet hours hold back go to `deferred`.
+fn send_dose_reminders(
+    schedule: &Schedule,
+    meds: &[Medication],
+    now: NaiveDateTime,
+    notified_today: &mut HashMap<u32, NaiveDateTime>,
+    deferred: &mut DeferredReminders,
+    config: &PharmConfig,
+) {
+    for reminder in schedule.due(now) {
+        if notified_today.contains_key(&reminder.med_id) || deferred.is_held(reminder) {
+            continue;
+        }
+        let Some(med) = meds.iter().find(|med| med.id == reminder.med_id) else {
+            continue;
+        };
+
+        if deliver(&dose_reminder(reminder, med, now), med, config) {
+            notified_today.insert(reminder.med_id, now);
+            logging::info(&format!(
+                "Reminder sent: {} - {}",
+                reminder.name, reminder.dose
+            ));
+        } else if quiet_delivery(med, config, now) == Delivery::Defer {
+            logging::info(&format!(
+                "Reminder deferred until quiet hours end: {} - {}",
+                reminder.name, reminder.dose
+            ));
+            deferred.hold(reminder.clone());
+        }
+    }
+}
+
+/// Sends the reminders quiet hours held back past midnight once they are
+/// over, unless the dose has been taken in the meantime. Today's are still in
+/// the schedule and go out with the other reminders.
+fn send_deferred_reminders(
+    deferred: &mut DeferredReminders,
+    meds: &[Medication],
+    now: NaiveDateTime,
+    config: &PharmConfig,
+) {
+    for reminder in deferred.release(config.quiet_hours.as_ref(), now) {
+        let Some(med) = meds.iter().find(|med| med.id == reminder.med_id) else {
+            continue;
+        };
+        if reminder.at.date() == now.date()
+            || taken_since(med, reminder.at.date().and_time(NaiveTime::MIN))
+        {
+            continue;
+        }
+
+        if deliver(&dose_reminder(&reminder, med, now), med, config) {
+            logging::info(&format!(
+                "Deferred reminder sent: {} - {}",
+                reminder.name, reminder.dose
+            ));
+        }
+    }
+}
+
+/// Sends a "missed dose" alert for each reminder that has gone unanswered for
+/// `missed_dose_minutes`, once per dose
+fn send_missed_dose_alerts(
+    schedule: &Schedule,
+    meds: &[Medication],
+    now: NaiveDateTime,
+    notified_today: &HashMap<u32, NaiveDateTime>,
+    missed_notified_today: &mut HashSet<u32>,
+    config: &PharmConfig,
+) {
+    if config.missed_dose_minutes == 0 {
+        return;
+    }
+    let grace = chrono::Duration::minutes(config.missed_dose_minutes as i64);
+
+    for reminder in schedule.due(now) {
+        let Some(reminded_at) = notified_today.get(&reminder.med_id) else {
+            continue;
+        };
+        if now - *reminded_at < grace || missed_notified_today.contains(&reminder.med_id) {
+            continue;
+        }
+        let Some(med) = meds.iter().find(|med| med.id == reminder.med_id) else {
+            continue;
+        };
+
+        let alert = Alert::new(
+            AlertKind::Missed,
+            med,
+            "MISSED DOSE",
+            format!(
+                "{} ({}) was due at {} and hasn't been taken ({})",
+                reminder.name,
+                reminder.dose,
+                reminder.time_of_day,
+                describe_wait(reminder.at, now)
+            ),
+        )
+        .scheduled_at(&reminder.time_of_day);
+        if deliver(&alert, med, config) {
+            missed_notified_today.insert(reminder.med_id);
+            logging::warn(&format!(
+                "Missed dose alert sent: {} - {}",
+                reminder.name, reminder.dose
+            ));
+        }
+    }
+}
+
+/// Alerts a medication's caregivers when the patient's reminder has gone
+/// unanswered for the escalation delay, once per dose
+fn send_caregiver_alerts(
+    schedule: &Schedule,
+    meds: &[Medication],
+    now: NaiveDateTime,
+    notified_today: &HashMap<u32, NaiveDateTime>,
+    escalated_today: &mut HashSet<u32>,
+    config: &PharmConfig,
+) {
+    for reminder in schedule.due(now) {
+        let Some(med) = meds.iter().find(|med| med.id == reminder.med_id) else {
+            continue;
+        };
+        let reminded_at = notified_today.get(&reminder.med_id).copied();
+        let held_back = quiet_delivery(med, config, now) == Delivery::Defer;
+        if reminded_at.is_none() && held_back {
+            // Quiet hours are holding the reminder back on purpose
+            continue;
+        }
+        let since = Escalation::waiting_since(reminder.at, reminded_at);
+        if escalated_today.contains(&med.id)
+            || !med.escalation.is_due(since, now, config.escalation_minutes)
+        {
+            continue;
+        }
+        let waited = crate::interval::format_minutes((now - since).num_minutes().max(0) as u32);
+
+        let alert = Alert::new(
+            AlertKind::Caregiver,
+            med,
+            "CAREGIVER ALERT",
+            format!(
+                "{} ({}) was due at {} and still hasn't been taken, {} after {}",
+                reminder.name,
+                reminder.dose,
+                reminder.time_of_day,
+                waited,
+                if reminded_at.is_some() {
+                    "the reminder"
+                } else {
+                    "it was due (the reminder could not be delivered)"
+                }
+            ),
+        )
+        .scheduled_at(&reminder.time_of_day);
+
+        let (delivered, failed) = send_alert(&alert, &med.escalation.contacts, config);
+        for error in &failed {
+            logging::error(&format!(
+                "Failed to alert caregiver about {}: {}",
+                med.name, error
+            ));
+        }
+        if !delivered.is_empty() {
+            escalated_today.insert(med.id);
+            logging::warn(&format!(
+                "Caregiver alerted about {} via {}",
+                med.name,
+                delivered.join(", ")
+            ));
+        }
+    }
+}
+
+/// Sends one notification listing the doses that came due while the daemon
+/// was away (stopped, or the computer asleep) from `away_since` until
+/// `back_at`. Today's overdue doses count as reminded about; earlier ones are
+/// recorded if `record_missed_doses` is set.
+///
+/// Quiet hours apply as to any other alert, breaking through if one of the
+/// medications is critical. Returns false if they hold the notification back
+/// or no notifier delivered it, so it is tried again later.
+fn send_catch_up(
+    meds: &[Medication],
+    away_since: NaiveDateTime,
+    back_at: NaiveDateTime,
+    now: NaiveDateTime,
+    notified_today: &mut HashMap<u32, NaiveDateTime>,
+    config: &PharmConfig,
+) -> bool {
+    let missed = missed_while_away(meds, away_since, back_at);
+    if missed.is_empty() {
         return true;
     }
 
-    // Parse last dose date
-    let last_dose = match NaiveDate::parse_from_str(last_dose_date, "%Y-%m-%d") {
-        Ok(date) => date,
-        Err(_) => return true, // If we can't parse, assume it's due (safer)
+    let delivery = catch_up_delivery(&missed, meds, config.quiet_hours.as_ref(), now);
+    if delivery == Delivery::Defer {
+        return false;
+    }
+
+    // Every notifier any of the medications would have used
+    let mut names: Vec<String> = Vec::new();
+    for dose in &missed {
+        let Some(med) = meds.iter().find(|med| med.id == dose.med_id) else {
+            continue;
+        };
+        for name in notifiers_for(med, config) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+
+    let summary_med = Medication {
+        name: "pharm".to_string(),
+        ..Default::default()
     };
+    let mut alert = Alert::new(
+        AlertKind::CatchUp,
+        &summary_med,
+        "WHILE YOU WERE AWAY",
+        summarize(&missed, away_since, now),
+    );
+    if delivery == Delivery::Downgrade {
+        alert.urgency = Urgency::Normal;
+    }
+    let (delivered, failed) = send_alert(&alert, &names, config);
+    for error in &failed {
+        logging::error(&format!("Failed to send catch-up notification: {}", error));
+    }
+    if delivered.is_empty() {
+        return false;
+    }
+    for dose in missed.iter().filter(|dose| !dose.is_past(now)) {
+        notified_today.entry(dose.med_id).or_insert(now);
+    }
+    logging::warn(&format!(
+        "Catch-up sent: {} missed or overdue dose(s)",
+        missed.len()
+    ));
+
+    if config.record_missed_doses {
+        let past: Vec<_> = missed
+            .into_iter()
+            .filter(|dose| dose.is_past(now))
+            .collect();
+        let recorded = record_missed_doses(&past);
+        if recorded > 0 {
+            logging::info(&format!("Recorded {} missed dose(s)", recorded));
+        }
+    }
+    true
+}
 
-    // Calculate days since last dose
-    let days_since_dose = (*today - last_dose).num_days();
+/// Sends a "refill needed" notification for each medication running low,
+/// at most once per medication per day
+fn send_refill_reminders(
+    meds: &[Medication],
+    refill_notified_today: &mut HashSet<u32>,
+    config: &PharmConfig,
+) {
+    let now = Local::now();
+
+    for med in meds {
+        let Some(message) =
+            crate::stock::refill_needed(med, now.naive_local(), config.refill_threshold_days)
+        else {
+            // Replenished (or not tracked) - remind again once it runs low
+            refill_notified_today.remove(&med.id);
+            continue;
+        };
+        if refill_notified_today.contains(&med.id) {
+            continue;
+        }
 
-    // Medication is due if enough days have passed
-    days_since_dose >= interval_days as i64
+        let alert = Alert::new(
+            AlertKind::Refill,
+            med,
+            "REFILL NEEDED",
+            format!(
+                "{}\nUpdate the count with: pharm stock add {} <quantity>",
+                message, med.id
+            ),
+        );
+        if deliver(&alert, med, config) {
+            refill_notified_today.insert(med.id);
+            logging::info(&format!("Refill reminder sent: {}", message));
+        }
+    }
 }
 
-pub fn run_daemon() {
-    println!("Daemon started. Checking for medication reminders...");
-    println!("Press Ctrl+C to stop.");
-    println!(
-        "Don't forget to add this process to your system's autostart/startup to always have reminders active!"
+/// Sends one notification per medication with alerts (e.g. an expiring
+/// prescription), at most once per medication per day
+fn send_daily_alerts(
+    meds: &[Medication],
+    notified_today: &mut HashSet<u32>,
+    kind: AlertKind,
+    summary: &str,
+    config: &PharmConfig,
+    alerts_for: impl Fn(&Medication) -> Vec<String>,
+) {
+    for med in meds {
+        if notified_today.contains(&med.id) {
+            continue;
+        }
+        let alerts = alerts_for(med);
+        if alerts.is_empty() {
+            continue;
+        }
+
+        let alert = Alert::new(kind, med, summary, alerts.join("\n"));
+        if deliver(&alert, med, config) {
+            notified_today.insert(med.id);
+            for alert in &alerts {
+                logging::info(&format!("{} sent: {}", summary, alert));
+            }
+        }
+    }
+}
+
+/// When the loop next has something to do: a dose coming due, a missed-dose
+/// alert or caregiver alert falling due, quiet hours ending (deferred
+/// reminders and catch-up), or midnight
+fn next_wakeup(
+    schedule: &Schedule,
+    meds: &[Medication],
+    now: NaiveDateTime,
+    notified_today: &HashMap<u32, NaiveDateTime>,
+    missed_notified_today: &HashSet<u32>,
+    escalated_today: &HashSet<u32>,
+    config: &PharmConfig,
+) -> NaiveDateTime {
+    let midnight = (now.date() + chrono::Duration::days(1)).and_time(NaiveTime::MIN);
+    let mut deadlines = vec![midnight];
+    deadlines.extend(schedule.next_after(now).map(|reminder| reminder.at));
+
+    for reminder in schedule.due(now) {
+        let reminded_at = notified_today.get(&reminder.med_id).copied();
+        if let Some(reminded_at) = reminded_at {
+            if config.missed_dose_minutes > 0 && !missed_notified_today.contains(&reminder.med_id) {
+                deadlines.push(
+                    reminded_at + chrono::Duration::minutes(config.missed_dose_minutes as i64),
+                );
+            }
+        }
+
+        let Some(med) = meds.iter().find(|med| med.id == reminder.med_id) else {
+            continue;
+        };
+        if !med.escalation.contacts.is_empty() && !escalated_today.contains(&med.id) {
+            let delay = med.escalation.delay_minutes(config.escalation_minutes);
+            deadlines.push(
+                Escalation::waiting_since(reminder.at, reminded_at)
+                    + chrono::Duration::minutes(delay as i64),
+            );
+        }
+    }
+
+    deadlines.extend(
+        config
+            .quiet_hours
+            .as_ref()
+            .and_then(|quiet| quiet.ends_at(now)),
     );
-    println!("Enter HELP, help, -h or --help at any time for more information about a command.");
+
+    // Deadlines already passed were acted on this pass; one whose alert
+    // failed is retried on the next
+    deadlines
+        .into_iter()
+        .filter(|at| *at > now)
+        .min()
+        .unwrap_or(midnight)
+}
+
+/// What wakes the daemon loop before its timeout
+#[derive(Clone)]
+enum Wakeup {
+    /// The database or config file changed
+    FilesChanged,
+    /// A request on the control socket, with a channel for the reply
+    Control(ControlRequest, Sender<ControlResponse>),
+}
+
+/// Everything `pharm daemon status` reports
+fn daemon_status(
+    started: DateTime<Local>,
+    watching_files: bool,
+    meds: &[Medication],
+    notified_today: &HashMap<u32, NaiveDateTime>,
+) -> DaemonStatus {
+    let now = Local::now();
+    let schedule = Schedule::build(meds, now.naive_local());
+
+    DaemonStatus {
+        pid: std::process::id(),
+        started_at: started.format("%Y-%m-%d %H:%M:%S").to_string(),
+        uptime_secs: (now - started).num_seconds().max(0) as u64,
+        watching_files,
+        next_reminders: schedule
+            .upcoming()
+            .into_iter()
+            .map(|reminder| UpcomingReminder {
+                at: reminder.at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                med_id: reminder.med_id,
+                name: reminder.name,
+                dose: reminder.dose,
+            })
+            .collect(),
+        notified_today: meds
+            .iter()
+            .filter(|med| notified_today.contains_key(&med.id))
+            .map(|med| MedicationRef {
+                id: med.id,
+                name: med.name.clone(),
+            })
+            .collect(),
+    }
+}
+
+pub fn run_daemon() {
+    // Only one daemon may run at a time
+    let _lock = match acquire_lock() {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Check on it with: pharm daemon status");
+            return;
+        }
+    };
+
+    logging::info("Daemon started. Checking for medication reminders...");
+    // systemd sets INVOCATION_ID for the processes it starts
+    if std::env::var_os("INVOCATION_ID").is_none() {
+        println!("Press Ctrl+C to stop.");
+        println!("Run 'pharm daemon install' to start reminders automatically when you log in.");
+        println!(
+            "Enter HELP, help, -h or --help at any time for more information about a command."
+        );
+    }
+
+    // Encrypted databases are unlocked once here, not on every reload
+    crate::crypto::unlock_at_startup();
 
     // CRITICAL: Reset medications on startup in case daemon wasn't running at midnight
     // This ensures medications due today are reset even if daemon was off overnight
-    println!("Checking for medications that need to be reset...");
+    logging::info("Checking for medications that need to be reset...");
     reset_all_medications();
 
     // Track which medications we've already notified about today
-    let mut notified_today: HashSet<String> = HashSet::new();
+    let mut notified_today: HashMap<u32, NaiveDateTime> = HashMap::new();
+    let mut missed_notified_today: HashSet<u32> = HashSet::new();
+    let mut escalated_today: HashSet<u32> = HashSet::new();
+    let mut refill_notified_today: HashSet<u32> = HashSet::new();
+    let mut rx_notified_today: HashSet<u32> = HashSet::new();
+    let mut expiry_notified_today: HashSet<u32> = HashSet::new();
+    // Not reset at midnight: held until quiet hours end
+    let mut deferred = DeferredReminders::default();
     let mut current_day = Local::now().day();
+    let mut was_quiet = false;
+    // When the loop last ran, to notice time spent stopped or suspended
+    let mut last_pass = crate::catchup::last_heartbeat();
+    // (away since, back at) of a catch-up held back by quiet hours; not
+    // reset at midnight
+    let mut catch_up: Option<(NaiveDateTime, NaiveDateTime)> = None;
+    let mut heartbeat_failed = false;
+    let started = Local::now();
+
+    let (wakeup_sender, wakeups) = channel();
+
+    // Reload only when another pharm command changes the database
+    let watcher = match FileWatcher::start(wakeup_sender.clone(), Wakeup::FilesChanged) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            logging::warn(&format!(
+                "Can't watch the database for changes ({}), re-reading it every minute",
+                e
+            ));
+            None
+        }
+    };
+
+    // `pharm daemon status/stop/reload` talk to us through this socket
+    let _control = match ControlServer::start(wakeup_sender.clone(), Wakeup::Control) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            logging::warn(&format!("Control socket unavailable ({})", e));
+            None
+        }
+    };
+
+    // "Taken" buttons on push notifications land here
+    let _take_server = crate::config::load_config()
+        .take_endpoint
+        .and_then(
+            |endpoint| match TakeServer::start(&endpoint, wakeup_sender, Wakeup::Control) {
+                Ok(server) => {
+                    logging::info(&format!("Take endpoint listening on {}", endpoint.listen));
+                    Some(server)
+                }
+                Err(e) => {
+                    logging::warn(&format!("Take endpoint unavailable ({})", e));
+                    None
+                }
+            },
+        );
+
+    if let Some(Err(e)) = crate::config::load_config()
+        .quiet_hours
+        .map(|quiet| quiet.validate())
+    {
+        logging::warn(&format!("Quiet hours ignored: {}", e));
+    }
+
+    let mut meds = load_medications();
+
+    // Tell systemd (Type=notify) that start-up is done
+    crate::service::notify_systemd(&format!(
+        "READY=1\nSTATUS=Watching {} medication(s)",
+        meds.len()
+    ));
 
     loop {
         let now = Local::now();
@@ -61,72 +618,217 @@ pub fn run_daemon() {
         // Reset notifications and medication status at midnight
         if now.day() != current_day {
             notified_today.clear();
+            missed_notified_today.clear();
+            escalated_today.clear();
+            refill_notified_today.clear();
+            rx_notified_today.clear();
+            expiry_notified_today.clear();
             current_day = now.day();
-            println!(
-                "[{}] NEW DAY DETECTED - RESETTING ALL MEDICATIONS TO UNTAKEN AND RESETTING TIMERS",
-                now.format("%H:%M:%S")
+            logging::info(
+                "NEW DAY DETECTED - RESETTING ALL MEDICATIONS TO UNTAKEN AND RESETTING TIMERS",
             );
             reset_all_medications();
+            meds = load_medications();
         }
 
-        let meds = load_medications();
         let today_date = now.date_naive();
+        let schedule = Schedule::build(&meds, now.naive_local());
 
-        for med in meds.iter() {
-            // Skip PRN (as-needed) medications - they have no schedule
-            if parse_interval_to_days(&med.medication_frequency).is_none() {
-                continue;
+        // A medication that has been taken can be reminded about again next time
+        for med in meds.iter().filter(|med| med.taken) {
+            notified_today.remove(&med.id);
+            missed_notified_today.remove(&med.id);
+            escalated_today.remove(&med.id);
+        }
+        let config = crate::config::load_config();
+        let quiet_until = config
+            .quiet_hours
+            .as_ref()
+            .and_then(|quiet| quiet.ends_at(now.naive_local()));
+        if quiet_until.is_some() != was_quiet {
+            was_quiet = quiet_until.is_some();
+            match quiet_until {
+                Some(until) => logging::info(&format!(
+                    "Quiet hours until {}, {} reminders for non-critical medications",
+                    until.format("%H:%M"),
+                    match config.quiet_hours.as_ref().map(|quiet| quiet.mode) {
+                        Some(QuietMode::Downgrade) => "downgrading",
+                        _ => "deferring",
+                    }
+                )),
+                None => logging::info("Quiet hours over"),
             }
-
-            // Clear notification flag if medication was taken
-            if med.taken && notified_today.contains(&med.name) {
-                notified_today.remove(&med.name);
+        }
+        // Catch up on doses that came due while the daemon was stopped, the
+        // computer was asleep or the clock jumped forward
+        if let Some(away_since) = last_pass.filter(|last| now.naive_local() - *last > CATCH_UP_GAP)
+        {
+            logging::info(&format!(
+                "Away since {}, checking for missed doses",
+                away_since.format("%Y-%m-%d %H:%M")
+            ));
+            // Still holding one back: cover both absences
+            let since = catch_up.map_or(away_since, |(since, _)| since.min(away_since));
+            catch_up = Some((since, now.naive_local()));
+        }
+        if let Some((away_since, back_at)) = catch_up {
+            if send_catch_up(
+                &meds,
+                away_since,
+                back_at,
+                now.naive_local(),
+                &mut notified_today,
+                &config,
+            ) {
+                catch_up = None;
+            } else if last_pass.is_some_and(|last| last < back_at) && quiet_until.is_some() {
+                logging::info("Catch-up deferred until quiet hours end");
             }
+        }
+        last_pass = Some(now.naive_local());
+        match save_heartbeat(now.naive_local()) {
+            Ok(()) => heartbeat_failed = false,
+            Err(e) if !heartbeat_failed => {
+                logging::warn(&format!("Can't save heartbeat ({})", e));
+                heartbeat_failed = true;
+            }
+            Err(_) => {}
+        }
 
-            // Check if medication is due by both time-of-day AND interval
-            let time_is_due = is_time_due(&med.time_of_day);
-            let interval_allows = is_medication_due_by_interval(
-                &med.last_dose_date,
-                &med.medication_frequency,
-                &today_date,
-            );
+        send_deferred_reminders(&mut deferred, &meds, now.naive_local(), &config);
+        send_dose_reminders(
+            &schedule,
+            &meds,
+            now.naive_local(),
+            &mut notified_today,
+            &mut deferred,
+            &config,
+        );
+        send_missed_dose_alerts(
+            &schedule,
+            &meds,
+            now.naive_local(),
+            &notified_today,
+            &mut missed_notified_today,
+            &config,
+        );
+        send_caregiver_alerts(
+            &schedule,
+            &meds,
+            now.naive_local(),
+            &notified_today,
+            &mut escalated_today,
+            &config,
+        );
 
-            // Only notify for untaken medications that are:
-            // 1. Past their scheduled time of day
-            // 2. Haven't been taken too recently (interval check)
-            // 3. Haven't been notified yet today
-            if !med.taken && time_is_due && interval_allows && !notified_today.contains(&med.name) {
-                let result = Notification::new()
-                    .summary("MEDICATION REMINDER")
-                    .body(&format!(
-                        "Time to take: {} ({})\nScheduled for: {}",
-                        med.name, med.dose, med.time_of_day
-                    ))
-                    .icon("MEDICATION")
-                    .timeout(0) // Don't auto-dismiss
-                    .appname("pharm")
-                    .urgency(Urgency::Critical)
-                    .show();
-
-                if result.is_ok() {
-                    notified_today.insert(med.name.clone());
-                    println!(
-                        "[{}] Reminder sent: {} - {}",
-                        now.format("%H:%M:%S"),
-                        med.name,
-                        med.dose
-                    );
-                } else {
-                    eprintln!(
-                        "[{}] Failed to send notification for: {}",
-                        now.format("%H:%M:%S"),
-                        med.name
-                    );
+        send_refill_reminders(&meds, &mut refill_notified_today, &config);
+
+        send_daily_alerts(
+            &meds,
+            &mut rx_notified_today,
+            AlertKind::Prescription,
+            "PRESCRIPTION",
+            &config,
+            |med| prescription_alerts(med, today_date, config.prescription_warning_days),
+        );
+        send_daily_alerts(
+            &meds,
+            &mut expiry_notified_today,
+            AlertKind::Expiry,
+            "EXPIRING SUPPLY",
+            &config,
+            |med| expiry_alerts(med, today_date, config.expiry_warning_days),
+        );
+
+        // Sleep until there is something to send, a change to the database
+        // or a control request - whichever comes first
+        let wakeup = next_wakeup(
+            &schedule,
+            &meds,
+            now.naive_local(),
+            &notified_today,
+            &missed_notified_today,
+            &escalated_today,
+            &config,
+        );
+        let timeout = (wakeup - Local::now().naive_local())
+            .to_std()
+            .unwrap_or_default()
+            .clamp(Duration::from_millis(100), CLOCK_CHECK_INTERVAL);
+
+        let slept_at = (Instant::now(), Local::now().naive_local());
+
+        let mut requests = Vec::new();
+        match wakeups.recv_timeout(timeout) {
+            Ok(Wakeup::FilesChanged) => {
+                // One save touches the file several times - let it finish
+                thread::sleep(SETTLE_TIME);
+                for wakeup in wakeups.try_iter() {
+                    if let Wakeup::Control(request, reply) = wakeup {
+                        requests.push((request, reply));
+                    }
+                }
+                logging::info("Database changed, reloading medications");
+                meds = load_medications();
+            }
+            Ok(Wakeup::Control(request, reply)) => requests.push((request, reply)),
+            Err(_) => {
+                if watcher.is_none() {
+                    meds = load_medications();
                 }
             }
         }
 
-        // Check every 60 seconds
-        thread::sleep(Duration::from_secs(60));
+        for (request, reply) in requests {
+            let response = match request {
+                ControlRequest::Status => ControlResponse::Status(daemon_status(
+                    started,
+                    watcher.is_some(),
+                    &meds,
+                    &notified_today,
+                )),
+                ControlRequest::Reload => {
+                    meds = load_medications();
+                    logging::info(&format!(
+                        "Reload requested, reloaded {} medication(s)",
+                        meds.len()
+                    ));
+                    ControlResponse::Ok {
+                        message: format!("Daemon reloaded {} medication(s)", meds.len()),
+                    }
+                }
+                ControlRequest::Take { id } => match take_dose_by_id(id) {
+                    Ok(message) => {
+                        logging::info(&message);
+                        meds = load_medications();
+                        ControlResponse::Ok { message }
+                    }
+                    Err(message) => {
+                        logging::warn(&message);
+                        ControlResponse::Error { message }
+                    }
+                },
+                ControlRequest::Stop => {
+                    // The control thread has already answered
+                    logging::info("Stop requested, shutting down");
+                    crate::service::notify_systemd("STOPPING=1");
+                    return;
+                }
+            };
+            let _ = reply.send(response);
+        }
+
+        // The schedule is rebuilt from the wall clock on every pass, so a
+        // jump only needs to be reported
+        let wall_elapsed = Local::now().naive_local() - slept_at.1;
+        let monotonic_elapsed =
+            chrono::Duration::from_std(slept_at.0.elapsed()).unwrap_or_default();
+        let jump = wall_elapsed - monotonic_elapsed;
+        if jump.abs() > CLOCK_JUMP_TOLERANCE {
+            logging::info(&format!(
+                "Clock changed by {} seconds, recomputing reminders",
+                jump.num_seconds()
+            ));
+        }
     }
 }
@@ -1,5 +1,6 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
@@ -7,16 +8,46 @@ use std::os::unix::fs::PermissionsExt;
 use chrono::TimeZone;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::allergies::{confirm_allergy_conflicts, Allergy};
+use crate::batches::{describe_batches, expiry_alerts, Batch};
+use crate::config::load_config;
+use crate::dose::{parse_dose, sum_quantities, DoseQuantity, SUPPORTED_UNITS};
+use crate::escalation::Escalation;
+use crate::ingredients::{
+    check_ingredient_limits, describe_ingredients, format_mg, ingredient_totals, normalize_limits,
+    Ingredient, IngredientViolation,
+};
+use crate::limits::{check_dose_limits, check_schedule, DoseLimits, EarlyDose, LimitViolation};
+use crate::prescription::{prescription_alerts, Prescription, PrescriptionEdit};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct DoseRecord {
     pub timestamp: String, // Full datetime: "2025-10-21 08:30:15"
     pub dose: String,      // Dose at time of taking (in case it changes)
+    /// `dose` parsed into amount and unit (absent for unparseable old records)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<DoseQuantity>,
+    /// Units this dose took from the stock (absent if stock wasn't tracked);
+    /// what an untake puts back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stock_used: Option<f64>,
+    /// The part of `stock_used` that came from each batch, so an untake puts
+    /// it back before the right expiry date
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batches_used: Vec<Batch>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Medication {
+    /// Stable unique ID (shown in `list`), kept across renames and archiving
+    #[serde(default)]
+    pub id: u32,
     pub name: String,
+    /// Dose as entered by the user, kept for display
     pub dose: String,
+    /// `dose` parsed into amount and unit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dose_quantity: Option<DoseQuantity>,
     pub time_of_day: String,
     pub medication_frequency: String,
     pub taken: bool,
@@ -25,16 +56,54 @@ pub struct Medication {
     #[serde(default)]
     pub last_dose_date: String,
     pub notes: Option<String>,
+    /// Optional maximum doses per day and minimum spacing (see `limits`)
+    #[serde(default, skip_serializing_if = "DoseLimits::is_empty")]
+    pub limits: DoseLimits,
+    /// Active ingredients per dose, counted against `ingredient_limits_mg`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ingredients: Vec<Ingredient>,
+    /// Units on hand (pills, puffs, ml, ...), decremented by each dose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stock: Option<f64>,
+    /// Units taken from `stock` per dose, if not implied by the dose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units_per_dose: Option<f64>,
+    /// Batches of the stock with their expiry dates, oldest used first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batches: Vec<Batch>,
+    /// Prescriber, pharmacy, Rx number, refills and expiry
+    #[serde(default, skip_serializing_if = "Prescription::is_empty")]
+    pub prescription: Prescription,
+    /// Notifiers used for this medication's alerts instead of the config's
+    /// `notify_via`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_via: Vec<String>,
+    /// Caregivers alerted when a dose goes untaken
+    #[serde(default, skip_serializing_if = "Escalation::is_empty")]
+    pub escalation: Escalation,
+    /// Reminders break through quiet hours (e.g. insulin)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub critical: bool,
+    /// Scheduled doses the daemon found were never taken ("08:00:00 -
+    /// 2025/10/21"), kept only with `record_missed_doses`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missed: Vec<String>,
     /// Complete history of all doses taken
     #[serde(default)]
     pub history: Vec<DoseRecord>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct MedicationDatabase {
     pub medications: Vec<Medication>,
     #[serde(default)]
     pub archived_medications: Vec<Medication>,
+    /// Next medication ID to hand out (IDs are never reused)
+    #[serde(default)]
+    pub next_id: u32,
+    /// Recorded allergies, checked when medications are added or edited
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allergies: Vec<Allergy>,
 }
 
 /// Returns the path to the medication database file.
@@ -52,15 +121,15 @@ pub fn get_data_file() -> PathBuf {
 /// Handles automatic migration from the old format (just `Vec<Medication>`)
 /// to the new format with archive support (`MedicationDatabase`).
 ///
+/// Encrypted databases are decrypted transparently (see `crypto`); the process
+/// exits if the passphrase is wrong rather than treating the file as corrupted.
+///
 /// If the file is corrupted, creates a backup and returns an empty database.
 /// If the file doesn't exist, returns an empty database.
 pub fn load_database() -> MedicationDatabase {
     let file_path = get_data_file();
     if !file_path.exists() {
-        return MedicationDatabase {
-            medications: Vec::new(),
-            archived_medications: Vec::new(),
-        };
+        return MedicationDatabase::default();
     }
 
     let contents = match fs::read_to_string(&file_path) {
@@ -71,27 +140,52 @@ pub fn load_database() -> MedicationDatabase {
                 "Using empty medication list. Check file permissions on: {}",
                 file_path.display()
             );
-            return MedicationDatabase {
-                medications: Vec::new(),
-                archived_medications: Vec::new(),
-            };
+            return MedicationDatabase::default();
+        }
+    };
+
+    // Encrypted databases are only ever decrypted in memory. A wrong passphrase
+    // must not fall through to the "corrupted" path below.
+    let contents = if crate::crypto::is_encrypted(&contents) {
+        match crate::crypto::unlock_database(&contents) {
+            Ok(plaintext) => plaintext,
+            Err(e) => {
+                eprintln!("Error: Cannot unlock medication database: {}", e);
+                std::process::exit(1);
+            }
         }
+    } else {
+        contents
     };
 
     // Try to parse as new format first
-    if let Ok(db) = serde_json::from_str::<MedicationDatabase>(&contents) {
+    if let Ok(mut db) = serde_json::from_str::<MedicationDatabase>(&contents) {
+        // Databases from before medication IDs / structured doses existed
+        let assigned_ids = assign_missing_ids(&mut db);
+        let parsed_doses = fill_dose_quantities(&mut db);
+        if assigned_ids {
+            eprintln!("Assigning IDs to medications...");
+        }
+        if parsed_doses {
+            eprintln!("Converting doses to amount + unit...");
+        }
+        if assigned_ids || parsed_doses {
+            crate::audit::with_command("migrate", || save_database(&db));
+        }
         return db;
     }
 
     // Try to parse as old format (just Vec<Medication>) and migrate
     if let Ok(meds) = serde_json::from_str::<Vec<Medication>>(&contents) {
         eprintln!("Migrating medication database to new format with archive support...");
-        let db = MedicationDatabase {
+        let mut db = MedicationDatabase {
             medications: meds,
-            archived_medications: Vec::new(),
+            ..Default::default()
         };
+        assign_missing_ids(&mut db);
+        fill_dose_quantities(&mut db);
         // Save migrated data immediately
-        save_database(&db);
+        crate::audit::with_command("migrate", || save_database(&db));
         eprintln!("Migration complete!");
         return db;
     }
@@ -110,10 +204,156 @@ pub fn load_database() -> MedicationDatabase {
     }
 
     eprintln!("Starting with empty medication database.");
-    MedicationDatabase {
-        medications: Vec::new(),
-        archived_medications: Vec::new(),
+    MedicationDatabase::default()
+}
+
+/// Parses database file contents in either the current format or the old
+/// `Vec<Medication>` format, without touching the file on disk.
+///
+/// Returns `None` if the contents match neither format.
+pub fn parse_database(contents: &str) -> Option<MedicationDatabase> {
+    let mut db = match serde_json::from_str::<MedicationDatabase>(contents) {
+        Ok(db) => db,
+        Err(_) => MedicationDatabase {
+            medications: serde_json::from_str::<Vec<Medication>>(contents).ok()?,
+            ..Default::default()
+        },
+    };
+
+    // Same migrations `load_database` would apply, so old files compare cleanly
+    assign_missing_ids(&mut db);
+    fill_dose_quantities(&mut db);
+    Some(db)
+}
+
+/// Parses the free-text dose of every medication and dose record that has no
+/// structured quantity yet. Doses that cannot be parsed keep only their text.
+/// Returns true if anything changed.
+pub fn fill_dose_quantities(db: &mut MedicationDatabase) -> bool {
+    let mut changed = false;
+    for med in db
+        .medications
+        .iter_mut()
+        .chain(db.archived_medications.iter_mut())
+    {
+        if med.dose_quantity.is_none() {
+            med.dose_quantity = parse_dose(&med.dose);
+            changed |= med.dose_quantity.is_some();
+        }
+        for record in med.history.iter_mut() {
+            if record.quantity.is_none() {
+                record.quantity = parse_dose(&record.dose);
+                changed |= record.quantity.is_some();
+            }
+        }
+    }
+    changed
+}
+
+/// Gives every medication without an ID (from before IDs existed) a new one,
+/// active medications first. Returns true if anything changed.
+pub fn assign_missing_ids(db: &mut MedicationDatabase) -> bool {
+    let max_id = db
+        .medications
+        .iter()
+        .chain(db.archived_medications.iter())
+        .map(|m| m.id)
+        .max()
+        .unwrap_or(0);
+
+    let mut changed = false;
+    if db.next_id <= max_id {
+        // Only worth saving if medications already use higher IDs
+        changed = max_id > 0;
+        db.next_id = max_id + 1;
+    }
+
+    for med in db
+        .medications
+        .iter_mut()
+        .chain(db.archived_medications.iter_mut())
+    {
+        if med.id == 0 {
+            med.id = db.next_id;
+            db.next_id += 1;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Why a medication couldn't be resolved from what the user typed.
+#[derive(Debug, PartialEq)]
+pub enum LookupError {
+    NotFound,
+    /// Several medications share the name: (id, name, dose) of each
+    Ambiguous(Vec<(u32, String, String)>),
+}
+
+/// Finds a medication by ID ("3" or "#3") or by name (case-insensitive).
+///
+/// An ID match wins over a name match, so a medication literally named "3"
+/// can still be reached through its own ID.
+pub fn find_medication(meds: &[Medication], query: &str) -> Result<usize, LookupError> {
+    let query = query.trim();
+
+    if let Ok(id) = query.trim_start_matches('#').parse::<u32>() {
+        if let Some(index) = meds.iter().position(|m| m.id == id) {
+            return Ok(index);
+        }
+    }
+
+    let query_lower = query.to_lowercase();
+    let matches: Vec<usize> = meds
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.name.to_lowercase() == query_lower)
+        .map(|(i, _)| i)
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err(LookupError::NotFound),
+        [index] => Ok(*index),
+        _ => Err(LookupError::Ambiguous(
+            matches
+                .iter()
+                .map(|&i| (meds[i].id, meds[i].name.clone(), meds[i].dose.clone()))
+                .collect(),
+        )),
+    }
+}
+
+/// Returns true if `query` names this medication by ID or by name.
+pub fn matches_medication(med: &Medication, query: &str) -> bool {
+    let query = query.trim();
+    match query.trim_start_matches('#').parse::<u32>() {
+        Ok(id) if med.id == id => true,
+        _ => med.name.to_lowercase() == query.to_lowercase(),
+    }
+}
+
+/// Prints the candidates when a name matches more than one medication.
+pub fn report_ambiguous(query: &str, candidates: &[(u32, String, String)]) {
+    eprintln!(
+        "Error: '{}' matches {} medications. Use the ID instead:",
+        query,
+        candidates.len()
+    );
+    for (id, name, dose) in candidates {
+        eprintln!("  #{}  {} ({})", id, name, dose);
+    }
+}
+
+/// Reads and parses a database file (decrypting it if needed) without any of
+/// `load_database`'s side effects such as migration or corrupted-file backups.
+pub fn read_database_file(path: &Path) -> Option<MedicationDatabase> {
+    let contents = fs::read_to_string(path).ok()?;
+    if crate::crypto::is_encrypted(&contents) {
+        let plaintext = crate::crypto::decrypt_contents(&contents).ok()?;
+        return parse_database(&plaintext);
     }
+    parse_database(&contents)
 }
 
 /// Loads only the active medications from the database.
@@ -128,7 +368,10 @@ pub fn load_medications() -> Vec<Medication> {
 ///
 /// Uses atomic write pattern (write to temp file, then rename) to prevent
 /// data corruption if interrupted. Sets file permissions to 0600 on Unix
-/// systems for privacy.
+/// systems for privacy. The previous file is copied into the rolling backups
+/// first (see `backup::create_backup`), every change is appended to the
+/// audit log (see `audit::record`) and user commands are journaled for
+/// `pharm undo` (see `journal::record`).
 pub fn save_database(db: &MedicationDatabase) {
     let file_path = get_data_file();
 
@@ -140,6 +383,15 @@ pub fn save_database(db: &MedicationDatabase) {
         }
     };
 
+    // Encrypt if the database is encrypted (no-op for plain JSON databases)
+    let json = match crate::crypto::encode_for_disk(json, &file_path) {
+        Ok(j) => j,
+        Err(e) => {
+            eprintln!("Error: Failed to encrypt medication database: {}", e);
+            return;
+        }
+    };
+
     // Atomic write: write to temp file, then rename
     let temp_path = file_path.with_extension("json.tmp");
 
@@ -148,6 +400,10 @@ pub fn save_database(db: &MedicationDatabase) {
         return;
     }
 
+    // Keep a copy of the state we're about to replace
+    crate::backup::create_backup(&file_path);
+    let previous = read_database_file(&file_path);
+
     // Rename is atomic on POSIX systems
     if let Err(e) = fs::rename(&temp_path, &file_path) {
         eprintln!("Error: Failed to save medications file: {}", e);
@@ -156,6 +412,9 @@ pub fn save_database(db: &MedicationDatabase) {
         return;
     }
 
+    crate::audit::record(previous.as_ref(), db);
+    crate::journal::record(previous.as_ref(), db);
+
     // Set file permissions to 0600 (owner read/write only) for privacy
     // This is Unix-specific; Windows uses different permission models
     #[cfg(unix)]
@@ -181,11 +440,35 @@ pub fn save_medications(meds: &[Medication]) {
     save_database(&db);
 }
 
+/// Explains which dose formats are accepted
+fn report_invalid_dose(dose: &str) {
+    eprintln!("Error: Invalid dose '{}'", dose);
+    eprintln!(
+        "A dose is an amount followed by a unit, e.g. '500mg', '0.5 g', '2 tablets', '1/2 tab'"
+    );
+    eprintln!("Supported units: {}", SUPPORTED_UNITS);
+}
+
+/// Optional settings for `add_medication` beyond the required fields
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Allow a second active medication with the same name (e.g. two
+    /// strengths of "Vitamin D"); they are told apart by ID
+    pub allow_duplicate: bool,
+    /// Maximum doses per day / minimum gap between doses
+    pub limits: DoseLimits,
+    /// Active ingredients per dose
+    pub ingredients: Vec<Ingredient>,
+    /// Don't ask for confirmation when the medication matches an allergy
+    pub yes: bool,
+}
+
 /// Adds a new medication or unarchives an existing archived medication.
 ///
 /// If a medication with the same name (case-insensitive) exists in the archive,
 /// it will be moved back to active medications with updated fields but preserved
-/// history. Otherwise, creates a new medication with empty history.
+/// history and ID. Otherwise, creates a new medication with a new ID and empty
+/// history.
 ///
 /// # Arguments
 /// * `name` - Medication name
@@ -193,17 +476,19 @@ pub fn save_medications(meds: &[Medication]) {
 /// * `time` - Time to take (e.g., "8:00", "morning")
 /// * `interval` - Frequency (e.g., "daily", "every 3 days")
 /// * `notes` - Optional notes (e.g., "take with food")
+/// * `options` - Duplicate handling, dose limits and ingredients (see `AddOptions`)
 ///
 /// # Validation
 /// - Name, dose, and interval cannot be empty
 /// - Time must be parseable by `time::parse_time`
-/// - Name must not exist in active medications
+/// - Name must not exist in active medications unless `options.allow_duplicate` is set
 pub fn add_medication(
     name: String,
     dose: String,
     time: String,
     interval: String,
     notes: Option<String>,
+    options: AddOptions,
 ) {
     // Validate inputs
     if name.trim().is_empty() {
@@ -216,6 +501,11 @@ pub fn add_medication(
         return;
     }
 
+    let Some(dose_quantity) = parse_dose(&dose) else {
+        report_invalid_dose(&dose);
+        return;
+    };
+
     if interval.trim().is_empty() {
         eprintln!("Error: Interval cannot be empty!");
         return;
@@ -235,23 +525,27 @@ pub fn add_medication(
     let name_lower = name.to_lowercase();
 
     // Check if medication already exists in active list
-    if db
+    if let Some(existing) = db
         .medications
         .iter()
-        .any(|m| m.name.to_lowercase() == name_lower)
+        .find(|m| m.name.to_lowercase() == name_lower)
     {
-        eprintln!(
-            "Error: Medication '{}' already exists in active medications!",
-            name
-        );
-        return;
+        if !options.allow_duplicate {
+            eprintln!(
+                "Error: Medication '{}' already exists in active medications (#{})!",
+                name, existing.id
+            );
+            eprintln!("  To track a second product with the same name, add --allow-duplicate");
+            return;
+        }
     }
 
-    // Check if medication exists in archive - if so, unarchive it
+    // Check if medication exists in archive - if so, unarchive it (the most
+    // recently archived one if several share the name)
     let archived_index = db
         .archived_medications
         .iter()
-        .position(|m| m.name.to_lowercase() == name_lower);
+        .rposition(|m| m.name.to_lowercase() == name_lower);
 
     if let Some(index) = archived_index {
         // Unarchive: move from archived to active, updating fields
@@ -259,82 +553,153 @@ pub fn add_medication(
 
         // Update fields with new values, but preserve history
         med.dose = dose;
+        med.dose_quantity = Some(dose_quantity);
         med.time_of_day = time;
         med.medication_frequency = interval;
         med.notes = notes;
+        med.limits = options.limits;
+        med.ingredients = options.ingredients;
         med.taken = false;
         med.taken_at = String::new();
-        // Keep last_dose_date and history
+        // Keep id, last_dose_date and history
+
+        if !confirm_allergy_conflicts(&med, &db, options.yes) {
+            println!("Medication not added.");
+            return;
+        }
 
         db.medications.push(med.clone());
         save_database(&db);
 
         let history_count = med.history.len();
-        println!("Unarchived medication: {}", name);
+        println!("Unarchived medication: {} (#{})", name, med.id);
         if history_count > 0 {
             println!("  Restored {} dose record(s) from archive", history_count);
-            println!("  View history with: pharm history {}", name);
+            println!("  View history with: pharm history {}", med.id);
         }
+        crate::interactions::warn_interactions(med.id, &db.medications);
     } else {
         // Create fresh medication
+        assign_missing_ids(&mut db);
+        let id = db.next_id;
+        db.next_id += 1;
+
         let med = Medication {
+            id,
             name: name.clone(),
             dose,
+            dose_quantity: Some(dose_quantity),
             time_of_day: time,
             medication_frequency: interval,
             taken: false,
             taken_at: String::new(),
             last_dose_date: String::new(),
             notes,
+            limits: options.limits,
+            ingredients: options.ingredients,
             history: Vec::new(),
+            // Stock is tracked separately with `pharm stock`
+            ..Default::default()
         };
 
+        if !confirm_allergy_conflicts(&med, &db, options.yes) {
+            println!("Medication not added.");
+            return;
+        }
+
         db.medications.push(med);
         save_database(&db);
-        println!("Added medication: {}", name);
+        println!("Added medication: {} (#{})", name, id);
+        crate::interactions::warn_interactions(id, &db.medications);
     }
 }
 
 /// Removes a medication from active list and archives it with full history.
 ///
-/// The medication is moved to the archive, preserving all dose records. It can
-/// be unarchived later by using `add_medication` with the same name.
+/// The medication is moved to the archive, preserving all dose records and its
+/// ID. It can be unarchived later by using `add_medication` with the same name.
 ///
 /// # Safety
 /// This function does NOT permanently delete medication data. All history is
 /// preserved in the archive for medical compliance tracking.
 pub fn remove_medication(name: String) {
     let mut db = load_database();
-    let name_lower = name.to_lowercase();
 
-    // Find and remove from active medications
-    let mut found_med: Option<Medication> = None;
-    db.medications.retain(|m| {
-        if m.name.to_lowercase() == name_lower {
-            found_med = Some(m.clone());
-            false // Remove from active list
-        } else {
-            true // Keep in active list
+    let index = match find_medication(&db.medications, &name) {
+        Ok(index) => index,
+        Err(LookupError::Ambiguous(candidates)) => {
+            report_ambiguous(&name, &candidates);
+            return;
         }
-    });
+        Err(LookupError::NotFound) => {
+            println!("Medication '{}' not found!", name);
+            return;
+        }
+    };
 
-    if let Some(med) = found_med {
-        // Archive the medication with ALL its history
-        db.archived_medications.push(med.clone());
-        save_database(&db);
+    // Archive the medication with ALL its history
+    let med = db.medications.remove(index);
+    db.archived_medications.push(med.clone());
+    save_database(&db);
 
-        let history_count = med.history.len();
-        println!("Archived medication: {}", name);
-        if history_count > 0 {
-            println!("  Preserved {} dose record(s) in archive", history_count);
-            println!(
-                "  View history anytime with: pharm history {} --archived",
-                name
+    let history_count = med.history.len();
+    println!("Archived medication: {} (#{})", med.name, med.id);
+    if history_count > 0 {
+        println!("  Preserved {} dose record(s) in archive", history_count);
+        println!(
+            "  View history anytime with: pharm history {} --archived",
+            med.id
+        );
+    }
+}
+
+/// Renames a medication (active or archived), keeping its ID and history.
+pub fn rename_medication(name: String, new_name: String, allow_duplicate: bool) {
+    if new_name.trim().is_empty() {
+        eprintln!("Error: Medication name cannot be empty!");
+        return;
+    }
+
+    let mut db = load_database();
+
+    let (list, index) = match find_medication(&db.medications, &name) {
+        Ok(index) => (&mut db.medications, index),
+        Err(LookupError::Ambiguous(candidates)) => {
+            report_ambiguous(&name, &candidates);
+            return;
+        }
+        Err(LookupError::NotFound) => match find_medication(&db.archived_medications, &name) {
+            Ok(index) => (&mut db.archived_medications, index),
+            Err(LookupError::Ambiguous(candidates)) => {
+                report_ambiguous(&name, &candidates);
+                return;
+            }
+            Err(LookupError::NotFound) => {
+                eprintln!("Error: Medication '{}' not found!", name);
+                return;
+            }
+        },
+    };
+
+    let id = list[index].id;
+    let new_lower = new_name.to_lowercase();
+    let clash = list
+        .iter()
+        .find(|m| m.id != id && m.name.to_lowercase() == new_lower);
+    if let Some(other) = clash {
+        if !allow_duplicate {
+            eprintln!(
+                "Error: Another medication is already named '{}' (#{})!",
+                new_name, other.id
             );
+            eprintln!("  To use the same name anyway, add --allow-duplicate");
+            return;
         }
-    } else {
-        println!("Medication '{}' not found!", name);
     }
+
+    let old_name = std::mem::replace(&mut list[index].name, new_name.clone());
+    save_database(&db);
+    println!("Renamed '{}' to '{}' (#{})", old_name, new_name, id);
 }
 
 pub fn list_medications(archived: bool, due: bool) {
@@ -412,8 +777,10 @@ pub fn list_medications(archived: bool, due: bool) {
     }
     println!("{}", "=".repeat(60));
 
+    let config = load_config();
+    let today = chrono::Local::now().date_naive();
     for med in filtered_meds {
-        println!("\n{}", med.name);
+        println!("\n{} (#{})", med.name, med.id);
         println!("  Dose:     {}", med.dose);
         println!("  Time:     {}", med.time_of_day);
         println!("  Interval: {}", med.medication_frequency);
@@ -423,6 +790,47 @@ pub fn list_medications(archived: bool, due: bool) {
             println!("  Taken At: {}", med.taken_at);
         }
 
+        if !med.limits.is_empty() {
+            println!("  Limits:   {}", med.limits.describe());
+        }
+
+        if !med.ingredients.is_empty() {
+            println!("  Contains: {}", describe_ingredients(&med.ingredients));
+        }
+
+        if let Some(stock) = crate::stock::describe_stock(med, chrono::Local::now().naive_local()) {
+            println!("  Stock:    {}", stock);
+        }
+
+        if !med.batches.is_empty() {
+            println!("  Batches:  {}", describe_batches(&med.batches));
+        }
+
+        for line in med.prescription.describe() {
+            println!("  {}", line);
+        }
+
+        if !med.notify_via.is_empty() {
+            println!("  Notify:   {}", med.notify_via.join(", "));
+        }
+
+        if let Some(caregivers) = med.escalation.describe(config.escalation_minutes) {
+            println!("  {}", caregivers);
+        }
+
+        if med.critical {
+            println!("  Critical: reminders break through quiet hours");
+        }
+
+        if !archived {
+            for alert in expiry_alerts(med, today, config.expiry_warning_days) {
+                println!("  Warning:  {}", alert);
+            }
+            for alert in prescription_alerts(med, today, config.prescription_warning_days) {
+                println!("  Warning:  {}", alert);
+            }
+        }
+
         if let Some(notes) = &med.notes {
             println!("  Notes:    {}", notes);
         }
@@ -433,159 +841,419 @@ pub fn list_medications(archived: bool, due: bool) {
     }
     println!();
 }
-/// Marks a medication as taken and records it in history.
-///
-/// Records the current timestamp and dose amount. Updates `last_dose_date`
-/// for interval tracking. If the medication is archived, provides helpful
-/// error message about how to unarchive it.
-pub fn take_medication(name: String) {
-    let mut db = load_database();
-    let mut found = false;
-    let name_lower = name.to_lowercase();
-    let now = chrono::Local::now();
-    let now_str = now.format("%H:%M:%S - %Y/%m/%d").to_string();
-    let today = now.format("%Y-%m-%d").to_string();
-
-    for med in db.medications.iter_mut() {
-        if med.name.to_lowercase() == name_lower {
-            match med.taken {
-                true => {
-                    println!("Medication already marked as taken at {}", med.taken_at);
-                    return;
-                }
-                false => {
-                    med.taken = true;
-                    med.taken_at = now_str.clone();
-                    med.last_dose_date = today;
-
-                    // Append to history
-                    med.history.push(DoseRecord {
-                        timestamp: now_str.clone(),
-                        dose: med.dose.clone(),
-                    });
-
-                    found = true;
-                    break;
-                }
+
+/// Resolves an active medication by ID or name for a command, printing the
+/// appropriate error (ambiguous name, archived, not found) if it can't.
+pub fn find_active_or_report(db: &MedicationDatabase, name: &str) -> Option<usize> {
+    match find_medication(&db.medications, name) {
+        Ok(index) => Some(index),
+        Err(LookupError::Ambiguous(candidates)) => {
+            report_ambiguous(name, &candidates);
+            None
+        }
+        Err(LookupError::NotFound) => {
+            // Check if medication is archived
+            let is_archived =
+                find_medication(&db.archived_medications, name) != Err(LookupError::NotFound);
+
+            if is_archived {
+                eprintln!("Error: Medication '{}' is archived.", name);
+                eprintln!(
+                    "To restart taking it, use: pharm add {} --dose <DOSE> --time <TIME> --freq <FREQ>",
+                    name
+                );
+            } else {
+                eprintln!("Error: Medication '{}' not found!", name);
             }
+            None
         }
     }
+}
 
-    if found {
-        save_database(&db);
-        println!("Marked '{}' as taken at {}", name, now_str);
-    } else {
-        // Check if medication is archived
-        let is_archived = db
-            .archived_medications
+/// Records one dose: marks the medication taken, appends it to the history
+/// and takes it from the stock
+fn mark_taken(med: &mut Medication, now_str: &str, today: &str) {
+    med.taken = true;
+    med.taken_at = now_str.to_string();
+    med.last_dose_date = today.to_string();
+
+    // Append to history
+    let mut record = DoseRecord {
+        timestamp: now_str.to_string(),
+        dose: med.dose.clone(),
+        quantity: med.dose_quantity,
+        ..Default::default()
+    };
+    crate::stock::consume_dose(med, &mut record);
+    med.history.push(record);
+}
+
+/// Returns true for PRN (as-needed) medications, which may be taken repeatedly
+fn is_prn(med: &Medication) -> bool {
+    crate::interval::parse_interval_to_days(&med.medication_frequency).is_none()
+}
+
+/// Everything that speaks against taking another dose right now
+struct DoseCheck {
+    now: chrono::NaiveDateTime,
+    schedule: Option<EarlyDose>,
+    limits: Option<LimitViolation>,
+    ingredients: Vec<IngredientViolation>,
+}
+
+impl DoseCheck {
+    fn is_ok(&self) -> bool {
+        self.schedule.is_none() && self.limits.is_none() && self.ingredients.is_empty()
+    }
+
+    fn reasons(&self) -> Vec<String> {
+        let mut reasons: Vec<String> = self
+            .schedule
             .iter()
-            .any(|m| m.name.to_lowercase() == name_lower);
+            .map(|early| format!("too early: {}", early.describe(self.now)))
+            .collect();
+        reasons.extend(
+            self.limits
+                .iter()
+                .flat_map(|violation| violation.reasons.iter().cloned()),
+        );
+        reasons.extend(self.ingredients.iter().map(|v| v.describe()));
+        reasons
+    }
 
-        if is_archived {
-            eprintln!("Error: Medication '{}' is archived.", name);
+    /// Prints why a dose was refused and, if known, when the next one is allowed
+    fn report_refusal(&self, med: &Medication) {
+        eprintln!("Refusing to mark '{}' as taken:", med.name);
+        for reason in self.reasons() {
+            eprintln!("  - {}", reason);
+        }
+        if let Some(violation) = &self.limits {
             eprintln!(
-                "To restart taking it, use: pharm add {} --dose <DOSE> --time <TIME> --freq <FREQ>",
-                name
+                "  Next dose allowed at {}",
+                violation.next_allowed.format("%H:%M - %Y/%m/%d")
             );
-        } else {
-            eprintln!("Error: Medication '{}' not found!", name);
         }
     }
 }
-pub fn untake_medication(name: String) {
+
+/// Checks the medication's schedule, its own dose limits and the configured
+/// daily ingredient maximums, counting doses of every medication (archived
+/// ones too).
+fn check_dose(
+    db: &MedicationDatabase,
+    med: &Medication,
+    now: chrono::NaiveDateTime,
+    ingredient_limits: &BTreeMap<String, f64>,
+) -> DoseCheck {
+    let ingredients = if med.ingredients.is_empty() || ingredient_limits.is_empty() {
+        Vec::new()
+    } else {
+        let totals = ingredient_totals(db.medications.iter().chain(&db.archived_medications), now);
+        check_ingredient_limits(med, &totals, ingredient_limits)
+    };
+
+    DoseCheck {
+        now,
+        schedule: check_schedule(med, now),
+        limits: check_dose_limits(med, now),
+        ingredients,
+    }
+}
+
+/// Why `take_dose` didn't record a dose
+enum TakeRefused {
+    /// Already taken (at this time) and not PRN
+    AlreadyTaken(String),
+    /// The dose check failed and wasn't overridden
+    Check(DoseCheck),
+}
+
+/// Records a dose of `db.medications[index]` taken at `now`, after the
+/// interval, dose limit and ingredient checks shared by `pharm take`,
+/// `pharm take-all` and the daemon. `force` overrides the checks; the overridden check is returned so
+/// the caller can report it. Saving is left to the caller.
+fn take_dose(
+    db: &mut MedicationDatabase,
+    index: usize,
+    now: chrono::NaiveDateTime,
+    ingredient_limits: &BTreeMap<String, f64>,
+    force: bool,
+) -> Result<Option<DoseCheck>, TakeRefused> {
+    let med = &db.medications[index];
+    if med.taken && !is_prn(med) {
+        return Err(TakeRefused::AlreadyTaken(med.taken_at.clone()));
+    }
+
+    let check = check_dose(db, med, now, ingredient_limits);
+    let overridden = match (check.is_ok(), force) {
+        (true, _) => None,
+        (false, true) => Some(check),
+        (false, false) => return Err(TakeRefused::Check(check)),
+    };
+
+    mark_taken(
+        &mut db.medications[index],
+        &now.format("%H:%M:%S - %Y/%m/%d").to_string(),
+        &now.format("%Y-%m-%d").to_string(),
+    );
+    Ok(overridden)
+}
+
+/// Marks a medication as taken and records the dose in its history.
+///
+/// Scheduled medications can only be taken once per interval. PRN medications
+/// can be taken repeatedly. Doses that come before the schedule allows, or
+/// would exceed the medication's `limits` or a configured daily ingredient
+/// maximum, are refused unless `force` is set.
+pub fn take_medication(name: String, force: bool) {
     let mut db = load_database();
-    let mut found = false;
-    let name_lower = name.to_lowercase();
+    let ingredient_limits = normalize_limits(&load_config().ingredient_limits_mg);
+    let now = chrono::Local::now();
+    let now_str = now.format("%H:%M:%S - %Y/%m/%d").to_string();
 
-    for med in db.medications.iter_mut() {
-        if med.name.to_lowercase() == name_lower {
-            if !med.taken {
-                println!("Medication '{}' is not currently marked as taken", med.name);
-                return;
-            }
-            med.taken = false;
-            med.taken_at = String::new();
-            // Keep last_dose_date - it's still needed for interval tracking
+    let Some(index) = find_active_or_report(&db, &name) else {
+        return;
+    };
 
-            // Remove last history entry (undo the dose)
-            if !med.history.is_empty() {
-                med.history.pop();
+    match take_dose(&mut db, index, now.naive_local(), &ingredient_limits, force) {
+        Ok(None) => {}
+        Ok(Some(check)) => {
+            println!("Warning: dose check overridden with --force");
+            for reason in check.reasons() {
+                println!("  - {}", reason);
             }
-
-            found = true;
-            break;
+        }
+        Err(TakeRefused::AlreadyTaken(taken_at)) => {
+            println!("Medication already marked as taken at {}", taken_at);
+            return;
+        }
+        Err(TakeRefused::Check(check)) => {
+            check.report_refusal(&db.medications[index]);
+            eprintln!("  To take it anyway, add --force");
+            return;
         }
     }
 
-    if found {
-        save_database(&db);
-        println!("Unmarked '{}' as taken", name);
-    } else {
-        // Check if medication is archived
-        let is_archived = db
-            .archived_medications
-            .iter()
-            .any(|m| m.name.to_lowercase() == name_lower);
+    let med = &db.medications[index];
+    let med_name = med.name.clone();
+    let limited: Vec<String> = med
+        .ingredients
+        .iter()
+        .map(|i| i.name.clone())
+        .filter(|name| ingredient_limits.contains_key(name))
+        .collect();
 
-        if is_archived {
-            eprintln!("Error: Medication '{}' is archived.", name);
-            eprintln!(
-                "To restart taking it, use: pharm add {} --dose <DOSE> --time <TIME> --freq <FREQ>",
-                name
+    let stock = crate::stock::describe_stock(med, now.naive_local());
+
+    save_database(&db);
+    println!("Marked '{}' as taken at {}", med_name, now_str);
+    if let Some(stock) = stock {
+        println!("  Stock: {}", stock);
+    }
+
+    // Show how much of each limited ingredient has been used up today
+    if !limited.is_empty() {
+        let totals = ingredient_totals(
+            db.medications.iter().chain(&db.archived_medications),
+            now.naive_local(),
+        );
+        for name in limited {
+            println!(
+                "  {}: {} of {} in the last 24 hours",
+                name,
+                format_mg(totals.get(&name).copied().unwrap_or(0.0)),
+                format_mg(ingredient_limits[&name])
             );
-        } else {
-            eprintln!("Error: Medication '{}' not found!", name);
         }
     }
 }
 
-pub fn take_all_medications() {
-    let mut meds = load_medications();
+/// Marks the active medication with this ID as taken, for requests that
+/// arrive through the daemon (e.g. a "Taken" button on a phone notification).
+///
+/// Applies the same checks as `pharm take` without `--force`, and returns the
+/// message to show or the reason the dose was refused.
+pub fn take_dose_by_id(id: u32) -> Result<String, String> {
+    let mut db = load_database();
+    let ingredient_limits = normalize_limits(&load_config().ingredient_limits_mg);
     let now = chrono::Local::now();
     let now_str = now.format("%H:%M:%S - %Y/%m/%d").to_string();
-    let today = now.format("%Y-%m-%d").to_string();
 
-    if meds.is_empty() {
+    let Some(index) = db.medications.iter().position(|med| med.id == id) else {
+        return Err(format!("No active medication with ID {}", id));
+    };
+    let name = db.medications[index].name.clone();
+
+    match take_dose(&mut db, index, now.naive_local(), &ingredient_limits, false) {
+        Ok(_) => {}
+        Err(TakeRefused::AlreadyTaken(taken_at)) => {
+            return Err(format!(
+                "'{}' was already marked as taken at {}",
+                name, taken_at
+            ));
+        }
+        Err(TakeRefused::Check(check)) => {
+            return Err(format!(
+                "Refusing to mark '{}' as taken: {}",
+                name,
+                check.reasons().join("; ")
+            ));
+        }
+    }
+    let message = format!("Marked '{}' as taken at {}", name, now_str);
+
+    crate::audit::with_command("take", || save_database(&db));
+    Ok(message)
+}
+
+/// Records doses the daemon found were missed; returns how many were new
+pub fn record_missed_doses(doses: &[crate::catchup::MissedDose]) -> usize {
+    let mut db = load_database();
+    let mut recorded = 0;
+
+    for dose in doses {
+        let Some(med) = db.medications.iter_mut().find(|med| med.id == dose.med_id) else {
+            continue;
+        };
+        let timestamp = dose.at.format("%H:%M:%S - %Y/%m/%d").to_string();
+        if !med.missed.contains(&timestamp) {
+            med.missed.push(timestamp);
+            recorded += 1;
+        }
+    }
+
+    if recorded > 0 {
+        crate::audit::with_command("missed", || save_database(&db));
+    }
+    recorded
+}
+
+pub fn untake_medication(name: String) {
+    let mut db = load_database();
+
+    let Some(index) = find_active_or_report(&db, &name) else {
+        return;
+    };
+    let med = &mut db.medications[index];
+
+    if !med.taken {
+        println!("Medication '{}' is not currently marked as taken", med.name);
+        return;
+    }
+    med.taken = false;
+    med.taken_at = String::new();
+    // Keep last_dose_date - it's still needed for interval tracking
+
+    // Remove last history entry (undo the dose) and put it back in stock
+    if let Some(record) = med.history.pop() {
+        crate::stock::restore_dose(med, &record);
+    }
+
+    let med_name = med.name.clone();
+    save_database(&db);
+    println!("Unmarked '{}' as taken", med_name);
+}
+
+/// Marks every active medication as taken.
+///
+/// Medications whose dose limits or ingredient maximums would be exceeded are
+/// skipped unless `force` is set. Doses taken earlier in the same run count
+/// towards the ingredient totals of later ones.
+pub fn take_all_medications(force: bool) {
+    let mut db = load_database();
+    let ingredient_limits = normalize_limits(&load_config().ingredient_limits_mg);
+    let now = chrono::Local::now();
+    let now_str = now.format("%H:%M:%S - %Y/%m/%d").to_string();
+
+    if db.medications.is_empty() {
         println!("No medications to mark as taken.");
         return;
     }
 
-    for med in meds.iter_mut() {
-        match med.taken {
-            true => {
+    let mut skipped = 0;
+    for index in 0..db.medications.len() {
+        match take_dose(&mut db, index, now.naive_local(), &ingredient_limits, force) {
+            Ok(_) => {}
+            Err(TakeRefused::AlreadyTaken(taken_at)) => {
                 println!(
                     "Medication {} already marked as taken at {}",
-                    med.name, med.taken_at
+                    db.medications[index].name, taken_at
                 );
             }
-            false => {
-                med.taken = true;
-                med.taken_at = now_str.clone();
-                med.last_dose_date = today.clone();
-
-                // Append to history
-                med.history.push(DoseRecord {
-                    timestamp: now_str.clone(),
-                    dose: med.dose.clone(),
-                });
+            Err(TakeRefused::Check(check)) => {
+                check.report_refusal(&db.medications[index]);
+                skipped += 1;
             }
         }
     }
 
-    save_medications(&meds);
-    println!("Marked all medications as taken at {}", now_str);
+    save_database(&db);
+    if skipped > 0 {
+        println!(
+            "Marked medications as taken at {} ({} skipped as too early or over a dose limit, use --force to override)",
+            now_str, skipped
+        );
+    } else {
+        println!("Marked all medications as taken at {}", now_str);
+    }
+}
+
+/// Fields to change with `edit_medication`; `None` leaves a field untouched
+#[derive(Debug, Clone, Default)]
+pub struct MedicationEdit {
+    pub dose: Option<String>,
+    pub time: Option<String>,
+    pub freq: Option<String>,
+    /// Empty string clears the notes
+    pub notes: Option<String>,
+    /// 0 removes the limit
+    pub max_per_day: Option<u32>,
+    /// Minutes; 0 removes the limit
+    pub min_gap: Option<u32>,
+    /// Replaces the declared ingredients (empty list clears them)
+    pub ingredients: Option<Vec<Ingredient>>,
+    /// Don't ask for confirmation when new ingredients match an allergy
+    pub yes: bool,
+    /// Prescriber, pharmacy, Rx number, refills and expiry
+    pub prescription: PrescriptionEdit,
+    /// Replaces the notifiers (empty list goes back to the config's)
+    pub notify_via: Option<Vec<String>>,
+    /// Replaces the caregiver notifiers (empty list clears them)
+    pub caregivers: Option<Vec<String>>,
+    /// Minutes before caregivers are alerted; 0 goes back to the config's
+    pub escalate_after: Option<u32>,
+    /// Whether reminders break through quiet hours
+    pub critical: Option<bool>,
 }
 
-pub fn edit_medication(
-    name: String,
-    new_dose: Option<String>,
-    new_time: Option<String>,
-    new_freq: Option<String>,
-    new_notes: Option<String>,
-) {
-    let mut meds = load_medications();
-    let mut found = false;
-    let name_lower = name.to_lowercase();
+pub fn edit_medication(name: String, edit: MedicationEdit) {
+    let MedicationEdit {
+        dose: new_dose,
+        time: new_time,
+        freq: new_freq,
+        notes: new_notes,
+        max_per_day: new_max_per_day,
+        min_gap: new_min_gap,
+        ingredients: new_ingredients,
+        yes,
+        prescription,
+        notify_via: new_notify_via,
+        caregivers: new_caregivers,
+        escalate_after: new_escalate_after,
+        critical: new_critical,
+    } = edit;
+
+    if let Err(e) = prescription.validate() {
+        eprintln!("Error: {}", e);
+        return;
+    }
+
+    for names in [&new_notify_via, &new_caregivers].into_iter().flatten() {
+        if let Err(e) = crate::notifier::validate_names(names) {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    }
 
     // Validate new time if provided
     if let Some(ref time) = new_time {
@@ -605,6 +1273,10 @@ pub fn edit_medication(
             eprintln!("Error: Dose cannot be empty!");
             return;
         }
+        if parse_dose(dose).is_none() {
+            report_invalid_dose(dose);
+            return;
+        }
     }
 
     // Validate new frequency if provided
@@ -615,51 +1287,122 @@ pub fn edit_medication(
         }
     }
 
-    for med in meds.iter_mut() {
-        if med.name.to_lowercase() == name_lower {
-            let mut changes = Vec::new();
+    let mut db = load_database();
+    let Some(index) = find_active_or_report(&db, &name) else {
+        return;
+    };
 
-            if let Some(dose) = new_dose {
-                med.dose = dose.clone();
-                changes.push(format!("dose -> {}", dose));
-            }
+    // New ingredients may match a recorded allergy
+    if let Some(ref ingredients) = new_ingredients {
+        let mut updated = db.medications[index].clone();
+        updated.ingredients = ingredients.clone();
+        if !confirm_allergy_conflicts(&updated, &db, yes) {
+            println!("No changes made to '{}'", updated.name);
+            return;
+        }
+    }
 
-            if let Some(time) = new_time {
-                med.time_of_day = time.clone();
-                changes.push(format!("time -> {}", time));
-            }
+    let med = &mut db.medications[index];
+    let mut changes = Vec::new();
 
-            if let Some(freq) = new_freq {
-                med.medication_frequency = freq.clone();
-                changes.push(format!("frequency -> {}", freq));
-            }
+    if let Some(dose) = new_dose {
+        med.dose_quantity = parse_dose(&dose);
+        med.dose = dose.clone();
+        changes.push(format!("dose -> {}", dose));
+    }
 
-            if let Some(notes) = new_notes {
-                if notes.is_empty() {
-                    med.notes = None;
-                    changes.push("notes -> (cleared)".to_string());
-                } else {
-                    med.notes = Some(notes.clone());
-                    changes.push(format!("notes -> {}", notes));
-                }
-            }
+    if let Some(time) = new_time {
+        med.time_of_day = time.clone();
+        changes.push(format!("time -> {}", time));
+    }
 
-            if changes.is_empty() {
-                println!("No changes specified for '{}'", med.name);
-                return;
-            }
+    if let Some(freq) = new_freq {
+        med.medication_frequency = freq.clone();
+        changes.push(format!("frequency -> {}", freq));
+    }
 
-            found = true;
-            println!("Updated '{}': {}", med.name, changes.join(", "));
-            break;
+    if let Some(notes) = new_notes {
+        if notes.is_empty() {
+            med.notes = None;
+            changes.push("notes -> (cleared)".to_string());
+        } else {
+            med.notes = Some(notes.clone());
+            changes.push(format!("notes -> {}", notes));
         }
     }
 
-    if found {
-        save_medications(&meds);
-    } else {
-        println!("Medication '{}' not found!", name);
+    // 0 removes a limit
+    if let Some(max) = new_max_per_day {
+        med.limits.max_per_day = (max > 0).then_some(max);
+        changes.push(match max {
+            0 => "max per day -> (cleared)".to_string(),
+            _ => format!("max per day -> {}", max),
+        });
+    }
+
+    if let Some(gap) = new_min_gap {
+        med.limits.min_gap_minutes = (gap > 0).then_some(gap);
+        changes.push(match gap {
+            0 => "min gap -> (cleared)".to_string(),
+            _ => format!("min gap -> {}", crate::interval::format_minutes(gap)),
+        });
+    }
+
+    changes.extend(prescription.apply(&mut med.prescription));
+
+    if let Some(ingredients) = new_ingredients {
+        changes.push(if ingredients.is_empty() {
+            "ingredients -> (cleared)".to_string()
+        } else {
+            format!("ingredients -> {}", describe_ingredients(&ingredients))
+        });
+        med.ingredients = ingredients;
+    }
+
+    if let Some(names) = new_notify_via {
+        changes.push(if names.is_empty() {
+            "notify via -> (config default)".to_string()
+        } else {
+            format!("notify via -> {}", names.join(", "))
+        });
+        med.notify_via = names;
+    }
+
+    if let Some(names) = new_caregivers {
+        changes.push(if names.is_empty() {
+            "caregivers -> (cleared)".to_string()
+        } else {
+            format!("caregivers -> {}", names.join(", "))
+        });
+        med.escalation.contacts = names;
     }
+
+    if let Some(minutes) = new_escalate_after {
+        med.escalation.after_minutes = (minutes > 0).then_some(minutes);
+        changes.push(match minutes {
+            0 => "escalate after -> (config default)".to_string(),
+            _ => format!(
+                "escalate after -> {}",
+                crate::interval::format_minutes(minutes)
+            ),
+        });
+    }
+
+    if let Some(critical) = new_critical {
+        med.critical = critical;
+        changes.push(format!(
+            "critical -> {}",
+            if critical { "yes" } else { "no" }
+        ));
+    }
+
+    if changes.is_empty() {
+        println!("No changes specified for '{}'", med.name);
+        return;
+    }
+
+    println!("Updated '{}': {}", med.name, changes.join(", "));
+    save_database(&db);
 }
 
 /// Reset medications to untaken status if their interval has passed (called at midnight by daemon)
@@ -752,10 +1495,9 @@ pub fn display_history(medication_name: Option<String>, days: Option<u32>, archi
 
     // Filter medications if name provided
     let filtered_meds: Vec<&Medication> = if let Some(ref name) = medication_name {
-        let name_lower = name.to_lowercase();
         all_meds
             .into_iter()
-            .filter(|m| m.name.to_lowercase() == name_lower)
+            .filter(|m| matches_medication(m, name))
             .collect()
     } else {
         all_meds
@@ -770,49 +1512,60 @@ pub fn display_history(medication_name: Option<String>, days: Option<u32>, archi
 
     for med in filtered_meds {
         // Check if this medication is archived
-        let is_archived = db.archived_medications.iter().any(|m| m.name == med.name);
+        let is_archived = db.archived_medications.iter().any(|m| m.id == med.id);
 
         // Filter history by date if specified
+        let in_range = |timestamp: &str| {
+            if let Some(cutoff) = cutoff_date {
+                // Parse timestamp and compare
+                if let Ok(timestamp) =
+                    chrono::NaiveDateTime::parse_from_str(timestamp, "%H:%M:%S - %Y/%m/%d")
+                {
+                    let record_datetime = chrono::Local
+                        .from_local_datetime(&timestamp)
+                        .single()
+                        .unwrap_or_else(chrono::Local::now);
+                    record_datetime >= cutoff
+                } else {
+                    true // Include if we can't parse
+                }
+            } else {
+                true // No filter
+            }
+        };
         let history: Vec<&DoseRecord> = med
             .history
             .iter()
-            .filter(|record| {
-                if let Some(cutoff) = cutoff_date {
-                    // Parse timestamp and compare
-                    if let Ok(timestamp) = chrono::NaiveDateTime::parse_from_str(
-                        &record.timestamp,
-                        "%H:%M:%S - %Y/%m/%d",
-                    ) {
-                        let record_datetime = chrono::Local
-                            .from_local_datetime(&timestamp)
-                            .single()
-                            .unwrap_or_else(chrono::Local::now);
-                        record_datetime >= cutoff
-                    } else {
-                        true // Include if we can't parse
-                    }
-                } else {
-                    true // No filter
-                }
-            })
+            .filter(|record| in_range(&record.timestamp))
+            .collect();
+        let missed: Vec<&String> = med
+            .missed
+            .iter()
+            .filter(|timestamp| in_range(timestamp))
             .collect();
 
         if history.is_empty() {
             if is_archived {
-                println!("\n{} [ARCHIVED] - No history recorded", med.name);
+                println!(
+                    "\n{} (#{}) [ARCHIVED] - No history recorded",
+                    med.name, med.id
+                );
             } else {
-                println!("\n{} - No history recorded", med.name);
+                println!("\n{} (#{}) - No history recorded", med.name, med.id);
+            }
+            if let Some(d) = days {
+                println!("  (No doses in last {} days)", d);
             }
-            if days.is_some() {
-                println!("  (No doses in last {} days)", days.unwrap());
+            for timestamp in missed.iter().rev() {
+                println!("  {} - MISSED", timestamp);
             }
             continue;
         }
 
         if is_archived {
-            println!("\n{} [ARCHIVED] - History", med.name);
+            println!("\n{} (#{}) [ARCHIVED] - History", med.name, med.id);
         } else {
-            println!("\n{} - History", med.name);
+            println!("\n{} (#{}) - History", med.name, med.id);
         }
         if let Some(d) = days {
             println!("  (Last {} days)", d);
@@ -823,6 +1576,12 @@ pub fn display_history(medication_name: Option<String>, days: Option<u32>, archi
         for record in history.iter().rev() {
             println!("  {} - {}", record.timestamp, record.dose);
         }
+        if !missed.is_empty() {
+            println!("\n  Missed (recorded by the daemon):");
+            for timestamp in missed.iter().rev() {
+                println!("  {} - MISSED", timestamp);
+            }
+        }
 
         // Calculate adherence if we have a scheduled interval (not PRN)
         match crate::interval::parse_interval_to_days(&med.medication_frequency) {
@@ -847,6 +1606,112 @@ pub fn display_history(medication_name: Option<String>, days: Option<u32>, archi
                 println!("\n  Total doses: {} (as-needed)", history.len());
             }
         }
+
+        // Only meaningful if every record has a compatible amount
+        let quantities: Option<Vec<&DoseQuantity>> =
+            history.iter().map(|r| r.quantity.as_ref()).collect();
+        if let Some(total) = quantities.and_then(sum_quantities) {
+            println!("  Total amount: {}", total);
+        }
     }
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn med(id: u32, name: &str, dose: &str) -> Medication {
+        Medication {
+            id,
+            name: name.to_string(),
+            dose: dose.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_by_id_or_name() {
+        let meds = vec![med(1, "Aspirin", "500mg"), med(4, "Metformin", "500mg")];
+        assert_eq!(find_medication(&meds, "4"), Ok(1));
+        assert_eq!(find_medication(&meds, "#4"), Ok(1));
+        assert_eq!(find_medication(&meds, "aspirin"), Ok(0));
+        assert_eq!(find_medication(&meds, " ASPIRIN "), Ok(0));
+        assert_eq!(find_medication(&meds, "7"), Err(LookupError::NotFound));
+        assert_eq!(
+            find_medication(&meds, "Tylenol"),
+            Err(LookupError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_find_ambiguous_name() {
+        let meds = vec![med(2, "Vitamin D", "1000IU"), med(3, "Vitamin D", "5000IU")];
+        assert_eq!(
+            find_medication(&meds, "vitamin d"),
+            Err(LookupError::Ambiguous(vec![
+                (2, "Vitamin D".to_string(), "1000IU".to_string()),
+                (3, "Vitamin D".to_string(), "5000IU".to_string()),
+            ]))
+        );
+        assert_eq!(find_medication(&meds, "3"), Ok(1));
+    }
+
+    #[test]
+    fn test_id_wins_over_numeric_name() {
+        let meds = vec![med(1, "2", "1 tablet"), med(2, "Aspirin", "500mg")];
+        assert_eq!(find_medication(&meds, "2"), Ok(1));
+        assert_eq!(find_medication(&meds, "#1"), Ok(0));
+    }
+
+    #[test]
+    fn test_assign_missing_ids() {
+        let mut db = MedicationDatabase {
+            medications: vec![med(0, "Aspirin", "500mg"), med(5, "Metformin", "500mg")],
+            archived_medications: vec![med(0, "Old", "1mg")],
+            next_id: 0,
+            ..Default::default()
+        };
+        assert!(assign_missing_ids(&mut db));
+        assert_eq!(db.medications[0].id, 6);
+        assert_eq!(db.medications[1].id, 5);
+        assert_eq!(db.archived_medications[0].id, 7);
+        assert_eq!(db.next_id, 8);
+
+        // Nothing left to do the second time
+        assert!(!assign_missing_ids(&mut db));
+
+        // An empty database doesn't need saving
+        let mut empty = MedicationDatabase::default();
+        assert!(!assign_missing_ids(&mut empty));
+        assert_eq!(empty.next_id, 1);
+    }
+
+    #[test]
+    fn test_fill_dose_quantities() {
+        let mut aspirin = med(1, "Aspirin", "500mg");
+        aspirin.history.push(DoseRecord {
+            timestamp: "08:00:00 - 2025/10/21".to_string(),
+            dose: "2 tabs".to_string(),
+            ..Default::default()
+        });
+        let mut db = MedicationDatabase {
+            medications: vec![aspirin, med(2, "Cream", "apply thinly")],
+            ..Default::default()
+        };
+
+        assert!(fill_dose_quantities(&mut db));
+        assert_eq!(
+            db.medications[0].dose_quantity.unwrap().to_string(),
+            "500mg"
+        );
+        assert_eq!(
+            db.medications[0].history[0].quantity.unwrap().to_string(),
+            "2 tablets"
+        );
+        // Unparseable doses keep only their text
+        assert!(db.medications[1].dose_quantity.is_none());
+
+        assert!(!fill_dose_quantities(&mut db));
+    }
+}
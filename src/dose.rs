@@ -0,0 +1,210 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Unit of a dose quantity
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DoseUnit {
+    Mg,
+    Mcg,
+    G,
+    Ml,
+    Iu,
+    Units,
+    Tablets,
+    Puffs,
+    Drops,
+}
+
+impl DoseUnit {
+    /// Parse a unit name or abbreviation ("mg", "milligrams", "tabs", "IU", ...)
+    pub fn parse(unit: &str) -> Option<DoseUnit> {
+        let unit = match unit.trim().to_lowercase().as_str() {
+            "mg" | "milligram" | "milligrams" => DoseUnit::Mg,
+            "mcg" | "ug" | "µg" | "microgram" | "micrograms" => DoseUnit::Mcg,
+            "g" | "gram" | "grams" => DoseUnit::G,
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => DoseUnit::Ml,
+            "iu" | "international unit" | "international units" => DoseUnit::Iu,
+            "u" | "unit" | "units" => DoseUnit::Units,
+            "tab" | "tabs" | "tablet" | "tablets" | "pill" | "pills" | "cap" | "caps"
+            | "capsule" | "capsules" => DoseUnit::Tablets,
+            "puff" | "puffs" => DoseUnit::Puffs,
+            "drop" | "drops" | "gtt" | "gtts" => DoseUnit::Drops,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    /// Units in the same family can be converted into each other
+    fn family_factor(self) -> (u8, f64) {
+        match self {
+            // Mass, relative to milligrams
+            DoseUnit::Mcg => (0, 0.001),
+            DoseUnit::Mg => (0, 1.0),
+            DoseUnit::G => (0, 1000.0),
+            DoseUnit::Ml => (1, 1.0),
+            DoseUnit::Iu => (2, 1.0),
+            DoseUnit::Units => (3, 1.0),
+            DoseUnit::Tablets => (4, 1.0),
+            DoseUnit::Puffs => (5, 1.0),
+            DoseUnit::Drops => (6, 1.0),
+        }
+    }
+
+    fn label(self, amount: f64) -> &'static str {
+        let singular = amount == 1.0;
+        match self {
+            DoseUnit::Mg => "mg",
+            DoseUnit::Mcg => "mcg",
+            DoseUnit::G => "g",
+            DoseUnit::Ml => "ml",
+            DoseUnit::Iu => "IU",
+            DoseUnit::Units if singular => " unit",
+            DoseUnit::Units => " units",
+            DoseUnit::Tablets if singular => " tablet",
+            DoseUnit::Tablets => " tablets",
+            DoseUnit::Puffs if singular => " puff",
+            DoseUnit::Puffs => " puffs",
+            DoseUnit::Drops if singular => " drop",
+            DoseUnit::Drops => " drops",
+        }
+    }
+}
+
+/// A dose parsed into amount and unit, e.g. "500mg" or "2 tablets"
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DoseQuantity {
+    pub amount: f64,
+    pub unit: DoseUnit,
+}
+
+impl DoseQuantity {
+    /// Converts to another unit of the same family (mg <-> g <-> mcg);
+    /// returns `None` for incompatible units such as mg -> ml
+    pub fn convert_to(&self, unit: DoseUnit) -> Option<DoseQuantity> {
+        let (from_family, from_factor) = self.unit.family_factor();
+        let (to_family, to_factor) = unit.family_factor();
+        (from_family == to_family).then(|| DoseQuantity {
+            amount: self.amount * from_factor / to_factor,
+            unit,
+        })
+    }
+
+    /// The amount in milligrams, for mass units only
+    pub fn to_mg(&self) -> Option<f64> {
+        self.convert_to(DoseUnit::Mg).map(|q| q.amount)
+    }
+}
+
+impl fmt::Display for DoseQuantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rounded = (self.amount * 1000.0).round() / 1000.0;
+        write!(f, "{}{}", rounded, self.unit.label(self.amount))
+    }
+}
+
+/// Units accepted by `parse_dose`, for error messages
+pub const SUPPORTED_UNITS: &str = "mg, mcg, g, ml, IU, units, tablets, puffs, drops";
+
+/// Parse a dose such as "500mg", "0.5 g", "1/2 tablet", "2 puffs" or "10u"
+///
+/// The amount may be a decimal or a simple fraction and must be positive.
+pub fn parse_dose(dose: &str) -> Option<DoseQuantity> {
+    let dose = dose.trim();
+    let number_len = dose
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '/'))
+        .unwrap_or(dose.len());
+
+    let amount = parse_amount(&dose[..number_len])?;
+    let unit = DoseUnit::parse(&dose[number_len..])?;
+
+    (amount.is_finite() && amount > 0.0).then_some(DoseQuantity { amount, unit })
+}
+
+fn parse_amount(amount: &str) -> Option<f64> {
+    match amount.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f64 = denominator.parse().ok()?;
+            (denominator != 0.0).then_some(numerator.parse::<f64>().ok()? / denominator)
+        }
+        None => amount.parse().ok(),
+    }
+}
+
+/// Adds up quantities, converting to the unit of the first one.
+///
+/// Returns `None` if the list is empty or mixes incompatible units.
+pub fn sum_quantities<'a>(
+    quantities: impl IntoIterator<Item = &'a DoseQuantity>,
+) -> Option<DoseQuantity> {
+    let mut quantities = quantities.into_iter();
+    let mut total = *quantities.next()?;
+    for quantity in quantities {
+        total.amount += quantity.convert_to(total.unit)?.amount;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(amount: f64, unit: DoseUnit) -> DoseQuantity {
+        DoseQuantity { amount, unit }
+    }
+
+    #[test]
+    fn test_parse_dose() {
+        assert_eq!(parse_dose("500mg"), Some(q(500.0, DoseUnit::Mg)));
+        assert_eq!(parse_dose("500 MG"), Some(q(500.0, DoseUnit::Mg)));
+        assert_eq!(parse_dose("0.5g"), Some(q(0.5, DoseUnit::G)));
+        assert_eq!(parse_dose("200mcg"), Some(q(200.0, DoseUnit::Mcg)));
+        assert_eq!(parse_dose("10ml"), Some(q(10.0, DoseUnit::Ml)));
+        assert_eq!(parse_dose("1000 IU"), Some(q(1000.0, DoseUnit::Iu)));
+        assert_eq!(parse_dose("10u"), Some(q(10.0, DoseUnit::Units)));
+        assert_eq!(parse_dose("2 tablets"), Some(q(2.0, DoseUnit::Tablets)));
+        assert_eq!(parse_dose("1 tab"), Some(q(1.0, DoseUnit::Tablets)));
+        assert_eq!(parse_dose("1/2 tablet"), Some(q(0.5, DoseUnit::Tablets)));
+        assert_eq!(parse_dose("2 puffs"), Some(q(2.0, DoseUnit::Puffs)));
+        assert_eq!(parse_dose("3 drops"), Some(q(3.0, DoseUnit::Drops)));
+    }
+
+    #[test]
+    fn test_parse_dose_invalid() {
+        assert_eq!(parse_dose(""), None);
+        assert_eq!(parse_dose("500"), None); // unit required
+        assert_eq!(parse_dose("mg"), None);
+        assert_eq!(parse_dose("500 bananas"), None);
+        assert_eq!(parse_dose("0mg"), None);
+        assert_eq!(parse_dose("1/0 tablet"), None);
+        assert_eq!(parse_dose("500mg/5ml"), None);
+        assert_eq!(parse_dose("one tablet"), None);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(q(1.5, DoseUnit::G).to_mg(), Some(1500.0));
+        assert_eq!(q(250.0, DoseUnit::Mcg).to_mg(), Some(0.25));
+        assert_eq!(q(10.0, DoseUnit::Ml).to_mg(), None);
+        assert_eq!(q(1.0, DoseUnit::Iu).convert_to(DoseUnit::Units), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(q(500.0, DoseUnit::Mg).to_string(), "500mg");
+        assert_eq!(q(1.0, DoseUnit::Tablets).to_string(), "1 tablet");
+        assert_eq!(q(0.5, DoseUnit::Tablets).to_string(), "0.5 tablets");
+        assert_eq!(q(1000.0, DoseUnit::Iu).to_string(), "1000IU");
+    }
+
+    #[test]
+    fn test_sum_quantities() {
+        let doses = [q(500.0, DoseUnit::Mg), q(1.0, DoseUnit::G)];
+        assert_eq!(sum_quantities(&doses), Some(q(1500.0, DoseUnit::Mg)));
+
+        let mixed = [q(500.0, DoseUnit::Mg), q(5.0, DoseUnit::Ml)];
+        assert_eq!(sum_quantities(&mixed), None);
+        assert_eq!(sum_quantities(&[]), None);
+    }
+}
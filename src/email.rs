@@ -0,0 +1,259 @@
+use std::fs;
+use std::path::PathBuf;
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+use crate::notifier::{Alert, Notifier, SEND_TIMEOUT};
+
+const DEFAULT_SUBJECT: &str = "pharm: {summary} - {medication}";
+
+const DEFAULT_BODY: &str = "{body}
+
+Medication: {medication}
+Dose:       {dose}
+Scheduled:  {scheduled}
+Notes:      {notes}
+
+Sent by pharm at {time}
+";
+
+/// How the connection to the SMTP server is secured
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (port 587)
+    #[default]
+    StartTls,
+    /// TLS from the start (port 465)
+    Tls,
+    /// No encryption - only for a local relay or testing (port 25)
+    None,
+}
+
+/// An email notifier in the config, e.g.
+/// `{"type": "email", "host": "smtp.example.com", "username": "me",
+/// "password_file": "/home/me/.smtp-pass", "from": "pharm@example.com",
+/// "to": ["me@example.com"]}`
+///
+/// `subject` and `body` are templates; see `Alert::render` for the
+/// placeholders.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EmailConfig {
+    pub host: String,
+    /// Defaults to 587, 465 or 25 depending on `tls`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// File containing the password, instead of `password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl EmailConfig {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        })
+    }
+
+    fn credentials(&self) -> Result<Option<Credentials>, String> {
+        let Some(username) = &self.username else {
+            return Ok(None);
+        };
+        let password = match (&self.password, &self.password_file) {
+            (Some(password), _) => password.clone(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None) => return Err(format!("no password for SMTP user '{}'", username)),
+        };
+        Ok(Some(Credentials::new(username.clone(), password)))
+    }
+
+    /// The email for `alert`, with the subject and body templates filled in
+    pub fn message(&self, alert: &Alert) -> Result<Message, String> {
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| format!("invalid address '{}': {}", address, e))
+        };
+        if self.to.is_empty() {
+            return Err("no recipients (\"to\")".to_string());
+        }
+
+        let mut builder = Message::builder().from(mailbox(&self.from)?);
+        for address in &self.to {
+            builder = builder.to(mailbox(address)?);
+        }
+        builder
+            .subject(alert.render(self.subject.as_deref().unwrap_or(DEFAULT_SUBJECT)))
+            .header(ContentType::TEXT_PLAIN)
+            .body(alert.render(self.body.as_deref().unwrap_or(DEFAULT_BODY)))
+            .map_err(|e| e.to_string())
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, String> {
+        let builder = match self.tls {
+            SmtpTls::StartTls => SmtpTransport::starttls_relay(&self.host),
+            SmtpTls::Tls => SmtpTransport::relay(&self.host),
+            SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&self.host)),
+        }
+        .map_err(|e| format!("{}: {}", self.host, e))?;
+
+        let mut builder = builder.port(self.port()).timeout(Some(SEND_TIMEOUT));
+        if let Some(credentials) = self.credentials()? {
+            builder = builder.credentials(credentials);
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Sends alerts as plain-text email through an SMTP server
+pub struct EmailNotifier {
+    pub config: EmailConfig,
+}
+
+impl Notifier for EmailNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let message = self.config.message(alert)?;
+        self.config
+            .transport()?
+            .send(&message)
+            .map(|_| ())
+            .map_err(|e| format!("{}:{}: {}", self.config.host, self.config.port(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Medication;
+    use crate::notifier::AlertKind;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn config(port: u16) -> EmailConfig {
+        EmailConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            password_file: None,
+            from: "pharm@example.com".to_string(),
+            to: vec!["me@example.com".to_string()],
+            subject: None,
+            body: None,
+        }
+    }
+
+    fn alert() -> Alert {
+        let med = Medication {
+            id: 3,
+            name: "Metformin".to_string(),
+            dose: "500mg".to_string(),
+            notes: Some("With food".to_string()),
+            ..Default::default()
+        };
+        Alert::new(
+            AlertKind::Dose,
+            &med,
+            "MEDICATION REMINDER",
+            "Time to take: Metformin (500mg)".to_string(),
+        )
+        .scheduled_at("08:00")
+    }
+
+    #[test]
+    fn test_config_format() {
+        let config: EmailConfig = serde_json::from_str(
+            r#"{"host": "smtp.example.com", "from": "a@example.com", "to": ["b@example.com"]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.tls, SmtpTls::StartTls);
+        assert_eq!(config.port(), 587);
+
+        let config: EmailConfig = serde_json::from_str(
+            r#"{"host": "smtp.example.com", "tls": "tls", "from": "a@example.com", "to": []}"#,
+        )
+        .unwrap();
+        assert_eq!(config.port(), 465);
+        assert!(config.message(&alert()).is_err());
+    }
+
+    #[test]
+    fn test_smtp_sink() {
+        // A stand-in SMTP server that accepts one message
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+            writer.write_all(b"220 sink ESMTP\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        transcript.push(line);
+                        continue;
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250 sink\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    transcript.push(line);
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            transcript
+        });
+
+        let notifier = EmailNotifier {
+            config: config(port),
+        };
+        notifier.send(&alert()).unwrap();
+
+        let transcript = server.join().unwrap().join("\n");
+        assert!(transcript.contains("MAIL FROM:<pharm@example.com>"));
+        assert!(transcript.contains("RCPT TO:<me@example.com>"));
+        assert!(transcript.contains("Subject: pharm: MEDICATION REMINDER - Metformin"));
+        assert!(transcript.contains("Dose:       500mg"));
+        assert!(transcript.contains("Scheduled:  08:00"));
+        assert!(transcript.contains("Notes:      With food"));
+    }
+}
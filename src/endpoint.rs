@@ -0,0 +1,442 @@
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::control::{ControlRequest, ControlResponse};
+
+/// How long a request waits for the daemon loop to answer
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the link in a notification can be used to take its dose
+const DOSE_LINK_HOURS: i64 = 12;
+
+const LINK_EXPIRY_FORMAT: &str = "%Y%m%d%H%M";
+
+/// The daemon's HTTP endpoint for marking doses as taken, used by the
+/// "Taken" buttons on push notifications, e.g.
+/// `{"listen": "0.0.0.0:8765", "url": "http://laptop.lan:8765", "token": "..."}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TakeEndpoint {
+    /// Address to listen on ("0.0.0.0:8765" to accept phones on the LAN)
+    pub listen: String,
+    /// Base URL the phone uses to reach the endpoint
+    pub url: String,
+    /// Shared secret: accepted as `Authorization: Bearer <token>`, and the key
+    /// that signs the single-dose links in notifications. Never put in a URL.
+    pub token: String,
+}
+
+impl TakeEndpoint {
+    /// URL that takes a dose with a POST request and `Authorization: Bearer <token>`
+    pub fn take_url(&self, med_id: u32) -> String {
+        format!("{}/take/{}", self.url.trim_end_matches('/'), med_id)
+    }
+
+    /// Link for a notification about one dose of `med_id`, raised at `issued`.
+    /// A POST takes the dose and a GET shows a page with a button that does;
+    /// it works once, for that medication, for `DOSE_LINK_HOURS`.
+    pub fn dose_link(&self, med_id: u32, issued: NaiveDateTime) -> String {
+        format!(
+            "{}?token={}",
+            self.take_url(med_id),
+            self.dose_token(med_id, issued)
+        )
+    }
+
+    fn mac(&self, med_id: u32, day: &str, expires: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.token.as_bytes())
+            .expect("HMAC takes keys of any length");
+        mac.update(format!("take:{}:{}:{}", med_id, day, expires).as_bytes());
+        mac
+    }
+
+    /// "20251021.202510212000.<signature>": the day of the dose, when the
+    /// link expires, and an HMAC of both and the medication ID
+    fn dose_token(&self, med_id: u32, issued: NaiveDateTime) -> String {
+        let day = issued.format("%Y%m%d").to_string();
+        let expires = (issued + chrono::Duration::hours(DOSE_LINK_HOURS))
+            .format(LINK_EXPIRY_FORMAT)
+            .to_string();
+        let signature = self.mac(med_id, &day, &expires).finalize().into_bytes();
+        let hex: String = signature.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}.{}.{}", day, expires, hex)
+    }
+
+    /// Checks a dose token from a link against the medication it is used for
+    fn check_dose_token(
+        &self,
+        med_id: u32,
+        token: &str,
+        now: NaiveDateTime,
+    ) -> Result<(), (u16, &'static str)> {
+        const INVALID: (u16, &str) = (401, "Missing or wrong token");
+        let mut parts = token.split('.');
+        let (Some(day), Some(expires), Some(hex), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(INVALID);
+        };
+        let signature = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(INVALID)?;
+        self.mac(med_id, day, expires)
+            .verify_slice(&signature)
+            .map_err(|_| INVALID)?;
+
+        let expires =
+            NaiveDateTime::parse_from_str(expires, LINK_EXPIRY_FORMAT).map_err(|_| INVALID)?;
+        if now >= expires {
+            return Err((410, "This link has expired"));
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.token.is_empty() {
+            return Err("take_endpoint needs a \"token\"".to_string());
+        }
+        if !self
+            .token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err("take_endpoint token may only contain letters, digits, '-' and '_'".into());
+        }
+        Ok(())
+    }
+}
+
+/// What a request to the endpoint asks for
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// GET with a dose link: a page with a button that POSTs the link back
+    Confirm { id: u32, link_token: String },
+    /// POST: take the dose, authorized by the master token (None) or a dose
+    /// link that is used up by it
+    Take { id: u32, link_token: Option<String> },
+}
+
+/// Compares without stopping at the first difference
+fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Works out the route for a request, or the status code and message to
+/// reject it with
+fn route(
+    endpoint: &TakeEndpoint,
+    method: &Method,
+    url: &str,
+    authorization: Option<&str>,
+    now: NaiveDateTime,
+) -> Result<Route, (u16, &'static str)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let id = path
+        .strip_prefix("/take/")
+        .and_then(|id| id.parse::<u32>().ok())
+        .ok_or((404, "Not found"))?;
+
+    let bearer = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| same_secret(given, &endpoint.token));
+    let link_token = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(str::to_string);
+
+    match (method, bearer, link_token) {
+        (Method::Post, true, _) => Ok(Route::Take {
+            id,
+            link_token: None,
+        }),
+        (Method::Get | Method::Post, false, Some(link_token)) => {
+            endpoint.check_dose_token(id, &link_token, now)?;
+            Ok(match method {
+                Method::Get => Route::Confirm { id, link_token },
+                _ => Route::Take {
+                    id,
+                    link_token: Some(link_token),
+                },
+            })
+        }
+        (Method::Get | Method::Post, _, _) => Err((401, "Missing or wrong token")),
+        _ => Err((405, "Method not allowed")),
+    }
+}
+
+fn confirm_page(id: u32, link_token: &str) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html><head><meta name=\"viewport\" content=\"width=device-width\"><title>pharm</title></head>
+<body><form method=\"post\" action=\"/take/{id}?token={link_token}\">
+<button type=\"submit\" style=\"font-size: 1.5em\">Mark medication #{id} as taken</button>
+</form></body></html>
+"
+    )
+}
+
+fn respond(request: Request, status: u16, content_type: &str, body: String) {
+    let mut response = Response::from_string(body).with_status_code(status);
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response = response.with_header(header);
+    }
+    let _ = request.respond(response);
+}
+
+/// Listens for take requests; stops listening when dropped
+pub struct TakeServer {
+    server: Arc<Server>,
+}
+
+impl Drop for TakeServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+impl TakeServer {
+    /// Starts answering HTTP requests in a background thread. Doses are
+    /// taken by the daemon loop: each request is passed through `sender`
+    /// (wrapped by `wrap`) as a `ControlRequest::Take`, like a request on the
+    /// control socket.
+    pub fn start<T: Send + 'static>(
+        endpoint: &TakeEndpoint,
+        sender: Sender<T>,
+        wrap: fn(ControlRequest, Sender<ControlResponse>) -> T,
+    ) -> Result<TakeServer, String> {
+        endpoint.validate()?;
+        let server = Arc::new(
+            Server::http(&endpoint.listen).map_err(|e| format!("{}: {}", endpoint.listen, e))?,
+        );
+
+        let listener = Arc::clone(&server);
+        let endpoint = endpoint.clone();
+        std::thread::spawn(move || {
+            // Dose links that have taken their dose (until the daemon restarts;
+            // they expire after DOSE_LINK_HOURS anyway)
+            let mut used_links: HashSet<String> = HashSet::new();
+
+            for request in listener.incoming_requests() {
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Authorization"))
+                    .map(|header| header.value.as_str().to_string());
+                let route = route(
+                    &endpoint,
+                    request.method(),
+                    request.url(),
+                    authorization.as_deref(),
+                    Local::now().naive_local(),
+                );
+
+                match route {
+                    Err((status, message)) => {
+                        respond(request, status, "text/plain", message.to_string())
+                    }
+                    Ok(Route::Confirm { link_token, .. }) if used_links.contains(&link_token) => {
+                        respond(request, 410, "text/plain", "Already used".to_string())
+                    }
+                    Ok(Route::Confirm { id, link_token }) => {
+                        respond(request, 200, "text/html", confirm_page(id, &link_token))
+                    }
+                    Ok(Route::Take {
+                        link_token: Some(link_token),
+                        ..
+                    }) if used_links.contains(&link_token) => {
+                        respond(request, 410, "text/plain", "Already used".to_string())
+                    }
+                    Ok(Route::Take { id, link_token }) => {
+                        let (reply, answer) = std::sync::mpsc::channel();
+                        let response = match sender.send(wrap(ControlRequest::Take { id }, reply)) {
+                            Ok(()) => answer.recv_timeout(REPLY_TIMEOUT).ok(),
+                            Err(_) => None,
+                        };
+                        match response {
+                            Some(ControlResponse::Ok { message }) => {
+                                used_links.extend(link_token);
+                                respond(request, 200, "text/plain", message)
+                            }
+                            Some(ControlResponse::Error { message }) => {
+                                respond(request, 409, "text/plain", message)
+                            }
+                            _ => respond(
+                                request,
+                                503,
+                                "text/plain",
+                                "The daemon did not answer".to_string(),
+                            ),
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(TakeServer { server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn endpoint(url: &str) -> TakeEndpoint {
+        TakeEndpoint {
+            listen: "127.0.0.1:0".to_string(),
+            url: url.to_string(),
+            token: "s3cret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let endpoint = endpoint("http://laptop.lan:8765");
+        let now = at("2025-10-21 08:30");
+        let route = |method, url: &str, authorization| {
+            super::route(&endpoint, &method, url, authorization, now)
+        };
+        assert_eq!(
+            route(Method::Post, "/take/3", Some("Bearer s3cret")),
+            Ok(Route::Take {
+                id: 3,
+                link_token: None
+            })
+        );
+        // The master token only works as a header
+        assert_eq!(
+            route(Method::Post, "/take/3?token=s3cret", None),
+            Err((401, "Missing or wrong token"))
+        );
+        assert_eq!(
+            route(Method::Post, "/take/abc", Some("Bearer s3cret")),
+            Err((404, "Not found"))
+        );
+        assert_eq!(
+            route(Method::Delete, "/take/3", Some("Bearer s3cret")),
+            Err((405, "Method not allowed"))
+        );
+    }
+
+    #[test]
+    fn test_dose_links() {
+        let endpoint = endpoint("http://laptop.lan:8765/");
+        let issued = at("2025-10-21 08:00");
+        let link = endpoint.dose_link(3, issued);
+        assert!(link.starts_with("http://laptop.lan:8765/take/3?token=20251021.202510212000."));
+        assert!(!link.contains("s3cret"));
+        let path = link.strip_prefix("http://laptop.lan:8765").unwrap();
+        let token = endpoint.dose_token(3, issued);
+
+        let route = |method, url: &str, now| super::route(&endpoint, &method, url, None, now);
+        assert_eq!(
+            route(Method::Get, path, at("2025-10-21 09:00")),
+            Ok(Route::Confirm {
+                id: 3,
+                link_token: token.clone()
+            })
+        );
+        assert_eq!(
+            route(Method::Post, path, at("2025-10-21 09:00")),
+            Ok(Route::Take {
+                id: 3,
+                link_token: Some(token.clone())
+            })
+        );
+
+        // Only for that medication, and not after it expires
+        let other = format!("/take/4?token={}", token);
+        assert_eq!(
+            route(Method::Post, &other, at("2025-10-21 09:00")),
+            Err((401, "Missing or wrong token"))
+        );
+        assert_eq!(
+            route(Method::Post, path, at("2025-10-21 20:00")),
+            Err((410, "This link has expired"))
+        );
+
+        // A later expiry can't be forged
+        let stretched = path.replace(".202510212000.", ".202510222000.");
+        assert_eq!(
+            route(Method::Post, &stretched, at("2025-10-21 09:00")),
+            Err((401, "Missing or wrong token"))
+        );
+    }
+
+    #[test]
+    fn test_take_server() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let endpoint = TakeEndpoint {
+            listen: format!("127.0.0.1:{}", port),
+            ..endpoint(&format!("http://127.0.0.1:{}/", port))
+        };
+        assert_eq!(
+            endpoint.take_url(4),
+            format!("http://127.0.0.1:{}/take/4", port)
+        );
+
+        // Stand-in for the daemon loop
+        let (sender, requests) = std::sync::mpsc::channel();
+        let _server =
+            TakeServer::start(&endpoint, sender, |request, reply| (request, reply)).unwrap();
+        std::thread::spawn(move || {
+            for (request, reply) in requests {
+                let _ = reply.send(match request {
+                    ControlRequest::Take { id: 4 } => ControlResponse::Ok {
+                        message: "Marked 'Aspirin' as taken".to_string(),
+                    },
+                    _ => ControlResponse::Error {
+                        message: "No active medication".to_string(),
+                    },
+                });
+            }
+        });
+
+        let taken = ureq::post(&endpoint.take_url(4))
+            .set("Authorization", "Bearer s3cret")
+            .call()
+            .unwrap();
+        assert_eq!(taken.into_string().unwrap(), "Marked 'Aspirin' as taken");
+
+        let refused = ureq::post(&endpoint.take_url(5))
+            .set("Authorization", "Bearer s3cret")
+            .call();
+        assert!(matches!(refused, Err(ureq::Error::Status(409, _))));
+
+        let link = endpoint.dose_link(4, Local::now().naive_local());
+        let page = ureq::get(&link).call().unwrap().into_string().unwrap();
+        assert!(page.contains("action=\"/take/4?token="));
+        assert!(!page.contains("s3cret"));
+
+        // A dose link works once
+        assert!(ureq::post(&link).call().is_ok());
+        let reused = ureq::post(&link).call();
+        assert!(matches!(reused, Err(ureq::Error::Status(410, _))));
+
+        let unauthorized = ureq::post(&endpoint.take_url(4)).call();
+        assert!(matches!(unauthorized, Err(ureq::Error::Status(401, _))));
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::interval::format_minutes;
+
+/// Who to alert when a dose goes untaken, and after how long
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Escalation {
+    /// Notifiers (from `notifiers` in the config) that reach the caregivers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contacts: Vec<String>,
+    /// Minutes after the patient's reminder; the config's
+    /// `escalation_minutes` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_minutes: Option<u32>,
+}
+
+impl Escalation {
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty() && self.after_minutes.is_none()
+    }
+
+    pub fn delay_minutes(&self, default_minutes: u32) -> u32 {
+        self.after_minutes.unwrap_or(default_minutes)
+    }
+
+    /// True once a reminder sent at `reminded_at` has gone unanswered long
+    /// enough to alert the caregivers
+    pub fn is_due(
+        &self,
+        reminded_at: NaiveDateTime,
+        now: NaiveDateTime,
+        default_minutes: u32,
+    ) -> bool {
+        !self.contacts.is_empty()
+            && now - reminded_at >= Duration::minutes(self.delay_minutes(default_minutes) as i64)
+    }
+
+    /// When the caregivers' wait starts for a dose due at `due_at`: when the
+    /// patient was reminded, or when it came due if no reminder got through
+    pub fn waiting_since(
+        due_at: NaiveDateTime,
+        reminded_at: Option<NaiveDateTime>,
+    ) -> NaiveDateTime {
+        reminded_at.unwrap_or(due_at)
+    }
+
+    /// "Caregiver: mom-email, carer-hook after 30m", or None without contacts
+    pub fn describe(&self, default_minutes: u32) -> Option<String> {
+        if self.contacts.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Caregiver: {} after {}",
+            self.contacts.join(", "),
+            format_minutes(self.delay_minutes(default_minutes))
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_is_due() {
+        let escalation = Escalation {
+            contacts: vec!["mom".to_string()],
+            after_minutes: None,
+        };
+        let reminded = at("2025-10-21 08:00");
+        assert!(!escalation.is_due(reminded, at("2025-10-21 08:29"), 30));
+        assert!(escalation.is_due(reminded, at("2025-10-21 08:30"), 30));
+
+        let slower = Escalation {
+            after_minutes: Some(90),
+            ..escalation.clone()
+        };
+        assert!(!slower.is_due(reminded, at("2025-10-21 09:00"), 30));
+        assert!(slower.is_due(reminded, at("2025-10-21 09:30"), 30));
+
+        // Nobody to tell
+        assert!(!Escalation::default().is_due(reminded, at("2025-10-22 08:00"), 30));
+    }
+
+    #[test]
+    fn test_waiting_since() {
+        let escalation = Escalation {
+            contacts: vec!["mom".to_string()],
+            after_minutes: None,
+        };
+        let due = at("2025-10-21 08:00");
+        assert_eq!(
+            Escalation::waiting_since(due, Some(at("2025-10-21 08:10"))),
+            at("2025-10-21 08:10")
+        );
+
+        // Every notifier failed: the patient never heard, so the caregivers
+        // are still told, counting from when the dose was due
+        let since = Escalation::waiting_since(due, None);
+        assert!(!escalation.is_due(since, at("2025-10-21 08:29"), 30));
+        assert!(escalation.is_due(since, at("2025-10-21 08:30"), 30));
+    }
+
+    #[test]
+    fn test_describe() {
+        let escalation = Escalation {
+            contacts: vec!["mom".to_string(), "carer".to_string()],
+            after_minutes: Some(90),
+        };
+        assert_eq!(
+            escalation.describe(30).unwrap(),
+            "Caregiver: mom, carer after 1h30m"
+        );
+        assert_eq!(Escalation::default().describe(30), None);
+    }
+}
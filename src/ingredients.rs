@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::database::Medication;
+use crate::dose::parse_dose;
+use crate::time::parse_dose_timestamp;
+
+/// An active ingredient contained in one dose of a medication
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ingredient {
+    /// Lowercase ingredient name, e.g. "acetaminophen"
+    pub name: String,
+    /// Amount per dose, in milligrams
+    pub amount_mg: f64,
+}
+
+impl Ingredient {
+    /// Parse "acetaminophen=500mg" (or "acetaminophen:500mg")
+    ///
+    /// Supported units: "mg", "mcg"/"ug"/"µg" and "g". A bare number is
+    /// treated as milligrams.
+    pub fn parse(spec: &str) -> Result<Ingredient, String> {
+        let Some((name, amount)) = spec.split_once(['=', ':']) else {
+            return Err(format!(
+                "invalid ingredient '{}' (expected NAME=AMOUNT, e.g. \"acetaminophen=500mg\")",
+                spec
+            ));
+        };
+
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Err(format!("ingredient name missing in '{}'", spec));
+        }
+
+        let amount_mg = parse_mass_mg(amount)
+            .ok_or_else(|| format!("invalid amount '{}' for {}", amount.trim(), name))?;
+
+        Ok(Ingredient { name, amount_mg })
+    }
+}
+
+/// Parse a mass such as "500mg", "0.5 g" or "200mcg" into milligrams
+pub fn parse_mass_mg(amount: &str) -> Option<f64> {
+    // A bare number is milligrams, anything else must be a mass unit
+    let mg = match amount.trim().parse::<f64>() {
+        Ok(mg) => mg,
+        Err(_) => parse_dose(amount)?.to_mg()?,
+    };
+    (mg.is_finite() && mg > 0.0).then_some(mg)
+}
+
+/// Format milligrams for display ("500mg", "1.5g", "250mcg")
+pub fn format_mg(mg: f64) -> String {
+    if mg >= 1000.0 {
+        format!("{}g", trim_number(mg / 1000.0))
+    } else if mg < 1.0 {
+        format!("{}mcg", trim_number(mg * 1000.0))
+    } else {
+        format!("{}mg", trim_number(mg))
+    }
+}
+
+fn trim_number(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    rounded.to_string()
+}
+
+/// "acetaminophen 500mg, caffeine 65mg"
+pub fn describe_ingredients(ingredients: &[Ingredient]) -> String {
+    ingredients
+        .iter()
+        .map(|i| format!("{} {}", i.name, format_mg(i.amount_mg)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Total amount of each ingredient taken in the 24 hours before `now`,
+/// summed over the history of every given medication.
+///
+/// Each dose counts the medication's currently declared ingredients.
+pub fn ingredient_totals<'a>(
+    meds: impl IntoIterator<Item = &'a Medication>,
+    now: NaiveDateTime,
+) -> BTreeMap<String, f64> {
+    let window_start = now - Duration::hours(24);
+    let mut totals = BTreeMap::new();
+
+    for med in meds {
+        if med.ingredients.is_empty() {
+            continue;
+        }
+        let doses = med
+            .history
+            .iter()
+            .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+            .filter(|taken| *taken > window_start && *taken <= now)
+            .count();
+        for ingredient in &med.ingredients {
+            *totals.entry(ingredient.name.clone()).or_insert(0.0) +=
+                ingredient.amount_mg * doses as f64;
+        }
+    }
+
+    totals
+}
+
+/// An ingredient whose daily maximum one more dose would exceed
+#[derive(Debug, Clone, PartialEq)]
+pub struct IngredientViolation {
+    pub ingredient: String,
+    /// Amount already taken in the last 24 hours (mg)
+    pub taken_mg: f64,
+    /// Amount the new dose would add (mg)
+    pub dose_mg: f64,
+    /// Configured daily maximum (mg)
+    pub max_mg: f64,
+}
+
+impl IngredientViolation {
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {} taken in the last 24 hours + {} would exceed the daily maximum of {}",
+            self.ingredient,
+            format_mg(self.taken_mg),
+            format_mg(self.dose_mg),
+            format_mg(self.max_mg)
+        )
+    }
+}
+
+/// Checks one more dose of `med` against the configured daily maximums.
+///
+/// `totals` comes from `ingredient_totals` over all medications (active and
+/// archived), and `limits` maps lowercase ingredient names to milligrams.
+pub fn check_ingredient_limits(
+    med: &Medication,
+    totals: &BTreeMap<String, f64>,
+    limits: &BTreeMap<String, f64>,
+) -> Vec<IngredientViolation> {
+    med.ingredients
+        .iter()
+        .filter_map(|ingredient| {
+            let max_mg = *limits.get(&ingredient.name)?;
+            let taken_mg = totals.get(&ingredient.name).copied().unwrap_or(0.0);
+            (taken_mg + ingredient.amount_mg > max_mg).then(|| IngredientViolation {
+                ingredient: ingredient.name.clone(),
+                taken_mg,
+                dose_mg: ingredient.amount_mg,
+                max_mg,
+            })
+        })
+        .collect()
+}
+
+/// Lowercases the keys of the configured limits so lookups are case-insensitive
+pub fn normalize_limits(limits: &BTreeMap<String, f64>) -> BTreeMap<String, f64> {
+    limits
+        .iter()
+        .map(|(name, mg)| (name.trim().to_lowercase(), *mg))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DoseRecord;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn med(name: &str, ingredients: &[(&str, f64)], doses: &[&str]) -> Medication {
+        Medication {
+            name: name.to_string(),
+            medication_frequency: "prn".to_string(),
+            ingredients: ingredients
+                .iter()
+                .map(|(n, mg)| Ingredient {
+                    name: n.to_string(),
+                    amount_mg: *mg,
+                })
+                .collect(),
+            history: doses
+                .iter()
+                .map(|d| DoseRecord {
+                    timestamp: at(d).format("%H:%M:%S - %Y/%m/%d").to_string(),
+                    dose: "1 tablet".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_ingredient() {
+        let parsed = Ingredient::parse("Acetaminophen=500mg").unwrap();
+        assert_eq!(parsed.name, "acetaminophen");
+        assert_eq!(parsed.amount_mg, 500.0);
+
+        assert_eq!(Ingredient::parse("caffeine:65").unwrap().amount_mg, 65.0);
+        assert_eq!(Ingredient::parse("x=1g").unwrap().amount_mg, 1000.0);
+        assert_eq!(Ingredient::parse("x=250mcg").unwrap().amount_mg, 0.25);
+
+        assert!(Ingredient::parse("acetaminophen").is_err());
+        assert!(Ingredient::parse("=500mg").is_err());
+        assert!(Ingredient::parse("x=500ml").is_err());
+        assert!(Ingredient::parse("x=0mg").is_err());
+    }
+
+    #[test]
+    fn test_format_mg() {
+        assert_eq!(format_mg(500.0), "500mg");
+        assert_eq!(format_mg(4000.0), "4g");
+        assert_eq!(format_mg(1500.0), "1.5g");
+        assert_eq!(format_mg(0.25), "250mcg");
+    }
+
+    #[test]
+    fn test_totals_across_medications() {
+        let tylenol = med(
+            "Tylenol",
+            &[("acetaminophen", 500.0)],
+            &["2025-10-21 08:00", "2025-10-21 14:00"],
+        );
+        let nyquil = med(
+            "NyQuil",
+            &[("acetaminophen", 650.0), ("doxylamine", 12.5)],
+            &["2025-10-20 06:00", "2025-10-21 22:00"],
+        );
+        let totals = ingredient_totals([&tylenol, &nyquil], at("2025-10-21 23:00"));
+
+        // The NyQuil dose from yesterday morning is outside the window
+        assert_eq!(totals["acetaminophen"], 1650.0);
+        assert_eq!(totals["doxylamine"], 12.5);
+    }
+
+    #[test]
+    fn test_check_ingredient_limits() {
+        let tylenol = med(
+            "Tylenol",
+            &[("acetaminophen", 1000.0)],
+            &["2025-10-21 06:00", "2025-10-21 12:00", "2025-10-21 18:00"],
+        );
+        let limits = normalize_limits(&BTreeMap::from([("Acetaminophen".to_string(), 4000.0)]));
+
+        let totals = ingredient_totals([&tylenol], at("2025-10-21 23:00"));
+        assert!(check_ingredient_limits(&tylenol, &totals, &limits).is_empty());
+
+        let totals = ingredient_totals([&tylenol], at("2025-10-21 23:00"));
+        let mut extra = tylenol.clone();
+        extra.ingredients[0].amount_mg = 1500.0;
+        let violations = check_ingredient_limits(&extra, &totals, &limits);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].taken_mg, 3000.0);
+        assert!(violations[0].describe().contains("daily maximum of 4g"));
+    }
+}
@@ -0,0 +1,341 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::load_config;
+use crate::database::{load_database, Medication};
+
+/// Small built-in table, always loaded (see `data/interactions.csv`)
+const BUNDLED_INTERACTIONS: &str = include_str!("../data/interactions.csv");
+
+/// How serious an interaction is, from least to most
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Minor,
+    Moderate,
+    Major,
+}
+
+impl Severity {
+    pub fn parse(severity: &str) -> Option<Severity> {
+        match severity.trim().to_lowercase().as_str() {
+            "minor" | "low" => Some(Severity::Minor),
+            "moderate" | "medium" => Some(Severity::Moderate),
+            "major" | "high" | "severe" => Some(Severity::Major),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Minor => "MINOR",
+            Severity::Moderate => "MODERATE",
+            Severity::Major => "MAJOR",
+        }
+    }
+}
+
+/// One known interacting pair. Drug names are matched case-insensitively
+/// against medication names and declared ingredients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub drug_a: String,
+    pub drug_b: String,
+    pub severity: Severity,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Parse an interaction table in CSV form:
+/// `drug_a,drug_b,severity,description` (description may contain commas).
+///
+/// Blank lines, `#` comments and a header line are skipped.
+pub fn parse_csv(contents: &str) -> Result<Vec<Interaction>, String> {
+    let mut interactions = Vec::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.splitn(4, ',').map(str::trim).collect();
+        if fields.len() < 3 {
+            return Err(format!(
+                "line {}: expected drug_a,drug_b,severity[,description]",
+                number + 1
+            ));
+        }
+        if fields[2].eq_ignore_ascii_case("severity") {
+            continue; // header
+        }
+
+        let severity = Severity::parse(fields[2]).ok_or_else(|| {
+            format!(
+                "line {}: unknown severity '{}' (use minor, moderate or major)",
+                number + 1,
+                fields[2]
+            )
+        })?;
+
+        interactions.push(Interaction {
+            drug_a: fields[0].to_lowercase(),
+            drug_b: fields[1].to_lowercase(),
+            severity,
+            description: fields
+                .get(3)
+                .map(|d| d.trim_matches('"').to_string())
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(interactions)
+}
+
+/// Parse an interaction table in JSON form (an array of `Interaction`)
+pub fn parse_json(contents: &str) -> Result<Vec<Interaction>, String> {
+    let mut interactions: Vec<Interaction> =
+        serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    for interaction in interactions.iter_mut() {
+        interaction.drug_a = interaction.drug_a.trim().to_lowercase();
+        interaction.drug_b = interaction.drug_b.trim().to_lowercase();
+    }
+    Ok(interactions)
+}
+
+/// Reads a user-supplied table; `.json` files are JSON, anything else CSV
+fn read_interactions_file(path: &Path) -> Result<Vec<Interaction>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        parse_json(&contents)
+    } else {
+        parse_csv(&contents)
+    }
+}
+
+/// The bundled table plus the user's `interactions_file`, if configured.
+///
+/// A broken user file is reported and skipped so the bundled checks still run.
+pub fn load_interactions() -> Vec<Interaction> {
+    let mut interactions = parse_csv(BUNDLED_INTERACTIONS).unwrap_or_default();
+
+    if let Some(path) = load_config().interactions_file {
+        match read_interactions_file(&path) {
+            Ok(extra) => interactions.extend(extra),
+            Err(e) => eprintln!(
+                "Warning: Failed to read interactions file {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    interactions
+}
+
+/// Names a medication can be recognised by: its full name, the words in
+/// its name ("Aspirin 81mg" -> "aspirin") and its declared ingredients
+pub fn drug_terms(med: &Medication) -> Vec<String> {
+    let name = med.name.to_lowercase();
+    let mut terms: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect();
+    terms.push(name);
+    terms.extend(med.ingredients.iter().map(|i| i.name.to_lowercase()));
+    terms
+}
+
+fn mentions(terms: &[String], drug: &str) -> bool {
+    terms.iter().any(|term| term == drug)
+}
+
+/// A known interaction between two of the user's medications
+#[derive(Debug, Clone)]
+pub struct InteractionMatch<'a> {
+    pub first: &'a Medication,
+    pub second: &'a Medication,
+    pub interaction: &'a Interaction,
+}
+
+/// Finds every interacting pair of `meds`, most severe first.
+pub fn find_interactions<'a>(
+    meds: &[&'a Medication],
+    table: &'a [Interaction],
+) -> Vec<InteractionMatch<'a>> {
+    let terms: Vec<Vec<String>> = meds.iter().map(|m| drug_terms(m)).collect();
+    let mut found = Vec::new();
+
+    for i in 0..meds.len() {
+        for j in (i + 1)..meds.len() {
+            for interaction in table {
+                let forward = mentions(&terms[i], &interaction.drug_a)
+                    && mentions(&terms[j], &interaction.drug_b);
+                let backward = mentions(&terms[i], &interaction.drug_b)
+                    && mentions(&terms[j], &interaction.drug_a);
+                if forward || backward {
+                    found.push(InteractionMatch {
+                        first: meds[i],
+                        second: meds[j],
+                        interaction,
+                    });
+                }
+            }
+        }
+    }
+
+    found.sort_by_key(|m| std::cmp::Reverse(m.interaction.severity));
+    found
+}
+
+fn print_match(found: &InteractionMatch) {
+    let interaction = found.interaction;
+    println!(
+        "  [{}] {} (#{}) + {} (#{}): {} + {}",
+        interaction.severity.label(),
+        found.first.name,
+        found.first.id,
+        found.second.name,
+        found.second.id,
+        interaction.drug_a,
+        interaction.drug_b
+    );
+    if !interaction.description.is_empty() {
+        println!("      {}", interaction.description);
+    }
+}
+
+/// Warns about interactions between a newly added medication and the other
+/// active medications. Called by `add_medication` after saving.
+pub fn warn_interactions(med_id: u32, active: &[Medication]) {
+    let table = load_interactions();
+    let meds: Vec<&Medication> = active.iter().collect();
+    let found: Vec<InteractionMatch> = find_interactions(&meds, &table)
+        .into_iter()
+        .filter(|m| m.first.id == med_id || m.second.id == med_id)
+        .collect();
+
+    if found.is_empty() {
+        return;
+    }
+
+    println!("\nWarning: possible interactions with your other medications:");
+    for m in &found {
+        print_match(m);
+    }
+    println!("  Check with your doctor or pharmacist. Run `pharm check` to review all.");
+}
+
+/// `pharm check`: lists known interactions among all active medications
+pub fn check_interactions(min_severity: Option<Severity>) {
+    let db = load_database();
+    let table = load_interactions();
+    let meds: Vec<&Medication> = db.medications.iter().collect();
+
+    let found: Vec<InteractionMatch> = find_interactions(&meds, &table)
+        .into_iter()
+        .filter(|m| min_severity.is_none_or(|min| m.interaction.severity >= min))
+        .collect();
+
+    if found.is_empty() {
+        println!(
+            "No known interactions among {} active medication(s).",
+            meds.len()
+        );
+        println!("(Only interactions in the local table are checked - this is not a full review.)");
+        return;
+    }
+
+    println!("\nKnown Interactions:");
+    println!("{}", "=".repeat(60));
+    for m in &found {
+        print_match(m);
+    }
+    println!(
+        "\n{} interaction(s) found. Check with your doctor or pharmacist.",
+        found.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingredients::Ingredient;
+
+    fn med(id: u32, name: &str, ingredients: &[&str]) -> Medication {
+        Medication {
+            id,
+            name: name.to_string(),
+            ingredients: ingredients
+                .iter()
+                .map(|n| Ingredient {
+                    name: n.to_string(),
+                    amount_mg: 1.0,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bundled_table_parses() {
+        let table = parse_csv(BUNDLED_INTERACTIONS).unwrap();
+        assert!(table.len() > 10);
+        assert!(table
+            .iter()
+            .all(|i| i.drug_a == i.drug_a.to_lowercase() && !i.drug_b.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "# comment\ndrug_a,drug_b,severity,description\n\
+                   Warfarin,Aspirin,high,Bleeding, possibly severe\nfoo,bar,minor\n";
+        let table = parse_csv(csv).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0].drug_a, "warfarin");
+        assert_eq!(table[0].severity, Severity::Major);
+        assert_eq!(table[0].description, "Bleeding, possibly severe");
+        assert_eq!(table[1].description, "");
+
+        assert!(parse_csv("a,b,catastrophic").is_err());
+        assert!(parse_csv("a,b").is_err());
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json = r#"[{"drug_a": "Foo", "drug_b": "bar", "severity": "moderate"}]"#;
+        let table = parse_json(json).unwrap();
+        assert_eq!(table[0].drug_a, "foo");
+        assert_eq!(table[0].severity, Severity::Moderate);
+    }
+
+    #[test]
+    fn test_find_interactions() {
+        let table = parse_csv(
+            "warfarin,aspirin,major,Bleeding\nwarfarin,acetaminophen,moderate,INR\n\
+             ibuprofen,aspirin,minor,Less effective",
+        )
+        .unwrap();
+        let coumadin = med(1, "Coumadin", &["warfarin"]);
+        let aspirin = med(2, "Aspirin 81mg", &[]);
+        let tylenol = med(3, "Tylenol", &["acetaminophen"]);
+        let vitamin = med(4, "Vitamin D", &[]);
+
+        let meds = [&tylenol, &aspirin, &vitamin, &coumadin];
+        let found = find_interactions(&meds, &table);
+        assert_eq!(found.len(), 2);
+        // Most severe first, matched by name word and by ingredient
+        assert_eq!(found[0].interaction.severity, Severity::Major);
+        assert_eq!(found[0].first.id, 2);
+        assert_eq!(found[0].second.id, 1);
+        assert_eq!(found[1].interaction.severity, Severity::Moderate);
+    }
+}
@@ -51,6 +51,105 @@ pub fn parse_interval_to_days(interval: &str) -> Option<u32> {
     Some(1)
 }
 
+/// Estimate how many doses per day a frequency means, for supply calculations
+///
+/// - "twice daily", "bid" -> 2; "3 times daily", "tid" -> 3; "qid" -> 4
+/// - "every 8 hours" -> 3
+/// - anything else -> 1 / `parse_interval_to_days` ("every 3 days" -> 0.33)
+/// - "prn", "as needed" -> None (no schedule)
+pub fn doses_per_day(interval: &str) -> Option<f64> {
+    let days = parse_interval_to_days(interval)?;
+    let lower = interval.trim().to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+
+    match lower.replace('.', "").as_str() {
+        "bid" => return Some(2.0),
+        "tid" => return Some(3.0),
+        "qid" => return Some(4.0),
+        _ => {}
+    }
+
+    // "every 8 hours"
+    if words.len() >= 3 && words[0] == "every" && words[2].starts_with("hour") {
+        if let Ok(hours) = words[1].parse::<f64>() {
+            if hours > 0.0 {
+                return Some(24.0 / hours);
+            }
+        }
+    }
+
+    // "twice daily", "3 times daily", "three times a day", "2x daily"
+    if lower.contains("day") || lower.contains("daily") {
+        let count = match words.first().copied() {
+            Some("once") => Some(1.0),
+            Some("twice") => Some(2.0),
+            Some("two") => Some(2.0),
+            Some("three") | Some("thrice") => Some(3.0),
+            Some("four") => Some(4.0),
+            Some(word) => word.trim_end_matches('x').parse::<f64>().ok(),
+            None => None,
+        };
+        if let Some(count) = count.filter(|c| *c > 0.0) {
+            return Some(count);
+        }
+    }
+
+    Some(1.0 / days as f64)
+}
+
+/// Parse a duration such as "4h", "30m", "1h30m", "90 min" or "2 hours" into minutes
+///
+/// Supported units:
+/// - "m", "min", "mins", "minute", "minutes"
+/// - "h", "hr", "hrs", "hour", "hours"
+/// - "d", "day", "days"
+/// - A bare number is treated as hours ("4" -> 240)
+pub fn parse_duration_minutes(duration: &str) -> Option<u32> {
+    let lower = duration.trim().to_lowercase();
+    if lower.is_empty() {
+        return None;
+    }
+
+    // Bare number means hours (the usual unit for dose spacing)
+    if let Ok(hours) = lower.parse::<u32>() {
+        return hours.checked_mul(60);
+    }
+
+    let mut total: u32 = 0;
+    let mut rest = lower.as_str();
+    while !rest.is_empty() {
+        rest = rest.trim_start();
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let number: u32 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let unit_len = rest.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+        let minutes_per_unit = match &rest[..unit_len] {
+            "m" | "min" | "mins" | "minute" | "minutes" => 1,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60,
+            "d" | "day" | "days" => 24 * 60,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+
+        total = total.checked_add(number.checked_mul(minutes_per_unit)?)?;
+    }
+
+    Some(total)
+}
+
+/// Format minutes as a short duration ("4h", "30m", "1h30m")
+pub fn format_minutes(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,4 +211,51 @@ mod tests {
         assert_eq!(parse_interval_to_days("4 times a day"), Some(1));
         assert_eq!(parse_interval_to_days("every 8 hours"), Some(1)); // contains "day" fallthrough
     }
+
+    #[test]
+    fn test_parse_duration_minutes() {
+        assert_eq!(parse_duration_minutes("4h"), Some(240));
+        assert_eq!(parse_duration_minutes("4 hours"), Some(240));
+        assert_eq!(parse_duration_minutes("30m"), Some(30));
+        assert_eq!(parse_duration_minutes("90 min"), Some(90));
+        assert_eq!(parse_duration_minutes("1h30m"), Some(90));
+        assert_eq!(parse_duration_minutes("1h 30m"), Some(90));
+        assert_eq!(parse_duration_minutes("1d"), Some(1440));
+        assert_eq!(parse_duration_minutes("6"), Some(360)); // bare number = hours
+        assert_eq!(parse_duration_minutes(" 4H "), Some(240));
+        assert_eq!(parse_duration_minutes("0"), Some(0));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert_eq!(parse_duration_minutes(""), None);
+        assert_eq!(parse_duration_minutes("h"), None);
+        assert_eq!(parse_duration_minutes("4 weeks"), None);
+        assert_eq!(parse_duration_minutes("four hours"), None);
+        assert_eq!(parse_duration_minutes("-4h"), None);
+        assert_eq!(parse_duration_minutes("99999999999h"), None);
+    }
+
+    #[test]
+    fn test_format_minutes() {
+        assert_eq!(format_minutes(240), "4h");
+        assert_eq!(format_minutes(30), "30m");
+        assert_eq!(format_minutes(90), "1h30m");
+        assert_eq!(format_minutes(0), "0m");
+    }
+
+    #[test]
+    fn test_doses_per_day() {
+        assert_eq!(doses_per_day("daily"), Some(1.0));
+        assert_eq!(doses_per_day("twice daily"), Some(2.0));
+        assert_eq!(doses_per_day("3 times daily"), Some(3.0));
+        assert_eq!(doses_per_day("three times a day"), Some(3.0));
+        assert_eq!(doses_per_day("2x daily"), Some(2.0));
+        assert_eq!(doses_per_day("BID"), Some(2.0));
+        assert_eq!(doses_per_day("t.i.d."), Some(3.0));
+        assert_eq!(doses_per_day("every 8 hours"), Some(3.0));
+        assert_eq!(doses_per_day("weekly"), Some(1.0 / 7.0));
+        assert_eq!(doses_per_day("every 2 days"), Some(0.5));
+        assert_eq!(doses_per_day("prn"), None);
+    }
 }
@@ -0,0 +1,730 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::allergies::Allergy;
+use crate::database::{
+    get_data_file, load_database, save_database, Medication, MedicationDatabase,
+};
+
+/// A medication as stored in the database, including which list it was in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredMedication {
+    pub archived: bool,
+    pub medication: Medication,
+}
+
+/// State of one medication before and after an operation (`None` = absent).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MedicationChange {
+    /// Medication ID (0 for operations journaled before IDs existed)
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+    pub before: Option<StoredMedication>,
+    pub after: Option<StoredMedication>,
+}
+
+/// The recorded allergies before and after an operation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AllergyChange {
+    pub before: Vec<Allergy>,
+    pub after: Vec<Allergy>,
+}
+
+/// One user command that changed the database.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Operation {
+    pub timestamp: String, // "2025-10-21 08:30:15"
+    pub command: String,   // Subcommand name, e.g. "take"
+    #[serde(default)]
+    pub arguments: String,
+    pub changes: Vec<MedicationChange>,
+    /// Set if the operation changed the allergy list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allergies: Option<AllergyChange>,
+}
+
+/// Persisted undo/redo stacks (`~/.pharm.journal.json`).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Journal {
+    #[serde(default)]
+    pub undo: Vec<Operation>,
+    #[serde(default)]
+    pub redo: Vec<Operation>,
+}
+
+/// Commands whose saves are not user operations that can be undone: automatic
+/// daemon resets and missed-dose bookkeeping, the undo machinery itself, and
+/// whole-database rewrites.
+const NOT_JOURNALED: &[&str] = &[
+    "daemon", "missed", "undo", "redo", "backup", "encrypt", "decrypt", "migrate",
+];
+
+fn is_journaled(command: &str) -> bool {
+    !NOT_JOURNALED.contains(&command)
+}
+
+/// The operation (see `audit::current_operation`) this process journaled
+/// last, so that several saves made by one command are undone together.
+static RECORDED: Mutex<Option<u64>> = Mutex::new(None);
+
+/// Returns the path to the operation journal (`~/.pharm.journal.json`).
+pub fn get_journal_file() -> PathBuf {
+    get_data_file().with_extension("journal.json")
+}
+
+/// Loads the journal; a missing or unreadable journal is an empty one.
+pub fn load_journal() -> Journal {
+    let Ok(contents) = fs::read_to_string(get_journal_file()) else {
+        return Journal::default();
+    };
+
+    let json = if crate::crypto::is_encrypted(&contents) {
+        match crate::crypto::decrypt_contents(&contents) {
+            Ok(plaintext) => plaintext,
+            Err(e) => {
+                eprintln!("Warning: Cannot read undo journal: {}", e);
+                return Journal::default();
+            }
+        }
+    } else {
+        contents
+    };
+
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: Undo journal is corrupted, starting a new one: {}",
+            e
+        );
+        Journal::default()
+    })
+}
+
+/// Saves the journal (encrypted if the database is), with 0600 permissions.
+pub fn save_journal(journal: &Journal) {
+    let file_path = get_journal_file();
+    let written = serde_json::to_string_pretty(journal)
+        .map_err(|e| e.to_string())
+        .and_then(crate::crypto::seal)
+        .and_then(|contents| fs::write(&file_path, contents).map_err(|e| e.to_string()));
+
+    if let Err(e) = written {
+        eprintln!("Warning: Failed to save undo journal: {}", e);
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        let _ = fs::set_permissions(&file_path, fs::Permissions::from_mode(0o600));
+    }
+}
+
+/// Journals the changes made by one save so they can be undone.
+///
+/// Called by `save_database` with the database as it was on disk. A new
+/// operation clears the redo stack, like in any editor.
+pub fn record(before: Option<&MedicationDatabase>, after: &MedicationDatabase) {
+    let command = crate::audit::current_command();
+    if !is_journaled(&command) {
+        return;
+    }
+
+    let empty = MedicationDatabase::default();
+    let before = before.unwrap_or(&empty);
+    let changes = diff_databases(before, after);
+    let allergies = diff_allergies(before, after);
+    if changes.is_empty() && allergies.is_none() {
+        return;
+    }
+
+    let mut journal = load_journal();
+    add_changes(
+        &mut journal,
+        &mut RECORDED.lock().unwrap(),
+        crate::audit::current_operation(),
+        Operation {
+            timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            command,
+            arguments: crate::audit::current_arguments(),
+            changes,
+            allergies,
+        },
+    );
+
+    let limit = crate::config::load_config().undo_levels;
+    if journal.undo.len() > limit {
+        let excess = journal.undo.len() - limit;
+        journal.undo.drain(..excess);
+    }
+
+    save_journal(&journal);
+}
+
+/// Adds the changes of one save to the journal: folded into the last
+/// operation if this process journaled that one during the same `operation`,
+/// as a new operation otherwise.
+fn add_changes(journal: &mut Journal, recorded: &mut Option<u64>, operation: u64, save: Operation) {
+    if !is_journaled(&save.command) {
+        return;
+    }
+    let merge = recorded.replace(operation) == Some(operation);
+    match journal.undo.last_mut() {
+        Some(last) if merge => {
+            merge_changes(&mut last.changes, save.changes);
+            match (&mut last.allergies, save.allergies) {
+                (Some(existing), Some(later)) => existing.after = later.after,
+                (existing, later) => *existing = existing.take().or(later),
+            }
+        }
+        _ => journal.undo.push(save),
+    }
+    journal.redo.clear();
+}
+
+/// Folds a later save of the same command into an operation, keeping the
+/// earliest "before" and the latest "after" of each medication.
+fn merge_changes(existing: &mut Vec<MedicationChange>, later: Vec<MedicationChange>) {
+    for change in later {
+        match existing.iter_mut().find(|c| c.id == change.id) {
+            Some(c) => c.after = change.after,
+            None => existing.push(change),
+        }
+    }
+}
+
+/// The allergy list before and after, if it changed
+fn diff_allergies(
+    before: &MedicationDatabase,
+    after: &MedicationDatabase,
+) -> Option<AllergyChange> {
+    (before.allergies != after.allergies).then(|| AllergyChange {
+        before: before.allergies.clone(),
+        after: after.allergies.clone(),
+    })
+}
+
+fn index(db: &MedicationDatabase) -> BTreeMap<u32, StoredMedication> {
+    let mut map = BTreeMap::new();
+    for med in &db.archived_medications {
+        map.insert(
+            med.id,
+            StoredMedication {
+                archived: true,
+                medication: med.clone(),
+            },
+        );
+    }
+    // Active wins if an ID is somehow in both lists
+    for med in &db.medications {
+        map.insert(
+            med.id,
+            StoredMedication {
+                archived: false,
+                medication: med.clone(),
+            },
+        );
+    }
+    map
+}
+
+fn same(a: Option<&StoredMedication>, b: Option<&StoredMedication>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            a.archived == b.archived
+                && serde_json::to_value(&a.medication).ok()
+                    == serde_json::to_value(&b.medication).ok()
+        }
+        _ => false,
+    }
+}
+
+/// Returns the before/after state of every medication that differs.
+pub fn diff_databases(
+    before: &MedicationDatabase,
+    after: &MedicationDatabase,
+) -> Vec<MedicationChange> {
+    let old = index(before);
+    let new = index(after);
+
+    let mut ids: Vec<u32> = old.keys().chain(new.keys()).copied().collect();
+    ids.sort();
+    ids.dedup();
+
+    ids.into_iter()
+        .filter(|id| !same(old.get(id), new.get(id)))
+        .map(|id| {
+            let before = old.get(&id).cloned();
+            let after = new.get(&id).cloned();
+            let name = after
+                .as_ref()
+                .or(before.as_ref())
+                .map(|s| s.medication.name.clone())
+                .unwrap_or_default();
+            MedicationChange {
+                id,
+                name,
+                before,
+                after,
+            }
+        })
+        .collect()
+}
+
+/// Puts a medication into the state `target` (removing it if `None`),
+/// keeping its position when it stays in the same list.
+fn apply_state(db: &mut MedicationDatabase, id: u32, target: Option<&StoredMedication>) {
+    let find = |list: &Vec<Medication>| list.iter().position(|m| m.id == id);
+    let active_pos = find(&db.medications);
+    let archived_pos = find(&db.archived_medications);
+
+    match target {
+        Some(stored) if !stored.archived => {
+            if let Some(i) = archived_pos {
+                db.archived_medications.remove(i);
+            }
+            match active_pos {
+                Some(i) => db.medications[i] = stored.medication.clone(),
+                None => db.medications.push(stored.medication.clone()),
+            }
+        }
+        Some(stored) => {
+            if let Some(i) = active_pos {
+                db.medications.remove(i);
+            }
+            match archived_pos {
+                Some(i) => db.archived_medications[i] = stored.medication.clone(),
+                None => db.archived_medications.push(stored.medication.clone()),
+            }
+        }
+        None => {
+            if let Some(i) = active_pos {
+                db.medications.remove(i);
+            }
+            if let Some(i) = archived_pos {
+                db.archived_medications.remove(i);
+            }
+        }
+    }
+}
+
+/// Names of the medications (and "allergies") that have changed since the
+/// operation left them (e.g. reset by the daemon, or edited later), so
+/// undoing or redoing it would overwrite those changes.
+fn drifted(db: &MedicationDatabase, op: &Operation, undo: bool) -> Vec<String> {
+    let current = index(db);
+    let mut drifted: Vec<String> = op
+        .changes
+        .iter()
+        .filter(|change| {
+            let expected = if undo { &change.after } else { &change.before };
+            !same(current.get(&change.id), expected.as_ref())
+        })
+        .map(|change| change.name.clone())
+        .collect();
+
+    if let Some(change) = &op.allergies {
+        let expected = if undo { &change.after } else { &change.before };
+        if db.allergies != *expected {
+            drifted.push("allergies".to_string());
+        }
+    }
+
+    drifted
+}
+
+/// Applies one side of an operation to the database.
+fn apply_operation(db: &mut MedicationDatabase, op: &Operation, undo: bool) {
+    for change in &op.changes {
+        let target = if undo { &change.before } else { &change.after };
+        apply_state(db, change.id, target.as_ref());
+    }
+
+    if let Some(change) = &op.allergies {
+        let target = if undo { &change.before } else { &change.after };
+        db.allergies = target.clone();
+    }
+}
+
+fn describe(op: &Operation) -> String {
+    let mut names: Vec<&str> = op.changes.iter().map(|c| c.name.as_str()).collect();
+    if let Some(change) = &op.allergies {
+        // The allergies added or removed
+        fn only_in<'a>(list: &'a [Allergy], other: &[Allergy]) -> Vec<&'a str> {
+            list.iter()
+                .filter(|a| !other.iter().any(|b| b.name == a.name))
+                .map(|a| a.name.as_str())
+                .collect()
+        }
+        let mut allergies = only_in(&change.after, &change.before);
+        allergies.extend(only_in(&change.before, &change.after));
+        if allergies.is_empty() {
+            allergies.push("allergies");
+        }
+        names.extend(allergies);
+    }
+    format!("{} {} ({})", op.command, names.join(", "), op.timestamp)
+}
+
+/// Undoes the last `steps` operations (default 1), newest first. Stops at an
+/// operation whose medications have changed since, unless `force` is set.
+pub fn undo_operations(steps: Option<usize>, force: bool) {
+    step_operations(steps.unwrap_or(1), true, force);
+}
+
+/// Redoes the last `steps` undone operations (default 1).
+pub fn redo_operations(steps: Option<usize>, force: bool) {
+    step_operations(steps.unwrap_or(1), false, force);
+}
+
+fn step_operations(steps: usize, undo: bool, force: bool) {
+    let mut journal = load_journal();
+    let available = if undo {
+        journal.undo.len()
+    } else {
+        journal.redo.len()
+    };
+
+    if available == 0 {
+        println!("Nothing to {}.", if undo { "undo" } else { "redo" });
+        return;
+    }
+    if steps > available {
+        println!(
+            "Only {} operation(s) can be {}.",
+            available,
+            if undo { "undone" } else { "redone" }
+        );
+    }
+
+    let mut db = load_database();
+    let mut applied = 0;
+    for _ in 0..steps.min(available) {
+        let op = if undo {
+            journal.undo.pop()
+        } else {
+            journal.redo.pop()
+        };
+        let Some(op) = op else { break };
+
+        if op.changes.iter().any(|c| c.id == 0) {
+            println!(
+                "Cannot {} '{}': it was recorded before medication IDs existed.",
+                if undo { "undo" } else { "redo" },
+                describe(&op)
+            );
+            break;
+        }
+
+        let drifted = drifted(&db, &op, undo);
+        if !drifted.is_empty() && !force {
+            println!(
+                "Cannot {} '{}': {} changed since, and would lose those changes.",
+                if undo { "undo" } else { "redo" },
+                describe(&op),
+                drifted
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            println!(
+                "Use 'pharm {} --force' to overwrite them anyway.",
+                if undo { "undo" } else { "redo" }
+            );
+            if undo {
+                journal.undo.push(op);
+            } else {
+                journal.redo.push(op);
+            }
+            break;
+        }
+
+        apply_operation(&mut db, &op, undo);
+        applied += 1;
+        println!(
+            "{}: {}",
+            if undo { "Undid" } else { "Redid" },
+            describe(&op)
+        );
+        for name in drifted {
+            println!("  Note: '{}' had changed since; overwritten", name);
+        }
+
+        if undo {
+            journal.redo.push(op);
+        } else {
+            journal.undo.push(op);
+        }
+    }
+
+    // Every save makes a backup; don't rotate one out for nothing
+    if applied > 0 {
+        save_database(&db);
+    }
+    save_journal(&journal);
+}
+
+/// Shows what `pharm undo` and `pharm redo` would do next.
+pub fn show_journal() {
+    let journal = load_journal();
+    if journal.undo.is_empty() && journal.redo.is_empty() {
+        println!("No operations recorded.");
+        return;
+    }
+
+    println!("\nUndo (most recent first):");
+    println!("{}", "=".repeat(60));
+    if journal.undo.is_empty() {
+        println!("  (nothing)");
+    }
+    for (i, op) in journal.undo.iter().rev().enumerate() {
+        println!("  {:>3}  {}", i + 1, describe(op));
+    }
+
+    if !journal.redo.is_empty() {
+        println!("\nRedo:");
+        println!("{}", "=".repeat(60));
+        for (i, op) in journal.redo.iter().rev().enumerate() {
+            println!("  {:>3}  {}", i + 1, describe(op));
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DoseRecord;
+
+    fn med(id: u32, name: &str, doses: usize) -> Medication {
+        Medication {
+            id,
+            name: name.to_string(),
+            dose: "500mg".to_string(),
+            time_of_day: "8:00".to_string(),
+            medication_frequency: "daily".to_string(),
+            history: (0..doses)
+                .map(|i| DoseRecord {
+                    timestamp: format!("08:00:0{} - 2025/10/21", i),
+                    dose: "500mg".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn db(active: Vec<Medication>, archived: Vec<Medication>) -> MedicationDatabase {
+        MedicationDatabase {
+            medications: active,
+            archived_medications: archived,
+            ..Default::default()
+        }
+    }
+
+    fn op(before: &MedicationDatabase, after: &MedicationDatabase) -> Operation {
+        Operation {
+            timestamp: "2025-10-21 08:00:00".to_string(),
+            command: "test".to_string(),
+            arguments: String::new(),
+            changes: diff_databases(before, after),
+            allergies: diff_allergies(before, after),
+        }
+    }
+
+    fn names(list: &[Medication]) -> Vec<&str> {
+        list.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_undo_redo_take() {
+        let before = db(vec![med(1, "Aspirin", 0), med(2, "Ibuprofen", 0)], vec![]);
+        let after = db(vec![med(1, "Aspirin", 1), med(2, "Ibuprofen", 0)], vec![]);
+        let op = op(&before, &after);
+        assert_eq!(op.changes.len(), 1);
+
+        let mut current = after.clone();
+        assert!(drifted(&current, &op, true).is_empty());
+        apply_operation(&mut current, &op, true);
+        assert_eq!(current.medications[0].history.len(), 0);
+        assert_eq!(names(&current.medications), vec!["Aspirin", "Ibuprofen"]);
+
+        assert!(drifted(&current, &op, false).is_empty());
+        apply_operation(&mut current, &op, false);
+        assert_eq!(current.medications[0].history.len(), 1);
+    }
+
+    #[test]
+    fn test_undo_archive_and_add() {
+        let before = db(vec![med(1, "Aspirin", 2)], vec![]);
+        let after = db(vec![med(3, "Metformin", 0)], vec![med(1, "Aspirin", 2)]);
+        let op = op(&before, &after);
+
+        let mut current = after.clone();
+        apply_operation(&mut current, &op, true);
+        assert_eq!(names(&current.medications), vec!["Aspirin"]);
+        assert!(current.archived_medications.is_empty());
+        assert_eq!(current.medications[0].history.len(), 2);
+    }
+
+    #[test]
+    fn test_drift_is_reported() {
+        let before = db(vec![med(1, "Aspirin", 0)], vec![]);
+        let after = db(vec![med(1, "Aspirin", 1)], vec![]);
+        let op = op(&before, &after);
+
+        // Something else changed Aspirin after the operation
+        let mut current = db(vec![med(1, "Aspirin", 2)], vec![]);
+        assert_eq!(drifted(&current, &op, true), vec!["Aspirin"]);
+
+        // Overwritten only when forced
+        apply_operation(&mut current, &op, true);
+        assert_eq!(current.medications[0].history.len(), 0);
+    }
+
+    #[test]
+    fn test_daemon_takes_are_separate_operations() {
+        let untaken = db(vec![med(1, "Aspirin", 0), med(2, "Ibuprofen", 0)], vec![]);
+        let taken = db(vec![med(1, "Aspirin", 1), med(2, "Ibuprofen", 0)], vec![]);
+        let edited = db(
+            vec![med(1, "Aspirin", 1), med(2, "Ibuprofen Extra", 0)],
+            vec![],
+        );
+        let taken_again = db(
+            vec![med(1, "Aspirin", 2), med(2, "Ibuprofen Extra", 0)],
+            vec![],
+        );
+
+        // The daemon takes a dose, then `pharm edit` runs in another process,
+        // then the daemon takes another dose
+        let mut journal = Journal::default();
+        let mut recorded = None;
+        add_changes(&mut journal, &mut recorded, 1, op(&untaken, &taken));
+        journal.undo.push(op(&taken, &edited));
+        add_changes(&mut journal, &mut recorded, 2, op(&edited, &taken_again));
+
+        assert_eq!(journal.undo.len(), 3);
+        let edit = &journal.undo[1];
+        assert_eq!(edit.changes.len(), 1);
+        assert_eq!(edit.changes[0].name, "Ibuprofen Extra");
+
+        // Undoing the last take leaves the edit alone
+        let mut current = taken_again.clone();
+        apply_operation(&mut current, &journal.undo[2], true);
+        assert_eq!(current.medications[0].history.len(), 1);
+        assert_eq!(current.medications[1].name, "Ibuprofen Extra");
+
+        // Two saves by the same command are still one operation
+        add_changes(&mut journal, &mut recorded, 2, op(&taken_again, &edited));
+        assert_eq!(journal.undo.len(), 3);
+    }
+
+    #[test]
+    fn test_undo_allergy() {
+        let penicillin = Allergy {
+            name: "penicillin".to_string(),
+            reaction: Some("hives".to_string()),
+        };
+        let before = db(vec![med(1, "Aspirin", 0)], vec![]);
+        let after = MedicationDatabase {
+            allergies: vec![penicillin.clone()],
+            ..before.clone()
+        };
+        let taken = MedicationDatabase {
+            medications: vec![med(1, "Aspirin", 1)],
+            ..after.clone()
+        };
+
+        // `pharm allergy add`, then `pharm take`
+        let mut journal = Journal::default();
+        let mut recorded = None;
+        add_changes(&mut journal, &mut recorded, 1, op(&before, &after));
+        add_changes(&mut journal, &mut recorded, 2, op(&after, &taken));
+        assert_eq!(journal.undo.len(), 2);
+        assert!(journal.undo[0].changes.is_empty());
+        assert!(describe(&journal.undo[0]).starts_with("test penicillin"));
+
+        // Undoing the take keeps the allergy, undoing the allergy removes it
+        let mut current = taken.clone();
+        assert!(drifted(&current, &journal.undo[1], true).is_empty());
+        apply_operation(&mut current, &journal.undo[1], true);
+        assert_eq!(current.allergies, vec![penicillin.clone()]);
+        assert_eq!(current.medications[0].history.len(), 0);
+        assert!(drifted(&current, &journal.undo[0], true).is_empty());
+        apply_operation(&mut current, &journal.undo[0], true);
+        assert!(current.allergies.is_empty());
+
+        // Recorded again by hand in the meantime
+        assert!(drifted(&current, &journal.undo[0], false).is_empty());
+        let mut changed = current.clone();
+        changed.allergies.push(penicillin.clone());
+        assert_eq!(
+            drifted(&changed, &journal.undo[0], false),
+            vec!["allergies"]
+        );
+
+        apply_operation(&mut current, &journal.undo[0], false);
+        assert_eq!(current.allergies, vec![penicillin]);
+    }
+
+    #[test]
+    fn test_missed_doses_are_not_journaled() {
+        let before = db(vec![med(1, "Aspirin", 0)], vec![]);
+        let taken = db(vec![med(1, "Aspirin", 1)], vec![]);
+        let mut missed = taken.clone();
+        missed.medications[0]
+            .missed
+            .push("08:00:00 - 2025/10/20".to_string());
+
+        let mut journal = Journal::default();
+        let mut recorded = None;
+        add_changes(&mut journal, &mut recorded, 1, op(&before, &taken));
+        journal.redo.push(op(&taken, &before));
+
+        // The daemon records a dose missed while it was away
+        let save = Operation {
+            command: "missed".to_string(),
+            ..op(&taken, &missed)
+        };
+        add_changes(&mut journal, &mut recorded, 2, save);
+        assert_eq!(journal.undo.len(), 1);
+        assert_eq!(
+            journal.undo[0].changes[0]
+                .after
+                .as_ref()
+                .unwrap()
+                .medication
+                .missed
+                .len(),
+            0
+        );
+        assert_eq!(journal.redo.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_keeps_first_before() {
+        let first = db(vec![med(1, "Aspirin", 0)], vec![]);
+        let second = db(vec![med(1, "Aspirin", 1)], vec![]);
+        let third = db(vec![med(1, "Aspirin", 2)], vec![]);
+
+        let mut changes = diff_databases(&first, &second);
+        merge_changes(&mut changes, diff_databases(&second, &third));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].before.as_ref().unwrap().medication.history.len(),
+            0
+        );
+        assert_eq!(
+            changes[0].after.as_ref().unwrap().medication.history.len(),
+            2
+        );
+    }
+}
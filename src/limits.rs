@@ -0,0 +1,315 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::database::Medication;
+use crate::interval::{doses_per_day, format_minutes, parse_interval_to_days};
+use crate::time::parse_dose_timestamp;
+
+/// Per-medication dosing limits, mostly useful for PRN (as-needed) medications.
+///
+/// Both limits are checked against the timestamps in the medication's history,
+/// so they keep working across days and regardless of the `taken` flag.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DoseLimits {
+    /// Maximum number of doses in any rolling 24 hour window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_day: Option<u32>,
+    /// Minimum time between two doses, in minutes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_gap_minutes: Option<u32>,
+}
+
+impl DoseLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_per_day.is_none() && self.min_gap_minutes.is_none()
+    }
+
+    /// Human readable summary, e.g. "max 4/day, at least 4h apart"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(max) = self.max_per_day {
+            parts.push(format!("max {}/day", max));
+        }
+        if let Some(gap) = self.min_gap_minutes {
+            parts.push(format!("at least {} apart", format_minutes(gap)));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Why a dose would break a medication's limits, and when it becomes allowed
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitViolation {
+    pub reasons: Vec<String>,
+    pub next_allowed: NaiveDateTime,
+}
+
+/// Checks whether taking another dose of `med` at `now` would exceed its limits.
+///
+/// History entries with unparseable timestamps are ignored.
+pub fn check_dose_limits(med: &Medication, now: NaiveDateTime) -> Option<LimitViolation> {
+    if med.limits.is_empty() {
+        return None;
+    }
+
+    let mut doses: Vec<NaiveDateTime> = med
+        .history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .filter(|taken| *taken <= now)
+        .collect();
+    doses.sort();
+
+    let mut reasons = Vec::new();
+    let mut next_allowed = now;
+
+    if let Some(max) = med.limits.max_per_day {
+        let window_start = now - Duration::hours(24);
+        let recent: Vec<NaiveDateTime> = doses
+            .iter()
+            .copied()
+            .filter(|taken| *taken > window_start)
+            .collect();
+
+        if recent.len() >= max as usize {
+            reasons.push(format!(
+                "{} of {} doses already taken in the last 24 hours",
+                recent.len(),
+                max
+            ));
+            // The window must drop enough old doses to leave room for one more
+            let allowed = match max {
+                0 => NaiveDateTime::MAX,
+                _ => recent[recent.len() - max as usize] + Duration::hours(24),
+            };
+            next_allowed = next_allowed.max(allowed);
+        }
+    }
+
+    if let (Some(gap), Some(last)) = (med.limits.min_gap_minutes, doses.last()) {
+        let allowed = *last + Duration::minutes(gap as i64);
+        if allowed > now {
+            let since = (now - *last).num_minutes().max(0) as u32;
+            reasons.push(format!(
+                "last dose was {} ago (minimum gap is {})",
+                format_minutes(since),
+                format_minutes(gap)
+            ));
+            next_allowed = next_allowed.max(allowed);
+        }
+    }
+
+    if reasons.is_empty() {
+        None
+    } else {
+        Some(LimitViolation {
+            reasons,
+            next_allowed,
+        })
+    }
+}
+
+/// A scheduled dose taken before its interval allows
+#[derive(Debug, Clone, PartialEq)]
+pub struct EarlyDose {
+    pub last_taken: NaiveDateTime,
+    pub next_allowed: NaiveDateTime,
+}
+
+impl EarlyDose {
+    /// "last taken 2 days ago, next due Thursday 2025/10/23"
+    pub fn describe(&self, now: NaiveDateTime) -> String {
+        let minutes = (now - self.last_taken).num_minutes().max(0);
+        let ago = match minutes / (24 * 60) {
+            0 => format_minutes(minutes as u32),
+            1 => "1 day".to_string(),
+            days => format!("{} days", days),
+        };
+        let next = if self.next_allowed.time() == NaiveTime::MIN {
+            self.next_allowed.format("%A %Y/%m/%d").to_string()
+        } else {
+            self.next_allowed.format("%H:%M - %Y/%m/%d").to_string()
+        };
+        format!("last taken {} ago, next due {}", ago, next)
+    }
+}
+
+/// Checks whether a dose of a scheduled medication at `now` comes too soon
+/// after the last one in its history.
+///
+/// Medications taken once a day or less often are due again on the calendar
+/// day their interval ends ("every 3 days" taken Monday -> Thursday). Every
+/// scheduled dose must also be at least half an interval after the last one,
+/// so a daily dose at 23:30 can't be repeated just after midnight. PRN
+/// medications are never early; their `limits` apply instead.
+pub fn check_schedule(med: &Medication, now: NaiveDateTime) -> Option<EarlyDose> {
+    let days = parse_interval_to_days(&med.medication_frequency)?;
+    let per_day = doses_per_day(&med.medication_frequency)?;
+
+    let last_taken = med
+        .history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .filter(|taken| *taken <= now)
+        .max()?;
+
+    let interval_minutes = (24.0 * 60.0 / per_day).round() as i64;
+    let mut next_allowed = last_taken + Duration::minutes(interval_minutes / 2);
+    if per_day <= 1.0 {
+        let due_day = last_taken.date() + Duration::days(days as i64);
+        next_allowed = next_allowed.max(due_day.and_time(NaiveTime::MIN));
+    }
+
+    (now < next_allowed).then_some(EarlyDose {
+        last_taken,
+        next_allowed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DoseRecord;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn prn(limits: DoseLimits, doses: &[&str]) -> Medication {
+        Medication {
+            name: "Ibuprofen".to_string(),
+            medication_frequency: "prn".to_string(),
+            limits,
+            history: doses
+                .iter()
+                .map(|d| DoseRecord {
+                    timestamp: at(d).format("%H:%M:%S - %Y/%m/%d").to_string(),
+                    dose: "400mg".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let med = prn(DoseLimits::default(), &["2025-10-21 08:00"]);
+        assert_eq!(check_dose_limits(&med, at("2025-10-21 08:01")), None);
+    }
+
+    #[test]
+    fn test_min_gap() {
+        let limits = DoseLimits {
+            min_gap_minutes: Some(240),
+            ..Default::default()
+        };
+        let med = prn(limits, &["2025-10-21 08:00"]);
+
+        let violation = check_dose_limits(&med, at("2025-10-21 10:30")).unwrap();
+        assert_eq!(violation.next_allowed, at("2025-10-21 12:00"));
+        assert!(violation.reasons[0].contains("2h30m ago"));
+
+        assert_eq!(check_dose_limits(&med, at("2025-10-21 12:00")), None);
+    }
+
+    #[test]
+    fn test_max_per_day_rolling_window() {
+        let limits = DoseLimits {
+            max_per_day: Some(3),
+            ..Default::default()
+        };
+        let med = prn(
+            limits,
+            &[
+                "2025-10-20 20:00",
+                "2025-10-21 06:00",
+                "2025-10-21 12:00",
+                "2025-10-21 18:00",
+            ],
+        );
+
+        // 20:00 yesterday has dropped out, but the three today fill the window
+        let violation = check_dose_limits(&med, at("2025-10-21 21:00")).unwrap();
+        assert_eq!(violation.next_allowed, at("2025-10-22 06:00"));
+
+        assert_eq!(check_dose_limits(&med, at("2025-10-22 06:00")), None);
+    }
+
+    #[test]
+    fn test_both_limits_use_latest_time() {
+        let limits = DoseLimits {
+            max_per_day: Some(2),
+            min_gap_minutes: Some(6 * 60),
+        };
+        let med = prn(limits, &["2025-10-21 02:00", "2025-10-21 23:00"]);
+
+        let violation = check_dose_limits(&med, at("2025-10-22 01:00")).unwrap();
+        assert_eq!(violation.reasons.len(), 2);
+        // Gap allows 05:00, the daily maximum only 02:00 - the later one wins
+        assert_eq!(violation.next_allowed, at("2025-10-22 05:00"));
+    }
+
+    fn scheduled(freq: &str, doses: &[&str]) -> Medication {
+        Medication {
+            medication_frequency: freq.to_string(),
+            ..prn(DoseLimits::default(), doses)
+        }
+    }
+
+    #[test]
+    fn test_schedule_every_few_days() {
+        let med = scheduled("every 3 days", &["2025-10-20 08:00"]);
+
+        let early = check_schedule(&med, at("2025-10-22 09:00")).unwrap();
+        assert_eq!(early.next_allowed, at("2025-10-23 00:00"));
+        assert_eq!(
+            early.describe(at("2025-10-22 09:00")),
+            "last taken 2 days ago, next due Thursday 2025/10/23"
+        );
+
+        assert_eq!(check_schedule(&med, at("2025-10-23 07:00")), None);
+    }
+
+    #[test]
+    fn test_schedule_daily_after_midnight() {
+        let med = scheduled("daily", &["2025-10-20 23:30"]);
+
+        let early = check_schedule(&med, at("2025-10-21 00:10")).unwrap();
+        assert_eq!(early.next_allowed, at("2025-10-21 11:30"));
+        assert_eq!(
+            early.describe(at("2025-10-21 00:10")),
+            "last taken 40m ago, next due 11:30 - 2025/10/21"
+        );
+
+        // A normal morning dose after an evening one is fine
+        let med = scheduled("daily", &["2025-10-20 08:00"]);
+        assert_eq!(check_schedule(&med, at("2025-10-21 07:00")), None);
+    }
+
+    #[test]
+    fn test_schedule_several_times_a_day() {
+        let med = scheduled("every 8 hours", &["2025-10-21 06:00"]);
+        assert!(check_schedule(&med, at("2025-10-21 09:00")).is_some());
+        assert_eq!(check_schedule(&med, at("2025-10-21 10:00")), None);
+    }
+
+    #[test]
+    fn test_schedule_ignores_prn_and_new() {
+        let med = scheduled("prn", &["2025-10-21 08:00"]);
+        assert_eq!(check_schedule(&med, at("2025-10-21 08:01")), None);
+
+        let med = scheduled("weekly", &[]);
+        assert_eq!(check_schedule(&med, at("2025-10-21 08:01")), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let limits = DoseLimits {
+            max_per_day: Some(4),
+            min_gap_minutes: Some(240),
+        };
+        assert_eq!(limits.describe(), "max 4/day, at least 4h apart");
+        assert_eq!(DoseLimits::default().describe(), "");
+    }
+}
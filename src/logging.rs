@@ -0,0 +1,79 @@
+use std::sync::OnceLock;
+
+use chrono::{Local, NaiveDateTime};
+
+/// Severity of a daemon log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Level {
+    /// syslog priority, understood by journald as a `<N>` line prefix
+    fn priority(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warning => 4,
+            Level::Info => 6,
+        }
+    }
+}
+
+/// True when output goes to the systemd journal. systemd sets
+/// `JOURNAL_STREAM` for services whose stdout/stderr are connected to it.
+pub fn journal_output() -> bool {
+    static JOURNAL: OnceLock<bool> = OnceLock::new();
+    *JOURNAL.get_or_init(|| std::env::var_os("JOURNAL_STREAM").is_some())
+}
+
+/// One daemon log line: "[13:05:00] message" on a terminal, "<6>message" for
+/// the journal, which adds its own timestamps and reads the priority prefix
+pub fn format_line(level: Level, message: &str, journal: bool, now: NaiveDateTime) -> String {
+    if journal {
+        format!("<{}>{}", level.priority(), message)
+    } else {
+        format!("[{}] {}", now.format("%H:%M:%S"), message)
+    }
+}
+
+fn log(level: Level, message: &str) {
+    let line = format_line(level, message, journal_output(), Local::now().naive_local());
+    match level {
+        Level::Info => println!("{}", line),
+        Level::Warning | Level::Error => eprintln!("{}", line),
+    }
+}
+
+pub fn info(message: &str) {
+    log(Level::Info, message);
+}
+
+pub fn warn(message: &str) {
+    log(Level::Warning, message);
+}
+
+pub fn error(message: &str) {
+    log(Level::Error, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        let now =
+            NaiveDateTime::parse_from_str("2025-10-21 13:05:09", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            format_line(Level::Info, "Reminder sent", false, now),
+            "[13:05:09] Reminder sent"
+        );
+        assert_eq!(
+            format_line(Level::Info, "Reminder sent", true, now),
+            "<6>Reminder sent"
+        );
+        assert_eq!(format_line(Level::Error, "Failed", true, now), "<3>Failed");
+    }
+}
@@ -1,21 +1,66 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use allergies::{add_allergy, list_allergies, remove_allergy};
+use audit::show_log;
+use backup::{list_backups, restore_backup};
+use config::show_config;
+use control::{control_daemon, show_daemon_status, ControlRequest};
+use crypto::{decrypt_database, encrypt_database, set_key_file};
 use daemon::run_daemon;
 use database::{
     add_medication, display_history, edit_medication, list_medications, remove_medication,
-    take_all_medications, take_medication, untake_medication,
+    rename_medication, take_all_medications, take_medication, untake_medication, AddOptions,
+    MedicationEdit,
 };
+use ingredients::Ingredient;
+use interactions::{check_interactions, Severity};
+use interval::parse_duration_minutes;
+use journal::{redo_operations, show_journal, undo_operations};
+use limits::DoseLimits;
+use notifier::test_notifiers;
+use prescription::PrescriptionEdit;
+use scheduler::show_next;
+use service::{install_service, uninstall_service};
+use stock::{discard_expired, list_stock, update_stock, StockChange};
 
+pub mod allergies;
+pub mod audit;
+pub mod backup;
+pub mod batches;
+pub mod catchup;
+pub mod config;
+pub mod control;
+pub mod crypto;
 pub mod daemon;
 pub mod database;
+pub mod dose;
+pub mod email;
+pub mod endpoint;
+pub mod escalation;
+pub mod ingredients;
+pub mod interactions;
 pub mod interval;
+pub mod journal;
+pub mod limits;
+pub mod logging;
+pub mod notifier;
+pub mod prescription;
+pub mod prompt;
+pub mod push;
+pub mod quiet;
+pub mod scheduler;
+pub mod service;
+pub mod stock;
 pub mod time;
+pub mod watch;
 
 #[derive(Parser)]
 #[command(name = "pharm")]
 #[command(
     about = "CLI-first medication management tool",
-    long_about = "A simple CLI tool to help remind you to take your medication and maintain medication compliance. No data privacy is implemented. Everything is saved as JSON for easy import/export."
+    long_about = "A simple CLI tool to help remind you to take your medication and maintain medication compliance. Everything is saved as JSON for easy import/export. Use `pharm encrypt` to protect the database with a passphrase."
 )]
 #[command(version)]
 struct Cli {
@@ -42,26 +87,54 @@ enum Commands {
         /// Optional notes
         #[arg(short, long)]
         notes: Option<String>,
+        /// Allow a second active medication with the same name (e.g. two strengths)
+        #[arg(long)]
+        allow_duplicate: bool,
+        /// Maximum number of doses in any 24 hour period
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        max_per_day: Option<u32>,
+        /// Minimum time between doses (e.g. "4h", "90m", "1h30m")
+        #[arg(long, value_parser = parse_min_gap)]
+        min_gap: Option<u32>,
+        /// Active ingredient per dose, repeatable (e.g. "acetaminophen=500mg")
+        #[arg(long = "ingredient", value_name = "NAME=AMOUNT", value_parser = Ingredient::parse)]
+        ingredients: Vec<Ingredient>,
+        /// Don't ask for confirmation if the medication matches an allergy
+        #[arg(short, long)]
+        yes: bool,
     },
     /// Remove a medication
     #[command(visible_alias = "r")]
     Remove {
-        /// Name of the medication
+        /// ID or name of the medication
         name: String,
     },
     /// Mark a medication as taken
     #[command(visible_alias = "t")]
-    Take { name: String },
+    Take {
+        /// ID or name of the medication
+        name: String,
+        /// Take the dose even if it is early for its schedule or exceeds dose limits
+        #[arg(long)]
+        force: bool,
+    },
     #[command(visible_alias = "u")]
     /// Mark a medication as NOT taken (undo)
-    Untake { name: String },
+    Untake {
+        /// ID or name of the medication
+        name: String,
+    },
     /// Mark ALL medications as taken
     #[command(visible_alias = "ta")]
-    TakeAll,
+    TakeAll {
+        /// Also take medications that are early or whose dose limits would be exceeded
+        #[arg(long)]
+        force: bool,
+    },
     /// Edit an existing medication
     #[command(visible_alias = "e")]
     Edit {
-        /// Name of the medication to edit
+        /// ID or name of the medication to edit
         name: String,
         /// New dosage
         #[arg(long)]
@@ -75,6 +148,75 @@ enum Commands {
         /// New notes (use empty string to clear)
         #[arg(long)]
         notes: Option<String>,
+        /// New maximum doses per 24 hours (0 to remove the limit)
+        #[arg(long)]
+        max_per_day: Option<u32>,
+        /// New minimum time between doses (0 to remove the limit)
+        #[arg(long, value_parser = parse_min_gap)]
+        min_gap: Option<u32>,
+        /// Replace the active ingredients, repeatable (e.g. "acetaminophen=500mg")
+        #[arg(long = "ingredient", value_name = "NAME=AMOUNT", value_parser = Ingredient::parse)]
+        ingredients: Vec<Ingredient>,
+        /// Remove all active ingredients
+        #[arg(long, conflicts_with = "ingredients")]
+        clear_ingredients: bool,
+        /// Don't ask for confirmation if new ingredients match an allergy
+        #[arg(short, long)]
+        yes: bool,
+        /// Prescribing doctor (empty string to clear)
+        #[arg(long, help_heading = "Prescription")]
+        prescriber: Option<String>,
+        /// Pharmacy name (empty string to clear)
+        #[arg(long, help_heading = "Prescription")]
+        pharmacy: Option<String>,
+        /// Pharmacy phone number (empty string to clear)
+        #[arg(long, help_heading = "Prescription")]
+        pharmacy_phone: Option<String>,
+        /// Prescription (Rx) number (empty string to clear)
+        #[arg(long, help_heading = "Prescription")]
+        rx_number: Option<String>,
+        /// Refills remaining on the prescription
+        #[arg(long, help_heading = "Prescription")]
+        refills: Option<u32>,
+        /// Prescription expiry date, YYYY-MM-DD (empty string to clear)
+        #[arg(long, help_heading = "Prescription")]
+        rx_expires: Option<String>,
+        /// Send this medication's alerts through these notifiers, repeatable
+        /// (e.g. "desktop", or a name from "notifiers" in the config)
+        #[arg(long = "notify-via", value_name = "NOTIFIER")]
+        notify_via: Vec<String>,
+        /// Go back to the notifiers set in the config
+        #[arg(long, conflicts_with = "notify_via")]
+        clear_notify_via: bool,
+        /// Let reminders break through quiet hours (true/false)
+        #[arg(long, value_name = "BOOL")]
+        critical: Option<bool>,
+        /// Alert a caregiver through this notifier when a dose goes untaken,
+        /// repeatable (replaces the current caregivers)
+        #[arg(
+            long = "caregiver",
+            value_name = "NOTIFIER",
+            help_heading = "Caregiver"
+        )]
+        caregivers: Vec<String>,
+        /// Stop alerting caregivers
+        #[arg(long, conflicts_with = "caregivers", help_heading = "Caregiver")]
+        clear_caregivers: bool,
+        /// How long after the reminder to alert caregivers, e.g. "45m" or
+        /// "1h30m"; a bare number is minutes (0 for the config's
+        /// escalation_minutes)
+        #[arg(long, value_parser = parse_escalate_after, help_heading = "Caregiver")]
+        escalate_after: Option<u32>,
+    },
+    /// Rename a medication, keeping its ID and history
+    Rename {
+        /// ID or name of the medication
+        name: String,
+        /// New name
+        new_name: String,
+        /// Allow the new name even if another medication already uses it
+        #[arg(long)]
+        allow_duplicate: bool,
     },
     /// List all medications
     #[command(visible_aliases = ["l", "s", "show"])]
@@ -89,7 +231,7 @@ enum Commands {
     /// View medication history
     #[command(visible_alias = "h")]
     History {
-        /// Name of medication (optional - shows all if not specified)
+        /// ID or name of medication (optional - shows all if not specified)
         name: Option<String>,
         /// Number of days to show (default: 30)
         #[arg(short, long)]
@@ -98,13 +240,222 @@ enum Commands {
         #[arg(short, long)]
         archived: bool,
     },
+    /// Record allergies and contraindications, checked when adding medications
+    Allergy {
+        #[command(subcommand)]
+        action: AllergyCommands,
+    },
+    /// Track how many pills (or puffs, ml, ...) are on hand
+    Stock {
+        #[command(subcommand)]
+        action: Option<StockCommands>,
+    },
+    /// Check active medications for known drug interactions
+    Check {
+        /// Only show interactions of at least this severity
+        #[arg(short, long, value_enum)]
+        severity: Option<Severity>,
+    },
+    /// Send a test alert through the configured notifiers
+    NotifyTest {
+        /// Notifiers to test (default: the config's "notify_via")
+        notifiers: Vec<String>,
+    },
+    /// Show the upcoming dose reminders, soonest first
+    Next {
+        /// Only show this many reminders
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+    },
     /// Start the background daemon for reminders
     #[command(visible_alias = "d")]
-    Daemon,
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonCommands>,
+        /// Read the passphrase of an encrypted database from this file
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// List or restore automatic database backups
+    Backup {
+        #[command(subcommand)]
+        action: BackupCommands,
+    },
+    /// Show the config file location and current settings
+    Config,
+    /// Undo the last operation(s): add, remove, edit, take, take-all, ...
+    Undo {
+        /// Number of operations to undo (default: 1)
+        steps: Option<usize>,
+        /// Show what can be undone/redone instead
+        #[arg(short, long)]
+        list: bool,
+        /// Also overwrite medications that have changed since the operation
+        #[arg(long)]
+        force: bool,
+    },
+    /// Redo operation(s) reverted by undo
+    Redo {
+        /// Number of operations to redo (default: 1)
+        steps: Option<usize>,
+        /// Also overwrite medications that have changed since the operation
+        #[arg(long)]
+        force: bool,
+    },
+    /// Browse the audit log of every change to the database
+    Log {
+        /// Only show changes to this medication (ID or name)
+        name: Option<String>,
+        /// Only show changes made by this command (e.g. "edit", "take", "daemon")
+        #[arg(short, long)]
+        command: Option<String>,
+        /// Number of days to show
+        #[arg(short, long)]
+        days: Option<u32>,
+        /// Maximum number of entries to show (default: 50)
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Encrypt the medication database with a passphrase
+    Encrypt,
+    /// Convert an encrypted database back to plain JSON
+    Decrypt,
+}
+
+#[derive(Subcommand)]
+enum AllergyCommands {
+    /// Record an allergy to a drug, ingredient or drug class (e.g. "penicillin", "sulfa")
+    #[command(visible_alias = "a")]
+    Add {
+        /// Drug, ingredient or drug class
+        name: String,
+        /// What happened (e.g. "hives", "anaphylaxis")
+        #[arg(short, long)]
+        reaction: Option<String>,
+    },
+    /// Remove a recorded allergy
+    #[command(visible_alias = "r")]
+    Remove {
+        /// Name of the allergy
+        name: String,
+    },
+    /// List recorded allergies and conflicting medications
+    #[command(visible_alias = "l")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum DaemonCommands {
+    /// Show whether the daemon is running and what it is waiting for
+    Status,
+    /// Stop the running daemon
+    Stop,
+    /// Make the running daemon re-read the database
+    Reload,
+    /// Start the daemon automatically at login (systemd user service, or an
+    /// XDG autostart entry without systemd)
+    Install {
+        /// Read the passphrase of an encrypted database from this file
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Write an XDG autostart entry even if systemd is available
+        #[arg(long)]
+        autostart: bool,
+        /// Enable the service without starting it now
+        #[arg(long)]
+        no_start: bool,
+    },
+    /// Remove the systemd user service and autostart entry
+    Uninstall,
+}
+
+#[derive(Subcommand)]
+enum StockCommands {
+    /// Show remaining stock and days of supply (default)
+    #[command(visible_alias = "l")]
+    List,
+    /// Set the quantity on hand (e.g. after counting pills)
+    Set {
+        /// ID or name of the medication
+        name: String,
+        /// Units on hand
+        quantity: f64,
+        /// Units used per dose (default: from the dose, e.g. "2 tablets" -> 2, else 1)
+        #[arg(long)]
+        per_dose: Option<f64>,
+        /// Expiry date of this supply (YYYY-MM-DD); replaces any batches
+        #[arg(long)]
+        expires: Option<String>,
+        /// Lot number of this supply
+        #[arg(long)]
+        lot: Option<String>,
+    },
+    /// Add a refill to the quantity on hand
+    Add {
+        /// ID or name of the medication
+        name: String,
+        /// Units to add
+        quantity: f64,
+        /// Units used per dose (default: from the dose, e.g. "2 tablets" -> 2, else 1)
+        #[arg(long)]
+        per_dose: Option<f64>,
+        /// Expiry date of the new supply (YYYY-MM-DD), tracked as its own batch
+        #[arg(long)]
+        expires: Option<String>,
+        /// Lot number of the new supply
+        #[arg(long)]
+        lot: Option<String>,
+    },
+    /// Remove expired batches from the quantity on hand
+    Discard {
+        /// ID or name of the medication
+        name: String,
+    },
+    /// Stop tracking stock for a medication
+    Clear {
+        /// ID or name of the medication
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// List available backups (newest first)
+    #[command(visible_alias = "l")]
+    List,
+    /// Restore a backup, showing what will change first
+    Restore {
+        /// Backup number from `pharm backup list` (1 = newest) or its timestamp
+        id: String,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+/// Parses `--min-gap` values ("4h", "30m", "1h30m"; a bare number is hours)
+fn parse_min_gap(value: &str) -> Result<u32, String> {
+    parse_duration_minutes(value)
+        .ok_or_else(|| format!("invalid duration '{}' (e.g. \"4h\", \"90m\")", value))
+}
+
+/// Parses `--escalate-after` values ("45m", "1h30m"; a bare number is
+/// minutes, like `escalation_minutes` in the config)
+fn parse_escalate_after(value: &str) -> Result<u32, String> {
+    match value.trim().parse::<u32>() {
+        Ok(minutes) => Ok(minutes),
+        Err(_) => parse_min_gap(value),
+    }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Canonical subcommand name (aliases resolved) for the audit log
+    if let Some(name) = matches.subcommand_name() {
+        audit::set_command(name);
+    }
 
     match cli.command {
         Commands::Add {
@@ -113,27 +464,102 @@ fn main() {
             time,
             freq,
             notes,
+            allow_duplicate,
+            max_per_day,
+            min_gap,
+            ingredients,
+            yes,
         } => {
-            add_medication(name, dose, time, freq, notes);
+            let options = AddOptions {
+                allow_duplicate,
+                limits: DoseLimits {
+                    max_per_day,
+                    min_gap_minutes: min_gap.filter(|gap| *gap > 0),
+                },
+                ingredients,
+                yes,
+            };
+            add_medication(name, dose, time, freq, notes, options);
         }
         Commands::Remove { name } => {
             remove_medication(name);
         }
-        Commands::Take { name } => {
-            take_medication(name);
+        Commands::Take { name, force } => {
+            take_medication(name, force);
         }
         Commands::Untake { name } => {
             untake_medication(name);
         }
-        Commands::TakeAll => take_all_medications(),
+        Commands::TakeAll { force } => take_all_medications(force),
         Commands::Edit {
             name,
             dose,
             time,
             freq,
             notes,
+            max_per_day,
+            min_gap,
+            ingredients,
+            clear_ingredients,
+            yes,
+            prescriber,
+            pharmacy,
+            pharmacy_phone,
+            rx_number,
+            refills,
+            rx_expires,
+            notify_via,
+            clear_notify_via,
+            critical,
+            caregivers,
+            clear_caregivers,
+            escalate_after,
         } => {
-            edit_medication(name, dose, time, freq, notes);
+            let ingredients = match (clear_ingredients, ingredients.is_empty()) {
+                (true, _) => Some(Vec::new()),
+                (false, true) => None,
+                (false, false) => Some(ingredients),
+            };
+            let notify_via = match (clear_notify_via, notify_via.is_empty()) {
+                (true, _) => Some(Vec::new()),
+                (false, true) => None,
+                (false, false) => Some(notify_via),
+            };
+            let caregivers = match (clear_caregivers, caregivers.is_empty()) {
+                (true, _) => Some(Vec::new()),
+                (false, true) => None,
+                (false, false) => Some(caregivers),
+            };
+            let edit = MedicationEdit {
+                dose,
+                time,
+                freq,
+                notes,
+                max_per_day,
+                min_gap,
+                ingredients,
+                yes,
+                prescription: PrescriptionEdit {
+                    prescriber,
+                    pharmacy,
+                    pharmacy_phone,
+                    rx_number,
+                    refills,
+                    expires: rx_expires,
+                },
+                notify_via,
+                caregivers,
+                escalate_after,
+                critical,
+            };
+            edit_medication(name, edit);
+        }
+        Commands::Rename {
+            name,
+            new_name,
+            allow_duplicate,
+        } => {
+            rename_medication(name, new_name, allow_duplicate);
         }
         Commands::List { archived, due } => {
             list_medications(archived, due);
@@ -145,8 +571,72 @@ fn main() {
         } => {
             display_history(name, days, archived);
         }
-        Commands::Daemon => {
-            run_daemon();
+        Commands::Allergy { action } => match action {
+            AllergyCommands::Add { name, reaction } => add_allergy(name, reaction),
+            AllergyCommands::Remove { name } => remove_allergy(name),
+            AllergyCommands::List => list_allergies(),
+        },
+        Commands::Stock { action } => match action.unwrap_or(StockCommands::List) {
+            StockCommands::List => list_stock(),
+            StockCommands::Set {
+                name,
+                quantity,
+                per_dose,
+                expires,
+                lot,
+            } => update_stock(name, StockChange::Set(quantity), per_dose, expires, lot),
+            StockCommands::Add {
+                name,
+                quantity,
+                per_dose,
+                expires,
+                lot,
+            } => update_stock(name, StockChange::Add(quantity), per_dose, expires, lot),
+            StockCommands::Discard { name } => discard_expired(name),
+            StockCommands::Clear { name } => {
+                update_stock(name, StockChange::Clear, None, None, None)
+            }
+        },
+        Commands::Check { severity } => check_interactions(severity),
+        Commands::NotifyTest { notifiers } => test_notifiers(notifiers),
+        Commands::Next { count } => show_next(count),
+        Commands::Daemon { action, key_file } => match action {
+            None => {
+                if let Some(path) = key_file {
+                    set_key_file(path);
+                }
+                run_daemon();
+            }
+            Some(DaemonCommands::Status) => show_daemon_status(),
+            Some(DaemonCommands::Stop) => control_daemon(ControlRequest::Stop),
+            Some(DaemonCommands::Reload) => control_daemon(ControlRequest::Reload),
+            Some(DaemonCommands::Install {
+                key_file,
+                autostart,
+                no_start,
+            }) => install_service(key_file, autostart, no_start),
+            Some(DaemonCommands::Uninstall) => uninstall_service(),
+        },
+        Commands::Backup { action } => match action {
+            BackupCommands::List => list_backups(),
+            BackupCommands::Restore { id, yes } => restore_backup(id, yes),
+        },
+        Commands::Config => show_config(),
+        Commands::Undo { steps, list, force } => {
+            if list {
+                show_journal();
+            } else {
+                undo_operations(steps, force);
+            }
         }
+        Commands::Redo { steps, force } => redo_operations(steps, force),
+        Commands::Log {
+            name,
+            command,
+            days,
+            limit,
+        } => show_log(name, command, days, limit),
+        Commands::Encrypt => encrypt_database(),
+        Commands::Decrypt => decrypt_database(),
     }
 }
@@ -0,0 +1,559 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_config, PharmConfig};
+use crate::database::Medication;
+use crate::email::{EmailConfig, EmailNotifier};
+use crate::push::{GotifyConfig, GotifyNotifier, NtfyConfig, NtfyNotifier};
+
+/// How long a notification command, webhook or email may take before it is
+/// abandoned
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What an alert is about
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Dose,
+    /// A dose still not taken `missed_dose_minutes` after it was due
+    Missed,
+    /// Sent to a medication's caregivers when a reminder goes unanswered
+    Caregiver,
+    /// Doses that came due while the daemon was stopped or the computer slept
+    CatchUp,
+    Refill,
+    Prescription,
+    Expiry,
+    Test,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Normal,
+    Critical,
+}
+
+/// One message for the user, independent of how it is delivered.
+///
+/// Serialized as-is for the FIFO and webhook backends:
+/// `{"kind":"dose","summary":"MEDICATION REMINDER","body":"...","urgency":"critical",
+/// "med_id":1,"med_name":"Aspirin","dose":"500mg","scheduled":"08:00",
+/// "time":"2025-10-21 08:00:00"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+    pub med_id: u32,
+    pub med_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub dose: String,
+    /// Time of day the dose was scheduled for (dose and missed-dose alerts)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Local time the alert was raised ("%Y-%m-%d %H:%M:%S")
+    pub time: String,
+}
+
+impl Alert {
+    pub fn new(kind: AlertKind, med: &Medication, summary: &str, body: String) -> Alert {
+        Alert {
+            kind,
+            summary: summary.to_string(),
+            body,
+            urgency: match kind {
+                AlertKind::Dose | AlertKind::Missed | AlertKind::Caregiver => Urgency::Critical,
+                _ => Urgency::Normal,
+            },
+            med_id: med.id,
+            med_name: med.name.clone(),
+            dose: med.dose.clone(),
+            scheduled: None,
+            notes: med.notes.clone(),
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+
+    pub fn scheduled_at(mut self, time_of_day: &str) -> Alert {
+        self.scheduled = Some(time_of_day.to_string());
+        self
+    }
+
+    /// Fills in a message template: `{summary}`, `{body}`, `{kind}`,
+    /// `{medication}`, `{id}`, `{dose}`, `{scheduled}`, `{notes}` and `{time}`.
+    /// Missing values show as "-".
+    pub fn render(&self, template: &str) -> String {
+        let or_dash = |value: Option<&str>| match value {
+            Some(value) if !value.is_empty() => value.to_string(),
+            _ => "-".to_string(),
+        };
+        template
+            .replace("{summary}", &self.summary)
+            .replace("{body}", &self.body)
+            .replace("{kind}", &enum_name(&self.kind))
+            .replace("{medication}", &self.med_name)
+            .replace("{id}", &self.med_id.to_string())
+            .replace("{dose}", &or_dash(Some(&self.dose)))
+            .replace("{scheduled}", &or_dash(self.scheduled.as_deref()))
+            .replace("{notes}", &or_dash(self.notes.as_deref()))
+            .replace("{time}", &self.time)
+    }
+}
+
+/// A way of delivering alerts to the user
+pub trait Notifier {
+    fn send(&self, alert: &Alert) -> Result<(), String>;
+}
+
+/// How a named notifier in the config delivers alerts, e.g.
+/// `{"type": "webhook", "url": "http://localhost:8080/pharm"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    /// Desktop notification popup
+    Desktop,
+    /// A line on the daemon's standard output, optionally ringing the bell
+    Terminal {
+        #[serde(default = "default_bell")]
+        bell: bool,
+    },
+    /// A shell command, given the alert in `PHARM_*` environment variables
+    Command { command: String },
+    /// One JSON line per alert written to a named pipe (or appended to a file)
+    Fifo { path: PathBuf },
+    /// The alert as JSON in an HTTP POST request
+    Webhook {
+        url: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+    /// An email sent through an SMTP server
+    Email(EmailConfig),
+    /// A push notification published to an ntfy topic
+    Ntfy(NtfyConfig),
+    /// A push notification sent to a Gotify server
+    Gotify(GotifyConfig),
+}
+
+fn default_bell() -> bool {
+    true
+}
+
+impl NotifierConfig {
+    /// The notifier itself. Push notifiers get "Taken" buttons if the config
+    /// has a `take_endpoint`.
+    pub fn build(&self, config: &PharmConfig) -> Box<dyn Notifier> {
+        match self {
+            NotifierConfig::Desktop => Box::new(DesktopNotifier),
+            NotifierConfig::Terminal { bell } => Box::new(TerminalNotifier { bell: *bell }),
+            NotifierConfig::Command { command } => Box::new(CommandNotifier {
+                command: command.clone(),
+            }),
+            NotifierConfig::Fifo { path } => Box::new(FifoNotifier { path: path.clone() }),
+            NotifierConfig::Webhook { url, headers } => Box::new(WebhookNotifier {
+                url: url.clone(),
+                headers: headers.clone(),
+            }),
+            NotifierConfig::Email(email) => Box::new(EmailNotifier {
+                config: email.clone(),
+            }),
+            NotifierConfig::Ntfy(ntfy) => Box::new(NtfyNotifier {
+                config: ntfy.clone(),
+                take: config.take_endpoint.clone(),
+            }),
+            NotifierConfig::Gotify(gotify) => Box::new(GotifyNotifier {
+                config: gotify.clone(),
+                take: config.take_endpoint.clone(),
+            }),
+        }
+    }
+}
+
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let mut notification = notify_rust::Notification::new();
+        notification
+            .summary(&alert.summary)
+            .body(&alert.body)
+            .icon("MEDICATION")
+            .appname("pharm");
+        if alert.urgency == Urgency::Critical {
+            notification
+                .timeout(0) // Don't auto-dismiss
+                .urgency(notify_rust::Urgency::Critical);
+        } else {
+            notification.urgency(notify_rust::Urgency::Normal);
+        }
+        notification.show().map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+pub struct TerminalNotifier {
+    pub bell: bool,
+}
+
+impl Notifier for TerminalNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let mut stdout = std::io::stdout().lock();
+        let bell = if self.bell { "\x07" } else { "" };
+        writeln!(
+            stdout,
+            "{}*** {}: {}",
+            bell,
+            alert.summary,
+            alert.body.replace('\n', " - ")
+        )
+        .and_then(|_| stdout.flush())
+        .map_err(|e| e.to_string())
+    }
+}
+
+/// Runs `command` through the shell with `PHARM_KIND`, `PHARM_SUMMARY`,
+/// `PHARM_BODY`, `PHARM_URGENCY`, `PHARM_MED_ID`, `PHARM_MED_NAME`,
+/// `PHARM_DOSE`, `PHARM_SCHEDULED`, `PHARM_NOTES` and `PHARM_TIME` set
+pub struct CommandNotifier {
+    pub command: String,
+}
+
+impl CommandNotifier {
+    fn shell(&self) -> Command {
+        if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C").arg(&self.command);
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c").arg(&self.command);
+            shell
+        }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let mut child = self
+            .shell()
+            .env("PHARM_KIND", enum_name(&alert.kind))
+            .env("PHARM_SUMMARY", &alert.summary)
+            .env("PHARM_BODY", &alert.body)
+            .env("PHARM_URGENCY", enum_name(&alert.urgency))
+            .env("PHARM_MED_ID", alert.med_id.to_string())
+            .env("PHARM_MED_NAME", &alert.med_name)
+            .env("PHARM_DOSE", &alert.dose)
+            .env("PHARM_SCHEDULED", alert.scheduled.as_deref().unwrap_or(""))
+            .env("PHARM_NOTES", alert.notes.as_deref().unwrap_or(""))
+            .env("PHARM_TIME", &alert.time)
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{}: {}", self.command, e))?;
+
+        // Don't let a stuck command hold up the daemon
+        let started = Instant::now();
+        loop {
+            match child.try_wait().map_err(|e| e.to_string())? {
+                Some(status) if status.success() => return Ok(()),
+                Some(status) => return Err(format!("'{}' failed ({})", self.command, status)),
+                None if started.elapsed() > SEND_TIMEOUT => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!("'{}' timed out", self.command));
+                }
+                None => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+}
+
+/// The serde name of a unit enum variant ("dose", "critical", ...)
+fn enum_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Writes one JSON line per alert to `path`, usually a FIFO made with
+/// `mkfifo` that another program reads from
+pub struct FifoNotifier {
+    pub path: PathBuf,
+}
+
+impl Notifier for FifoNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let mut options = fs::OpenOptions::new();
+        options.append(true);
+        // Opening a FIFO blocks until there is a reader - fail instead
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+        let mut file = options.open(&self.path).map_err(|e| {
+            if e.raw_os_error() == Some(libc::ENXIO) {
+                format!("{}: nobody is reading the FIFO", self.path.display())
+            } else {
+                format!("{}: {}", self.path.display(), e)
+            }
+        })?;
+
+        let json = serde_json::to_string(alert).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", json).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// POSTs the alert as JSON to `url`
+pub struct WebhookNotifier {
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Notifier for WebhookNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let json = serde_json::to_string(alert).map_err(|e| e.to_string())?;
+        let mut request = ureq::post(&self.url)
+            .timeout(SEND_TIMEOUT)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request
+            .send_string(&json)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Looks up a notifier by name: one defined under `notifiers` in the config,
+/// or the built-in "desktop" and "terminal"
+pub fn find_notifier(name: &str, config: &PharmConfig) -> Option<NotifierConfig> {
+    if let Some(notifier) = config.notifiers.get(name) {
+        return Some(notifier.clone());
+    }
+    match name {
+        "desktop" => Some(NotifierConfig::Desktop),
+        "terminal" => Some(NotifierConfig::Terminal { bell: true }),
+        _ => None,
+    }
+}
+
+/// The notifiers used for `med`: its own `notify_via` list, or the config's
+pub fn notifiers_for<'a>(med: &'a Medication, config: &'a PharmConfig) -> &'a [String] {
+    if med.notify_via.is_empty() {
+        &config.notify_via
+    } else {
+        &med.notify_via
+    }
+}
+
+/// Sends `alert` through each named notifier, returning the names that
+/// delivered it and an error message for each that didn't
+pub fn send_alert(
+    alert: &Alert,
+    names: &[String],
+    config: &PharmConfig,
+) -> (Vec<String>, Vec<String>) {
+    let mut delivered = Vec::new();
+    let mut failed = Vec::new();
+    for name in names {
+        let Some(notifier) = find_notifier(name, config) else {
+            failed.push(format!("{}: no such notifier in the config", name));
+            continue;
+        };
+        match notifier.build(config).send(alert) {
+            Ok(()) => delivered.push(name.clone()),
+            Err(e) => failed.push(format!("{}: {}", name, e)),
+        }
+    }
+    (delivered, failed)
+}
+
+/// Checks notifier names given on the command line against the config
+pub fn validate_names(names: &[String]) -> Result<(), String> {
+    let config = load_config();
+    match names
+        .iter()
+        .find(|name| find_notifier(name, &config).is_none())
+    {
+        Some(name) => Err(format!(
+            "Unknown notifier '{}' (built in: desktop, terminal; others are defined under \"notifiers\" in the config)",
+            name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// `pharm notify-test`: sends a test alert through the given notifiers, or
+/// the configured defaults
+pub fn test_notifiers(names: Vec<String>) {
+    let config = load_config();
+    let names = if names.is_empty() {
+        config.notify_via.clone()
+    } else {
+        names
+    };
+
+    let med = Medication {
+        name: "pharm".to_string(),
+        ..Default::default()
+    };
+    let alert = Alert::new(
+        AlertKind::Test,
+        &med,
+        "PHARM TEST",
+        "Notifications from pharm are working.".to_string(),
+    );
+
+    let (delivered, failed) = send_alert(&alert, &names, &config);
+    for name in &delivered {
+        println!("Sent test notification via {}", name);
+    }
+    for error in &failed {
+        eprintln!("Error: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    fn alert() -> Alert {
+        let med = Medication {
+            id: 7,
+            name: "Aspirin".to_string(),
+            ..Default::default()
+        };
+        Alert::new(
+            AlertKind::Dose,
+            &med,
+            "MEDICATION REMINDER",
+            "Time to take: Aspirin (500mg)".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_config_format() {
+        let notifiers: BTreeMap<String, NotifierConfig> = serde_json::from_str(
+            r#"{
+                "phone": {"type": "webhook", "url": "http://localhost/hook"},
+                "beep": {"type": "terminal"},
+                "log": {"type": "command", "command": "logger \"$PHARM_BODY\""}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            notifiers["phone"],
+            NotifierConfig::Webhook {
+                url: "http://localhost/hook".to_string(),
+                headers: BTreeMap::new()
+            }
+        );
+        assert_eq!(notifiers["beep"], NotifierConfig::Terminal { bell: true });
+        assert!(serde_json::from_str::<NotifierConfig>(r#"{"type": "pager"}"#).is_err());
+    }
+
+    #[test]
+    fn test_find_notifier() {
+        let mut config = PharmConfig::default();
+        assert_eq!(
+            find_notifier("desktop", &config),
+            Some(NotifierConfig::Desktop)
+        );
+        assert_eq!(find_notifier("phone", &config), None);
+
+        config.notifiers.insert(
+            "desktop".to_string(),
+            NotifierConfig::Terminal { bell: false },
+        );
+        assert_eq!(
+            find_notifier("desktop", &config),
+            Some(NotifierConfig::Terminal { bell: false })
+        );
+    }
+
+    #[test]
+    fn test_webhook() {
+        // A stand-in server that accepts one request
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/pharm", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = Vec::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                head.push(line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+
+        let mut headers = BTreeMap::new();
+        headers.insert("X-Token".to_string(), "secret".to_string());
+        let webhook = WebhookNotifier { url, headers };
+        let sent = alert();
+        webhook.send(&sent.clone()).unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert!(head[0].starts_with("POST /pharm "));
+        assert!(head.iter().any(|line| line.starts_with("X-Token: secret")));
+        let received: Alert = serde_json::from_str(&body).unwrap();
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn test_render() {
+        let alert = alert().scheduled_at("08:00");
+        assert_eq!(
+            alert.render("{summary}: {medication} #{id} {dose} at {scheduled}, notes {notes}"),
+            "MEDICATION REMINDER: Aspirin #7 - at 08:00, notes -"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command() {
+        let out = std::env::temp_dir().join(format!("pharm-notify-{}", std::process::id()));
+        let command = CommandNotifier {
+            command: format!(
+                "printf '%s|%s|%s' \"$PHARM_KIND\" \"$PHARM_MED_ID\" \"$PHARM_MED_NAME\" > '{}'",
+                out.display()
+            ),
+        };
+        command.send(&alert()).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "dose|7|Aspirin");
+        let _ = fs::remove_file(&out);
+
+        let failing = CommandNotifier {
+            command: "exit 3".to_string(),
+        };
+        assert!(failing.send(&alert()).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::database::Medication;
+use crate::time::parse_date;
+
+/// Prescription details for a medication. Every field is optional so OTC
+/// medications simply leave them out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Prescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prescriber: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pharmacy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pharmacy_phone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rx_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refills_remaining: Option<u32>,
+    /// Date the prescription expires (YYYY-MM-DD)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+}
+
+impl Prescription {
+    pub fn is_empty(&self) -> bool {
+        *self == Prescription::default()
+    }
+
+    pub fn expiry_date(&self) -> Option<NaiveDate> {
+        parse_date(self.expires.as_deref()?)
+    }
+
+    /// Lines shown under a medication in `pharm list`
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        let mut rx = Vec::new();
+        if let Some(number) = &self.rx_number {
+            rx.push(format!("#{}", number));
+        }
+        if let Some(prescriber) = &self.prescriber {
+            rx.push(prescriber.clone());
+        }
+        if let Some(refills) = self.refills_remaining {
+            rx.push(format!("{} refill(s) left", refills));
+        }
+        if let Some(expires) = &self.expires {
+            rx.push(format!("expires {}", expires));
+        }
+        if !rx.is_empty() {
+            lines.push(format!("Rx:       {}", rx.join(", ")));
+        }
+
+        match (&self.pharmacy, &self.pharmacy_phone) {
+            (Some(name), Some(phone)) => lines.push(format!("Pharmacy: {} ({})", name, phone)),
+            (Some(name), None) => lines.push(format!("Pharmacy: {}", name)),
+            (None, Some(phone)) => lines.push(format!("Pharmacy: {}", phone)),
+            (None, None) => {}
+        }
+
+        lines
+    }
+}
+
+/// Changes to prescription fields from `pharm edit`. For text fields an empty
+/// string clears the value.
+#[derive(Debug, Clone, Default)]
+pub struct PrescriptionEdit {
+    pub prescriber: Option<String>,
+    pub pharmacy: Option<String>,
+    pub pharmacy_phone: Option<String>,
+    pub rx_number: Option<String>,
+    pub refills: Option<u32>,
+    pub expires: Option<String>,
+}
+
+impl PrescriptionEdit {
+    /// Checks the expiry date before anything is changed
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.expires {
+            Some(date) if !date.is_empty() && parse_date(date).is_none() => Err(format!(
+                "Invalid expiry date '{}' (expected YYYY-MM-DD)",
+                date
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the edit, returning a description of each change
+    pub fn apply(self, rx: &mut Prescription) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        let mut set_text = |field: &mut Option<String>, value: Option<String>, label: &str| {
+            if let Some(value) = value {
+                let value = value.trim().to_string();
+                if value.is_empty() {
+                    *field = None;
+                    changes.push(format!("{} -> (cleared)", label));
+                } else {
+                    changes.push(format!("{} -> {}", label, value));
+                    *field = Some(value);
+                }
+            }
+        };
+        set_text(&mut rx.prescriber, self.prescriber, "prescriber");
+        set_text(&mut rx.pharmacy, self.pharmacy, "pharmacy");
+        set_text(
+            &mut rx.pharmacy_phone,
+            self.pharmacy_phone,
+            "pharmacy phone",
+        );
+        set_text(&mut rx.rx_number, self.rx_number, "Rx number");
+        set_text(&mut rx.expires, self.expires, "prescription expires");
+
+        if let Some(refills) = self.refills {
+            rx.refills_remaining = Some(refills);
+            changes.push(format!("refills -> {}", refills));
+        }
+
+        changes
+    }
+}
+
+/// Warnings about a medication's prescription: expired, expiring within
+/// `warning_days`, or no refills left.
+pub fn prescription_alerts(med: &Medication, today: NaiveDate, warning_days: u32) -> Vec<String> {
+    let rx = &med.prescription;
+    let mut alerts = Vec::new();
+
+    if let Some(expires) = rx.expiry_date() {
+        let days_left = (expires - today).num_days();
+        if days_left < 0 {
+            alerts.push(format!(
+                "{}: prescription expired on {}",
+                med.name,
+                expires.format("%Y-%m-%d")
+            ));
+        } else if days_left <= warning_days as i64 {
+            alerts.push(format!(
+                "{}: prescription expires in {} day(s) ({})",
+                med.name,
+                days_left,
+                expires.format("%Y-%m-%d")
+            ));
+        }
+    }
+
+    if rx.refills_remaining == Some(0) {
+        let contact = match (&rx.prescriber, &rx.pharmacy_phone) {
+            (Some(prescriber), _) => format!(" - contact {}", prescriber),
+            (None, Some(phone)) => format!(" - call the pharmacy at {}", phone),
+            (None, None) => String::new(),
+        };
+        alerts.push(format!("{}: no refills left{}", med.name, contact));
+    }
+
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn med(rx: Prescription) -> Medication {
+        Medication {
+            name: "Lisinopril".to_string(),
+            prescription: rx,
+            ..Default::default()
+        }
+    }
+
+    fn today() -> NaiveDate {
+        parse_date("2025-10-21").unwrap()
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let mut rx = Prescription::default();
+        let edit = PrescriptionEdit {
+            prescriber: Some("Dr. Smith".to_string()),
+            rx_number: Some(" 12345 ".to_string()),
+            refills: Some(3),
+            ..Default::default()
+        };
+        let changes = edit.apply(&mut rx);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(rx.rx_number.as_deref(), Some("12345"));
+        assert_eq!(rx.refills_remaining, Some(3));
+
+        let clear = PrescriptionEdit {
+            prescriber: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(clear.apply(&mut rx), vec!["prescriber -> (cleared)"]);
+        assert_eq!(rx.prescriber, None);
+    }
+
+    #[test]
+    fn test_validate_expiry() {
+        let bad = PrescriptionEdit {
+            expires: Some("next year".to_string()),
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+
+        let clear = PrescriptionEdit {
+            expires: Some(String::new()),
+            ..Default::default()
+        };
+        assert!(clear.validate().is_ok());
+    }
+
+    #[test]
+    fn test_alerts() {
+        let soon = med(Prescription {
+            expires: Some("2025-10-31".to_string()),
+            ..Default::default()
+        });
+        assert!(prescription_alerts(&soon, today(), 14)[0].contains("expires in 10 day(s)"));
+        assert!(prescription_alerts(&soon, today(), 7).is_empty());
+
+        let expired = med(Prescription {
+            expires: Some("2025-10-01".to_string()),
+            refills_remaining: Some(0),
+            prescriber: Some("Dr. Smith".to_string()),
+            ..Default::default()
+        });
+        let alerts = prescription_alerts(&expired, today(), 14);
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts[0].contains("expired on 2025-10-01"));
+        assert!(alerts[1].contains("no refills left - contact Dr. Smith"));
+
+        assert!(prescription_alerts(&med(Prescription::default()), today(), 14).is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let rx = Prescription {
+            rx_number: Some("12345".to_string()),
+            prescriber: Some("Dr. Smith".to_string()),
+            pharmacy: Some("CVS".to_string()),
+            pharmacy_phone: Some("555-0100".to_string()),
+            refills_remaining: Some(2),
+            expires: None,
+        };
+        assert_eq!(
+            rx.describe(),
+            vec![
+                "Rx:       #12345, Dr. Smith, 2 refill(s) left",
+                "Pharmacy: CVS (555-0100)"
+            ]
+        );
+    }
+}
@@ -0,0 +1,17 @@
+use std::io::{self, BufRead, Write};
+
+/// Asks a yes/no question on the terminal and returns true only for "y"/"yes".
+///
+/// Anything else - including EOF when stdin is not interactive - counts as no,
+/// so destructive actions are never confirmed by accident.
+pub fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
@@ -0,0 +1,204 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::endpoint::TakeEndpoint;
+use crate::notifier::{Alert, AlertKind, Notifier, Urgency, SEND_TIMEOUT};
+
+/// A topic on an ntfy server, e.g.
+/// `{"type": "ntfy", "url": "https://ntfy.example.com", "topic": "pills"}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NtfyConfig {
+    /// Server URL
+    pub url: String,
+    pub topic: String,
+    /// Access token for protected topics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// A Gotify server and application token, e.g.
+/// `{"type": "gotify", "url": "https://gotify.example.com", "token": "A1b2..."}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GotifyConfig {
+    pub url: String,
+    /// Application token
+    pub token: String,
+}
+
+/// Only reminders about a dose can be answered with "Taken"
+fn is_takeable(alert: &Alert) -> bool {
+    matches!(alert.kind, AlertKind::Dose | AlertKind::Missed)
+}
+
+/// Single-use link that takes the dose `alert` is about
+fn dose_link(take: &TakeEndpoint, alert: &Alert) -> String {
+    let issued = NaiveDateTime::parse_from_str(&alert.time, "%Y-%m-%d %H:%M:%S")
+        .unwrap_or_else(|_| chrono::Local::now().naive_local());
+    take.dose_link(alert.med_id, issued)
+}
+
+/// ntfy priorities run from 1 (min) to 5 (max, "urgent")
+fn ntfy_priority(urgency: Urgency) -> u8 {
+    match urgency {
+        Urgency::Critical => 5,
+        Urgency::Normal => 3,
+    }
+}
+
+/// Gotify apps show priorities of 8 and up as high-priority notifications
+fn gotify_priority(urgency: Urgency) -> u8 {
+    match urgency {
+        Urgency::Critical => 8,
+        Urgency::Normal => 5,
+    }
+}
+
+/// The JSON message published to ntfy, with a "Taken" button when the
+/// daemon's take endpoint is configured
+pub fn ntfy_message(config: &NtfyConfig, alert: &Alert, take: Option<&TakeEndpoint>) -> Value {
+    let mut message = json!({
+        "topic": config.topic,
+        "title": alert.summary,
+        "message": alert.body,
+        "priority": ntfy_priority(alert.urgency),
+        "tags": ["pill"],
+    });
+    if let Some(take) = take.filter(|_| is_takeable(alert)) {
+        message["actions"] = json!([{
+            "action": "http",
+            "label": "Taken",
+            "url": dose_link(take, alert),
+            "method": "POST",
+            "clear": true,
+        }]);
+    }
+    message
+}
+
+/// The JSON message sent to Gotify. Gotify has no action buttons, so tapping
+/// the notification opens the dose link's confirmation page instead.
+pub fn gotify_message(alert: &Alert, take: Option<&TakeEndpoint>) -> Value {
+    let mut message = json!({
+        "title": alert.summary,
+        "message": alert.body,
+        "priority": gotify_priority(alert.urgency),
+    });
+    if let Some(take) = take.filter(|_| is_takeable(alert)) {
+        message["extras"] = json!({
+            "client::notification": { "click": { "url": dose_link(take, alert) } }
+        });
+    }
+    message
+}
+
+fn post_json(url: &str, headers: &[(&str, String)], body: &Value) -> Result<(), String> {
+    let mut request = ureq::post(url)
+        .timeout(SEND_TIMEOUT)
+        .set("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    request
+        .send_string(&body.to_string())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+pub struct NtfyNotifier {
+    pub config: NtfyConfig,
+    pub take: Option<TakeEndpoint>,
+}
+
+impl Notifier for NtfyNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        let headers: Vec<(&str, String)> = self
+            .config
+            .token
+            .iter()
+            .map(|token| ("Authorization", format!("Bearer {}", token)))
+            .collect();
+        post_json(
+            self.config.url.trim_end_matches('/'),
+            &headers,
+            &ntfy_message(&self.config, alert, self.take.as_ref()),
+        )
+    }
+}
+
+pub struct GotifyNotifier {
+    pub config: GotifyConfig,
+    pub take: Option<TakeEndpoint>,
+}
+
+impl Notifier for GotifyNotifier {
+    fn send(&self, alert: &Alert) -> Result<(), String> {
+        post_json(
+            &format!("{}/message", self.config.url.trim_end_matches('/')),
+            &[("X-Gotify-Key", self.config.token.clone())],
+            &gotify_message(alert, self.take.as_ref()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Medication;
+
+    fn alert(kind: AlertKind) -> Alert {
+        let med = Medication {
+            id: 4,
+            name: "Insulin".to_string(),
+            ..Default::default()
+        };
+        Alert::new(
+            kind,
+            &med,
+            "MEDICATION REMINDER",
+            "Time to take: Insulin".to_string(),
+        )
+    }
+
+    fn endpoint() -> TakeEndpoint {
+        TakeEndpoint {
+            listen: "0.0.0.0:8765".to_string(),
+            url: "http://laptop.lan:8765".to_string(),
+            token: "s3cret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ntfy_message() {
+        let config = NtfyConfig {
+            url: "https://ntfy.example.com".to_string(),
+            topic: "pills".to_string(),
+            token: None,
+        };
+        let message = ntfy_message(&config, &alert(AlertKind::Dose), Some(&endpoint()));
+        assert_eq!(message["topic"], "pills");
+        assert_eq!(message["priority"], 5);
+        let url = message["actions"][0]["url"].as_str().unwrap();
+        assert!(url.starts_with("http://laptop.lan:8765/take/4?token="));
+        assert!(!message.to_string().contains("s3cret"));
+
+        // Nothing to take for a refill reminder
+        let message = ntfy_message(&config, &alert(AlertKind::Refill), Some(&endpoint()));
+        assert_eq!(message["priority"], 3);
+        assert!(message.get("actions").is_none());
+
+        let message = ntfy_message(&config, &alert(AlertKind::Dose), None);
+        assert!(message.get("actions").is_none());
+    }
+
+    #[test]
+    fn test_gotify_message() {
+        let message = gotify_message(&alert(AlertKind::Missed), Some(&endpoint()));
+        assert_eq!(message["priority"], 8);
+        let url = message["extras"]["client::notification"]["click"]["url"]
+            .as_str()
+            .unwrap();
+        assert!(url.starts_with("http://laptop.lan:8765/take/4?token="));
+        assert!(!url.contains("s3cret"));
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::scheduler::Reminder;
+use crate::time::parse_time;
+
+/// What happens to alerts for non-critical medications during quiet hours
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietMode {
+    /// Hold them until quiet hours end
+    #[default]
+    Defer,
+    /// Send them as ordinary notifications instead of critical ones
+    Downgrade,
+}
+
+/// A daily do-not-disturb window, e.g. `{"start": "23:00", "end": "06:30"}`.
+/// The window may wrap past midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub mode: QuietMode,
+}
+
+/// How the daemon should deliver an alert right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// As usual
+    Send,
+    /// As a normal-urgency notification
+    Downgrade,
+    /// Not yet - try again once quiet hours are over
+    Defer,
+}
+
+/// Dose reminders held back by quiet hours. They are kept apart from the
+/// daemon's per-day bookkeeping, so a reminder held back late in the evening
+/// is still sent when quiet hours end the next morning.
+#[derive(Debug, Default)]
+pub struct DeferredReminders {
+    held: Vec<Reminder>,
+}
+
+impl DeferredReminders {
+    pub fn hold(&mut self, reminder: Reminder) {
+        if !self.held.contains(&reminder) {
+            self.held.push(reminder);
+        }
+    }
+
+    pub fn is_held(&self, reminder: &Reminder) -> bool {
+        self.held.contains(reminder)
+    }
+
+    /// Takes out the held reminders if quiet hours no longer defer them at
+    /// `now`
+    pub fn release(&mut self, quiet: Option<&QuietHours>, now: NaiveDateTime) -> Vec<Reminder> {
+        let deferring =
+            quiet.is_some_and(|quiet| quiet.mode == QuietMode::Defer && quiet.contains(now.time()));
+        if deferring {
+            return Vec::new();
+        }
+        std::mem::take(&mut self.held)
+    }
+}
+
+fn parse(time: &str) -> Option<NaiveTime> {
+    let (hour, minute) = parse_time(time)?;
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+impl QuietHours {
+    /// Checks that both times can be parsed
+    pub fn validate(&self) -> Result<(), String> {
+        for time in [&self.start, &self.end] {
+            if parse(time).is_none() {
+                return Err(format!("invalid quiet hours time '{}'", time));
+            }
+        }
+        Ok(())
+    }
+
+    /// True if `time` falls inside the window (start inclusive, end exclusive)
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        let (Some(start), Some(end)) = (parse(&self.start), parse(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            start <= time && time < end
+        } else {
+            // Wraps past midnight, e.g. 23:00-06:30
+            time >= start || time < end
+        }
+    }
+
+    /// When the window containing `now` ends, or None outside quiet hours
+    pub fn ends_at(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if !self.contains(now.time()) {
+            return None;
+        }
+        let end = parse(&self.end)?;
+        let mut ends = now.date().and_time(end);
+        if ends <= now {
+            ends += Duration::days(1);
+        }
+        Some(ends)
+    }
+
+    /// How to deliver an alert at `now`; medications flagged critical always
+    /// break through
+    pub fn delivery(&self, critical: bool, now: NaiveDateTime) -> Delivery {
+        if critical || !self.contains(now.time()) {
+            return Delivery::Send;
+        }
+        match self.mode {
+            QuietMode::Defer => Delivery::Defer,
+            QuietMode::Downgrade => Delivery::Downgrade,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn quiet(start: &str, end: &str, mode: QuietMode) -> QuietHours {
+        QuietHours {
+            start: start.to_string(),
+            end: end.to_string(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let night = quiet("23:00", "06:30", QuietMode::Defer);
+        assert!(night.contains(at("2025-10-21 23:00").time()));
+        assert!(night.contains(at("2025-10-21 02:00").time()));
+        assert!(!night.contains(at("2025-10-21 06:30").time()));
+        assert!(!night.contains(at("2025-10-21 12:00").time()));
+
+        let nap = quiet("13:00", "15:00", QuietMode::Defer);
+        assert!(nap.contains(at("2025-10-21 14:00").time()));
+        assert!(!nap.contains(at("2025-10-21 23:00").time()));
+
+        assert!(quiet("23:00", "soon", QuietMode::Defer).validate().is_err());
+    }
+
+    #[test]
+    fn test_ends_at() {
+        let night = quiet("23:00", "06:30", QuietMode::Defer);
+        assert_eq!(
+            night.ends_at(at("2025-10-21 23:30")),
+            Some(at("2025-10-22 06:30"))
+        );
+        assert_eq!(
+            night.ends_at(at("2025-10-22 01:00")),
+            Some(at("2025-10-22 06:30"))
+        );
+        assert_eq!(night.ends_at(at("2025-10-22 12:00")), None);
+    }
+
+    #[test]
+    fn test_delivery() {
+        let night = quiet("23:00", "06:30", QuietMode::Defer);
+        assert_eq!(
+            night.delivery(false, at("2025-10-21 23:30")),
+            Delivery::Defer
+        );
+        assert_eq!(night.delivery(true, at("2025-10-21 23:30")), Delivery::Send);
+        assert_eq!(
+            night.delivery(false, at("2025-10-21 08:00")),
+            Delivery::Send
+        );
+
+        let soft = quiet("23:00", "06:30", QuietMode::Downgrade);
+        assert_eq!(
+            soft.delivery(false, at("2025-10-21 23:30")),
+            Delivery::Downgrade
+        );
+    }
+
+    #[test]
+    fn test_deferred_reminders() {
+        let night = quiet("23:00", "06:30", QuietMode::Defer);
+        let reminder = Reminder {
+            at: at("2025-10-21 23:30"),
+            med_id: 1,
+            name: "Melatonin".to_string(),
+            dose: "3mg".to_string(),
+            time_of_day: "23:30".to_string(),
+        };
+        let mut deferred = DeferredReminders::default();
+        deferred.hold(reminder.clone());
+        deferred.hold(reminder.clone());
+        assert!(deferred.is_held(&reminder));
+
+        // Still held after midnight, released when quiet hours end
+        assert!(deferred
+            .release(Some(&night), at("2025-10-22 00:05"))
+            .is_empty());
+        assert_eq!(
+            deferred.release(Some(&night), at("2025-10-22 06:30")),
+            vec![reminder.clone()]
+        );
+        assert!(!deferred.is_held(&reminder));
+
+        // Quiet hours switched off in the meantime
+        deferred.hold(reminder.clone());
+        assert_eq!(
+            deferred.release(None, at("2025-10-22 00:05")),
+            vec![reminder]
+        );
+    }
+}
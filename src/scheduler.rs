@@ -0,0 +1,232 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::database::{load_medications, Medication};
+use crate::interval::parse_interval_to_days;
+use crate::time::parse_time;
+
+/// One upcoming (or overdue) dose reminder
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reminder {
+    /// When the dose is due; ordering is by this field first
+    pub at: NaiveDateTime,
+    pub med_id: u32,
+    pub name: String,
+    pub dose: String,
+    pub time_of_day: String,
+}
+
+/// The instant the next dose of `med` is due, which may be in the past if
+/// today's dose is overdue.
+///
+/// A dose is due at the medication's time of day on the first day its
+/// interval allows after `last_dose_date` ("every 3 days" taken Monday ->
+/// Thursday), and never earlier than today. A medication already marked as
+/// taken is next due tomorrow at the earliest. PRN medications and unparseable
+/// times have no schedule.
+pub fn next_due(med: &Medication, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let interval_days = parse_interval_to_days(&med.medication_frequency)?;
+    let (hour, minute) = parse_time(&med.time_of_day)?;
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+
+    let today = now.date();
+    let mut day = match NaiveDate::parse_from_str(&med.last_dose_date, "%Y-%m-%d") {
+        Ok(last) => (last + Duration::days(interval_days as i64)).max(today),
+        Err(_) => today,
+    };
+    if med.taken && day == today {
+        day += Duration::days(1);
+    }
+
+    Some(day.and_time(time))
+}
+
+/// Priority queue of reminders, earliest first
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    queue: BinaryHeap<Reverse<Reminder>>,
+}
+
+impl Schedule {
+    pub fn build(meds: &[Medication], now: NaiveDateTime) -> Schedule {
+        let queue = meds
+            .iter()
+            .filter_map(|med| {
+                next_due(med, now).map(|at| {
+                    Reverse(Reminder {
+                        at,
+                        med_id: med.id,
+                        name: med.name.clone(),
+                        dose: med.dose.clone(),
+                        time_of_day: med.time_of_day.clone(),
+                    })
+                })
+            })
+            .collect();
+        Schedule { queue }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Reminders due at or before `now`, earliest first
+    pub fn due(&self, now: NaiveDateTime) -> Vec<&Reminder> {
+        let mut due: Vec<&Reminder> = self
+            .queue
+            .iter()
+            .map(|Reverse(reminder)| reminder)
+            .filter(|reminder| reminder.at <= now)
+            .collect();
+        due.sort();
+        due
+    }
+
+    /// The earliest reminder still in the future
+    pub fn next_after(&self, now: NaiveDateTime) -> Option<&Reminder> {
+        self.queue
+            .iter()
+            .map(|Reverse(reminder)| reminder)
+            .filter(|reminder| reminder.at > now)
+            .min()
+    }
+
+    /// All reminders in order, earliest first
+    pub fn upcoming(&self) -> Vec<Reminder> {
+        self.queue
+            .clone()
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|Reverse(reminder)| reminder)
+            .collect()
+    }
+}
+
+/// "in 3h20m", "in 2 days", "overdue by 15m"
+pub fn describe_wait(at: NaiveDateTime, now: NaiveDateTime) -> String {
+    let minutes = (at - now).num_minutes();
+    let span = |minutes: i64| match minutes / (24 * 60) {
+        0 => crate::interval::format_minutes(minutes as u32),
+        1 => "1 day".to_string(),
+        days => format!("{} days", days),
+    };
+    if minutes < 0 {
+        format!("overdue by {}", span(-minutes))
+    } else if minutes == 0 {
+        "now".to_string()
+    } else {
+        format!("in {}", span(minutes))
+    }
+}
+
+/// `pharm next`: shows the upcoming dose reminders
+pub fn show_next(count: Option<usize>) {
+    let meds = load_medications();
+    let now = chrono::Local::now().naive_local();
+    let schedule = Schedule::build(&meds, now);
+
+    if schedule.is_empty() {
+        println!("No scheduled medications.");
+        return;
+    }
+
+    println!("\nUpcoming Reminders:");
+    println!("{}", "=".repeat(60));
+    for reminder in schedule.upcoming().iter().take(count.unwrap_or(usize::MAX)) {
+        println!(
+            "  {}  {} (#{}) - {}  ({})",
+            reminder.at.format("%a %Y/%m/%d %H:%M"),
+            reminder.name,
+            reminder.med_id,
+            reminder.dose,
+            describe_wait(reminder.at, now)
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn med(id: u32, time: &str, freq: &str, last_dose: &str, taken: bool) -> Medication {
+        Medication {
+            id,
+            name: format!("Med {}", id),
+            time_of_day: time.to_string(),
+            medication_frequency: freq.to_string(),
+            last_dose_date: last_dose.to_string(),
+            taken,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_next_due_daily() {
+        let now = at("2025-10-21 12:00");
+        // Never taken: today, even if the time has passed (overdue)
+        assert_eq!(
+            next_due(&med(1, "08:00", "daily", "", false), now),
+            Some(at("2025-10-21 08:00"))
+        );
+        // Taken today: tomorrow
+        assert_eq!(
+            next_due(&med(1, "08:00", "daily", "2025-10-21", true), now),
+            Some(at("2025-10-22 08:00"))
+        );
+        // Later today
+        assert_eq!(
+            next_due(&med(1, "evening", "daily", "2025-10-20", false), now),
+            Some(at("2025-10-21 18:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_due_intervals() {
+        let now = at("2025-10-21 12:00");
+        assert_eq!(
+            next_due(&med(1, "08:00", "every 3 days", "2025-10-20", false), now),
+            Some(at("2025-10-23 08:00"))
+        );
+        // Missed for a week: due today, not in the past
+        assert_eq!(
+            next_due(&med(1, "08:00", "weekly", "2025-10-01", false), now),
+            Some(at("2025-10-21 08:00"))
+        );
+        assert_eq!(next_due(&med(1, "08:00", "prn", "", false), now), None);
+        assert_eq!(next_due(&med(1, "whenever", "daily", "", false), now), None);
+    }
+
+    #[test]
+    fn test_schedule_order() {
+        let now = at("2025-10-21 12:00");
+        let meds = vec![
+            med(1, "21:00", "daily", "2025-10-20", false),
+            med(2, "08:00", "daily", "2025-10-20", false),
+            med(3, "15:00", "daily", "2025-10-20", false),
+            med(4, "08:00", "prn", "", false),
+        ];
+        let schedule = Schedule::build(&meds, now);
+
+        let ids: Vec<u32> = schedule.upcoming().iter().map(|r| r.med_id).collect();
+        assert_eq!(ids, vec![2, 3, 1]);
+        assert_eq!(schedule.due(now).len(), 1);
+        assert_eq!(schedule.next_after(now).unwrap().med_id, 3);
+    }
+
+    #[test]
+    fn test_describe_wait() {
+        let now = at("2025-10-21 12:00");
+        assert_eq!(describe_wait(at("2025-10-21 15:20"), now), "in 3h20m");
+        assert_eq!(describe_wait(at("2025-10-23 12:00"), now), "in 2 days");
+        assert_eq!(describe_wait(at("2025-10-21 11:45"), now), "overdue by 15m");
+        assert_eq!(describe_wait(now, now), "now");
+    }
+}
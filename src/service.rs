@@ -0,0 +1,288 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the systemd user unit
+const UNIT_NAME: &str = "pharm.service";
+
+/// Returns the path of the systemd user unit
+/// (`~/.config/systemd/user/pharm.service`).
+pub fn get_unit_file() -> PathBuf {
+    config_home().join("systemd/user").join(UNIT_NAME)
+}
+
+/// Returns the path of the XDG autostart entry (`~/.config/autostart/pharm.desktop`).
+pub fn get_autostart_file() -> PathBuf {
+    config_home().join("autostart/pharm.desktop")
+}
+
+fn config_home() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".config")
+    })
+}
+
+/// Quotes an argument for an `ExecStart=` or `Exec=` line if it needs it
+fn quote(arg: &str) -> String {
+    if arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg.to_string()
+    }
+}
+
+/// The command line that starts the daemon
+fn daemon_command(exe: &Path, key_file: Option<&Path>) -> String {
+    let mut command = format!("{} daemon", quote(&exe.to_string_lossy()));
+    if let Some(key_file) = key_file {
+        command.push_str(&format!(
+            " --key-file {}",
+            quote(&key_file.to_string_lossy())
+        ));
+    }
+    command
+}
+
+/// Contents of the systemd user unit.
+///
+/// `Type=notify` makes systemd wait for the daemon's readiness message (see
+/// `notify_systemd`), and stop/reload go through the control socket so the
+/// daemon can shut down cleanly.
+pub fn systemd_unit(exe: &Path, key_file: Option<&Path>) -> String {
+    let pharm = quote(&exe.to_string_lossy());
+    format!(
+        "[Unit]
+Description=pharm medication reminder daemon
+Documentation=https://github.com/sqrew/pharm
+After=graphical-session.target
+
+[Service]
+Type=notify
+ExecStart={}
+ExecReload={} daemon reload
+ExecStop={} daemon stop
+Restart=on-failure
+RestartSec=10
+
+[Install]
+WantedBy=default.target
+",
+        daemon_command(exe, key_file),
+        pharm,
+        pharm
+    )
+}
+
+/// Contents of the XDG autostart entry, used where there is no systemd
+pub fn autostart_entry(exe: &Path, key_file: Option<&Path>) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Application
+Name=pharm reminders
+Comment=Medication reminder daemon
+Exec={}
+Terminal=false
+NoDisplay=true
+X-GNOME-Autostart-enabled=true
+",
+        daemon_command(exe, key_file)
+    )
+}
+
+/// True if this system runs systemd (see sd_booted(3))
+fn has_systemd() -> bool {
+    Path::new("/run/systemd/system").is_dir()
+}
+
+/// Runs `systemctl --user <args>`, returning an error message on failure
+fn systemctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| format!("systemctl --user {}: {}", args.join(" "), e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "systemctl --user {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// `pharm daemon install`: starts the daemon automatically at login, with a
+/// systemd user service or, without systemd (or with `autostart`), an XDG
+/// autostart entry.
+pub fn install_service(key_file: Option<PathBuf>, autostart: bool, no_start: bool) {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Error: Cannot find the pharm executable: {}", e);
+            return;
+        }
+    };
+    let key_file = match key_file.map(fs::canonicalize).transpose() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Error: Cannot read key file: {}", e);
+            return;
+        }
+    };
+    let encrypted = fs::read_to_string(crate::database::get_data_file())
+        .is_ok_and(|contents| crate::crypto::is_encrypted(&contents));
+    if key_file.is_none() && encrypted {
+        println!("Warning: The database is encrypted and the daemon can't ask for a passphrase");
+        println!("  when started in the background. Re-run with --key-file <file>.");
+    }
+
+    if autostart || !has_systemd() {
+        let path = get_autostart_file();
+        if let Err(e) = write_file(&path, &autostart_entry(&exe, key_file.as_deref())) {
+            eprintln!("Error: Failed to write autostart entry: {}", e);
+            return;
+        }
+        println!("Installed autostart entry: {}", path.display());
+        println!("The daemon will start the next time you log in.");
+        if !no_start {
+            println!("Start it now with: pharm daemon &");
+        }
+        return;
+    }
+
+    let path = get_unit_file();
+    if let Err(e) = write_file(&path, &systemd_unit(&exe, key_file.as_deref())) {
+        eprintln!("Error: Failed to write systemd unit: {}", e);
+        return;
+    }
+    println!("Installed systemd user service: {}", path.display());
+
+    let enable: &[&str] = if no_start {
+        &["enable", UNIT_NAME]
+    } else {
+        &["enable", "--now", UNIT_NAME]
+    };
+    let result = systemctl(&["daemon-reload"]).and_then(|_| systemctl(enable));
+    match result {
+        Ok(()) if no_start => println!("Enabled {} - it starts at your next login.", UNIT_NAME),
+        Ok(()) => {
+            println!("Enabled and started {}.", UNIT_NAME);
+            println!("  Logs:   journalctl --user -u pharm");
+            println!("  Status: pharm daemon status");
+        }
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            eprintln!(
+                "Enable it manually with: systemctl --user enable --now {}",
+                UNIT_NAME
+            );
+        }
+    }
+}
+
+/// `pharm daemon uninstall`: removes the systemd user service and the
+/// autostart entry, stopping the service first
+pub fn uninstall_service() {
+    let mut removed = false;
+
+    let unit = get_unit_file();
+    if unit.exists() {
+        if let Err(e) = systemctl(&["disable", "--now", UNIT_NAME]) {
+            eprintln!("Warning: {}", e);
+        }
+        match fs::remove_file(&unit) {
+            Ok(()) => {
+                println!("Removed systemd user service: {}", unit.display());
+                removed = true;
+                let _ = systemctl(&["daemon-reload"]);
+            }
+            Err(e) => eprintln!("Error: Failed to remove {}: {}", unit.display(), e),
+        }
+    }
+
+    let autostart = get_autostart_file();
+    if autostart.exists() {
+        match fs::remove_file(&autostart) {
+            Ok(()) => {
+                println!("Removed autostart entry: {}", autostart.display());
+                removed = true;
+            }
+            Err(e) => eprintln!("Error: Failed to remove {}: {}", autostart.display(), e),
+        }
+    }
+
+    if !removed {
+        println!("The daemon is not installed.");
+    } else if crate::control::is_daemon_running() {
+        println!("A daemon is still running; stop it with: pharm daemon stop");
+    }
+}
+
+/// Sends a state update to systemd (sd_notify(3)), e.g. "READY=1".
+///
+/// Does nothing unless systemd started us with `Type=notify`, which sets
+/// `NOTIFY_SOCKET`.
+pub fn notify_systemd(state: &str) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::net::UnixDatagram;
+
+        let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+            return;
+        };
+        let socket = socket.to_string_lossy().to_string();
+        let Ok(datagram) = UnixDatagram::unbound() else {
+            return;
+        };
+
+        // A leading '@' means a Linux abstract socket
+        #[cfg(target_os = "linux")]
+        if let Some(name) = socket.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            if let Ok(addr) = std::os::unix::net::SocketAddr::from_abstract_name(name) {
+                let _ = datagram.send_to_addr(state.as_bytes(), &addr);
+            }
+            return;
+        }
+
+        let _ = datagram.send_to(state.as_bytes(), &socket);
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(
+            Path::new("/usr/local/bin/pharm"),
+            Some(Path::new("/home/me/My Keys/pharm.key")),
+        );
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains(
+            "ExecStart=/usr/local/bin/pharm daemon --key-file \"/home/me/My Keys/pharm.key\"\n"
+        ));
+        assert!(unit.contains("ExecStop=/usr/local/bin/pharm daemon stop\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+    }
+
+    #[test]
+    fn test_autostart_entry() {
+        let entry = autostart_entry(Path::new("/opt/my apps/pharm"), None);
+        assert!(entry.starts_with("[Desktop Entry]\n"));
+        assert!(entry.contains("Exec=\"/opt/my apps/pharm\" daemon\n"));
+    }
+}
@@ -0,0 +1,443 @@
+use chrono::{Duration, NaiveDateTime};
+
+use crate::batches::{
+    describe_batches, expiry_alerts, remove_expired, return_to_batches, take_from_batches,
+    trim_batches, Batch,
+};
+use crate::database::{
+    find_active_or_report, load_database, save_database, DoseRecord, Medication,
+};
+use crate::dose::DoseUnit;
+use crate::interval::doses_per_day;
+use crate::time::{parse_date, parse_dose_timestamp};
+
+/// How far back PRN usage is averaged to estimate days of supply
+const PRN_USAGE_DAYS: i64 = 30;
+
+/// Units taken from the stock by one dose.
+///
+/// An explicit `units_per_dose` wins. Otherwise a dose counted in tablets,
+/// puffs, drops, ml or units uses its amount ("2 tablets" -> 2), and
+/// anything else ("500mg") is one pill per dose.
+pub fn units_per_dose(med: &Medication) -> f64 {
+    if let Some(units) = med.units_per_dose {
+        return units;
+    }
+    match med.dose_quantity {
+        Some(quantity) => match quantity.unit {
+            DoseUnit::Tablets
+            | DoseUnit::Puffs
+            | DoseUnit::Drops
+            | DoseUnit::Ml
+            | DoseUnit::Units => quantity.amount,
+            DoseUnit::Mg | DoseUnit::Mcg | DoseUnit::G | DoseUnit::Iu => 1.0,
+        },
+        None => 1.0,
+    }
+}
+
+/// Expected stock used per day: from the schedule for regular medications,
+/// from the last 30 days of history for PRN ones (None if never taken).
+pub fn daily_usage(med: &Medication, now: NaiveDateTime) -> Option<f64> {
+    if let Some(per_day) = doses_per_day(&med.medication_frequency) {
+        return Some(per_day * units_per_dose(med));
+    }
+
+    let since = now - Duration::days(PRN_USAGE_DAYS);
+    let recent = med
+        .history
+        .iter()
+        .filter_map(|record| parse_dose_timestamp(&record.timestamp))
+        .filter(|taken| *taken > since && *taken <= now)
+        .count();
+    (recent > 0).then(|| recent as f64 * units_per_dose(med) / PRN_USAGE_DAYS as f64)
+}
+
+/// Days the remaining stock will last at the expected usage
+pub fn days_of_supply(med: &Medication, now: NaiveDateTime) -> Option<f64> {
+    let stock = med.stock?;
+    let usage = daily_usage(med, now).filter(|u| *u > 0.0)?;
+    Some(stock / usage)
+}
+
+/// Takes one dose from the stock, if stock is tracked, using the oldest
+/// batch first. Never goes below zero.
+///
+/// Notes on `record` the units actually taken and what came from each batch,
+/// so `restore_dose` can put them back.
+pub fn consume_dose(med: &mut Medication, record: &mut DoseRecord) {
+    let units = units_per_dose(med);
+    let Some(stock) = med.stock.as_mut() else {
+        return;
+    };
+    let used = units.min(*stock).max(0.0);
+    *stock -= used;
+    record.stock_used = Some(used);
+    record.batches_used = take_from_batches(&mut med.batches, used);
+}
+
+/// Puts back what `record` took from the stock (used when a dose is
+/// un-taken), returning batched units to their batches
+pub fn restore_dose(med: &mut Medication, record: &DoseRecord) {
+    if let (Some(stock), Some(used)) = (med.stock.as_mut(), record.stock_used) {
+        *stock += used;
+        return_to_batches(&mut med.batches, &record.batches_used);
+    }
+}
+
+/// Formats a stock count without trailing ".0" ("60", "12.5")
+pub fn format_units(units: f64) -> String {
+    let rounded = (units * 100.0).round() / 100.0;
+    rounded.to_string()
+}
+
+/// "58 left (~29 days)" for `list` and `take`
+pub fn describe_stock(med: &Medication, now: NaiveDateTime) -> Option<String> {
+    let stock = med.stock?;
+    Some(match days_of_supply(med, now) {
+        Some(days) => format!("{} left (~{} days)", format_units(stock), days.floor()),
+        None => format!("{} left", format_units(stock)),
+    })
+}
+
+/// Returns a refill message if the tracked stock of `med` is empty or will
+/// last fewer than `threshold_days` days. A threshold of 0 disables this.
+pub fn refill_needed(med: &Medication, now: NaiveDateTime, threshold_days: u32) -> Option<String> {
+    let stock = med.stock?;
+    if threshold_days == 0 {
+        return None;
+    }
+
+    if stock <= 0.0 {
+        return Some(format!("{} is out of stock", med.name));
+    }
+
+    let days = days_of_supply(med, now)?;
+    (days < threshold_days as f64).then(|| {
+        format!(
+            "{}: {} left, about {} day(s) of supply",
+            med.name,
+            format_units(stock),
+            days.floor()
+        )
+    })
+}
+
+/// How a stock command changes the on-hand quantity
+pub enum StockChange {
+    /// Replace the count (after counting pills)
+    Set(f64),
+    /// Add a refill to the count
+    Add(f64),
+    /// Stop tracking stock
+    Clear,
+}
+
+/// `pharm stock set/add/clear`: updates the on-hand quantity and,
+/// optionally, the units taken per dose.
+///
+/// With `expires`, the quantity is recorded as a batch expiring on that date
+/// (`set` replaces all batches, `add` adds one).
+pub fn update_stock(
+    name: String,
+    change: StockChange,
+    per_dose: Option<f64>,
+    expires: Option<String>,
+    lot: Option<String>,
+) {
+    if per_dose.is_some_and(|units| !(units > 0.0 && units.is_finite())) {
+        eprintln!("Error: --per-dose must be greater than zero");
+        return;
+    }
+    if let Some(date) = &expires {
+        if parse_date(date).is_none() {
+            eprintln!(
+                "Error: Invalid expiry date '{}' (expected YYYY-MM-DD)",
+                date
+            );
+            return;
+        }
+    } else if lot.is_some() {
+        eprintln!("Error: --lot needs an --expires date");
+        return;
+    }
+    let amount = match change {
+        StockChange::Set(amount) | StockChange::Add(amount) => Some(amount),
+        StockChange::Clear => None,
+    };
+    if amount.is_some_and(|a| !(a >= 0.0 && a.is_finite())) {
+        eprintln!("Error: Quantity must be zero or more");
+        return;
+    }
+
+    let mut db = load_database();
+    let Some(index) = find_active_or_report(&db, &name) else {
+        return;
+    };
+    let med = &mut db.medications[index];
+
+    let batch = |quantity: f64| Batch {
+        lot: lot.clone().filter(|l| !l.trim().is_empty()),
+        quantity,
+        expires: expires.clone().unwrap_or_default().trim().to_string(),
+    };
+
+    match change {
+        StockChange::Set(amount) => {
+            med.stock = Some(amount);
+            if expires.is_some() {
+                med.batches = vec![batch(amount)];
+            } else {
+                trim_batches(&mut med.batches, amount);
+            }
+        }
+        StockChange::Add(amount) => {
+            med.stock = Some(med.stock.unwrap_or(0.0) + amount);
+            if expires.is_some() && amount > 0.0 {
+                med.batches.push(batch(amount));
+            }
+        }
+        StockChange::Clear => {
+            med.stock = None;
+            med.units_per_dose = None;
+            med.batches.clear();
+        }
+    }
+    med.batches.retain(|b| b.quantity > 0.0);
+    if per_dose.is_some() {
+        med.units_per_dose = per_dose;
+    }
+
+    let now = chrono::Local::now().naive_local();
+    match describe_stock(med, now) {
+        Some(summary) => println!(
+            "{} (#{}): {}, {} per dose",
+            med.name,
+            med.id,
+            summary,
+            format_units(units_per_dose(med))
+        ),
+        None => println!("{} (#{}): no longer tracking stock", med.name, med.id),
+    }
+    if !med.batches.is_empty() {
+        println!("  Batches: {}", describe_batches(&med.batches));
+    }
+    save_database(&db);
+}
+
+/// `pharm stock discard`: removes expired batches from the stock
+pub fn discard_expired(name: String) {
+    let mut db = load_database();
+    let Some(index) = find_active_or_report(&db, &name) else {
+        return;
+    };
+    let med = &mut db.medications[index];
+
+    let today = chrono::Local::now().date_naive();
+    let expired = remove_expired(&mut med.batches, today);
+    if expired.is_empty() {
+        println!("{} (#{}) has no expired batches.", med.name, med.id);
+        return;
+    }
+
+    for batch in &expired {
+        println!("Discarded {}", batch.describe());
+    }
+    let discarded: f64 = expired.iter().map(|b| b.quantity).sum();
+    if let Some(stock) = med.stock.as_mut() {
+        *stock = (*stock - discarded).max(0.0);
+    }
+
+    let now = chrono::Local::now().naive_local();
+    println!(
+        "{} (#{}): {}",
+        med.name,
+        med.id,
+        describe_stock(med, now).unwrap_or_default()
+    );
+    save_database(&db);
+}
+
+/// `pharm stock` / `pharm stock list`: remaining supply of every tracked
+/// medication, lowest days of supply first
+pub fn list_stock() {
+    let db = load_database();
+    let now = chrono::Local::now().naive_local();
+
+    let mut tracked: Vec<&Medication> = db
+        .medications
+        .iter()
+        .filter(|m| m.stock.is_some())
+        .collect();
+    if tracked.is_empty() {
+        println!("No medications have stock tracked.");
+        println!("Start with: pharm stock set <name> <quantity>");
+        return;
+    }
+
+    tracked.sort_by(|a, b| {
+        let days = |m: &Medication| days_of_supply(m, now).unwrap_or(f64::INFINITY);
+        days(a).total_cmp(&days(b))
+    });
+
+    let config = crate::config::load_config();
+    let today = now.date();
+
+    println!("\nMedication Stock:");
+    println!("{}", "=".repeat(60));
+    for med in tracked {
+        let refill = match refill_needed(med, now, config.refill_threshold_days) {
+            Some(_) => "  <- refill needed",
+            None => "",
+        };
+        println!(
+            "  {} (#{}): {}{}",
+            med.name,
+            med.id,
+            describe_stock(med, now).unwrap_or_default(),
+            refill
+        );
+        if !med.batches.is_empty() {
+            println!("      Batches: {}", describe_batches(&med.batches));
+        }
+        for alert in expiry_alerts(med, today, config.expiry_warning_days) {
+            println!("      Warning: {}", alert);
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dose::parse_dose;
+
+    fn med(dose: &str, freq: &str, stock: Option<f64>) -> Medication {
+        Medication {
+            name: "Test".to_string(),
+            dose: dose.to_string(),
+            dose_quantity: parse_dose(dose),
+            medication_frequency: freq.to_string(),
+            stock,
+            ..Default::default()
+        }
+    }
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2025-10-21 12:00", "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_units_per_dose() {
+        assert_eq!(units_per_dose(&med("500mg", "daily", None)), 1.0);
+        assert_eq!(units_per_dose(&med("2 tablets", "daily", None)), 2.0);
+        assert_eq!(units_per_dose(&med("1/2 tab", "daily", None)), 0.5);
+
+        let mut explicit = med("500mg", "daily", None);
+        explicit.units_per_dose = Some(2.0);
+        assert_eq!(units_per_dose(&explicit), 2.0);
+    }
+
+    #[test]
+    fn test_days_of_supply_scheduled() {
+        let aspirin = med("2 tablets", "twice daily", Some(60.0));
+        assert_eq!(days_of_supply(&aspirin, now()), Some(15.0));
+
+        let weekly = med("10mg", "weekly", Some(4.0));
+        assert_eq!(days_of_supply(&weekly, now()), Some(28.0));
+
+        assert_eq!(days_of_supply(&med("10mg", "daily", None), now()), None);
+    }
+
+    #[test]
+    fn test_days_of_supply_prn() {
+        let mut ibuprofen = med("400mg", "prn", Some(30.0));
+        assert_eq!(days_of_supply(&ibuprofen, now()), None);
+
+        // 15 doses in the last 30 days -> half a pill a day
+        ibuprofen.history = (1..=15)
+            .map(|day| DoseRecord {
+                timestamp: (now() - Duration::days(day))
+                    .format("%H:%M:%S - %Y/%m/%d")
+                    .to_string(),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(days_of_supply(&ibuprofen, now()), Some(60.0));
+    }
+
+    #[test]
+    fn test_consume_and_restore() {
+        let mut aspirin = med("2 tablets", "daily", Some(3.0));
+        let mut first = DoseRecord::default();
+        consume_dose(&mut aspirin, &mut first);
+        assert_eq!(first.stock_used, Some(2.0));
+        assert_eq!(aspirin.stock, Some(1.0));
+        // Only one tablet left to take
+        let mut second = DoseRecord::default();
+        consume_dose(&mut aspirin, &mut second);
+        assert_eq!(second.stock_used, Some(1.0));
+        assert_eq!(aspirin.stock, Some(0.0));
+        restore_dose(&mut aspirin, &second);
+        assert_eq!(aspirin.stock, Some(1.0));
+        restore_dose(&mut aspirin, &first);
+        assert_eq!(aspirin.stock, Some(3.0));
+
+        let mut untracked = med("2 tablets", "daily", None);
+        let mut record = DoseRecord::default();
+        consume_dose(&mut untracked, &mut record);
+        assert_eq!(record.stock_used, None);
+        assert_eq!(untracked.stock, None);
+    }
+
+    #[test]
+    fn test_consume_from_batches() {
+        let batch = |quantity: f64, expires: &str| Batch {
+            lot: None,
+            quantity,
+            expires: expires.to_string(),
+        };
+        // 10 without a batch, 1 expiring soon, 5 later
+        let mut aspirin = med("2 tablets", "daily", Some(16.0));
+        aspirin.batches = vec![batch(5.0, "2026-06-30"), batch(1.0, "2026-01-31")];
+
+        let mut record = DoseRecord::default();
+        consume_dose(&mut aspirin, &mut record);
+        assert_eq!(aspirin.stock, Some(14.0));
+        assert_eq!(aspirin.batches, vec![batch(4.0, "2026-06-30")]);
+
+        // Back into the batches they came from
+        restore_dose(&mut aspirin, &record);
+        assert_eq!(aspirin.stock, Some(16.0));
+        assert_eq!(
+            aspirin.batches,
+            vec![batch(1.0, "2026-01-31"), batch(5.0, "2026-06-30")]
+        );
+    }
+
+    #[test]
+    fn test_refill_needed() {
+        // 10 tablets at 2 a day -> 5 days
+        let low = med("1 tablet", "twice daily", Some(10.0));
+        assert!(refill_needed(&low, now(), 7)
+            .unwrap()
+            .contains("about 5 day(s)"));
+        assert_eq!(refill_needed(&low, now(), 5), None);
+        assert_eq!(refill_needed(&low, now(), 0), None);
+
+        let empty = med("400mg", "prn", Some(0.0));
+        assert!(refill_needed(&empty, now(), 7)
+            .unwrap()
+            .contains("out of stock"));
+
+        // Untracked stock or unknown PRN usage never asks for a refill
+        assert_eq!(
+            refill_needed(&med("1 tablet", "daily", None), now(), 7),
+            None
+        );
+        assert_eq!(
+            refill_needed(&med("400mg", "prn", Some(3.0)), now(), 7),
+            None
+        );
+    }
+}
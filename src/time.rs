@@ -1,4 +1,4 @@
-use chrono::{Local, Timelike};
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
 /// Parse time string in HH:MM format or named time (morning, noon, etc.)
 /// Accepts flexible formats:
 /// - Named times: "morning", "noon", "evening", etc.
@@ -54,6 +54,16 @@ pub fn parse_time(time_str: &str) -> Option<(u32, u32)> {
     None
 }
 
+/// Parse a YYYY-MM-DD date (prescription and batch expiry dates)
+pub fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+}
+
+/// Parse a dose record timestamp ("08:30:15 - 2025/10/21")
+pub fn parse_dose_timestamp(timestamp: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(timestamp.trim(), "%H:%M:%S - %Y/%m/%d").ok()
+}
+
 /// Check if current time is at or past the scheduled time
 pub fn is_time_due(scheduled_time: &str) -> bool {
     let Some((scheduled_hour, scheduled_min)) = parse_time(scheduled_time) else {
@@ -187,17 +197,25 @@ mod tests {
     #[test]
     fn test_is_time_due_invalid_input() {
         // Invalid time strings should return false (not due)
-        assert_eq!(is_time_due("garbage"), false);
-        assert_eq!(is_time_due("25:00"), false);
-        assert_eq!(is_time_due(""), false);
-        assert_eq!(is_time_due("invalid"), false);
+        assert!(!is_time_due("garbage"));
+        assert!(!is_time_due("25:00"));
+        assert!(!is_time_due(""));
+        assert!(!is_time_due("invalid"));
     }
 
     #[test]
     fn test_is_time_due_midnight() {
         // Midnight (00:00) should always be "due" since any time >= 00:00
         // Note: This test is time-dependent but midnight is special
-        assert_eq!(is_time_due("0:00"), true);
-        assert_eq!(is_time_due("midnight"), true);
+        assert!(is_time_due("0:00"));
+        assert!(is_time_due("midnight"));
+    }
+
+    #[test]
+    fn test_parse_dose_timestamp() {
+        let parsed = parse_dose_timestamp("08:30:15 - 2025/10/21").unwrap();
+        assert_eq!(parsed.to_string(), "2025-10-21 08:30:15");
+        assert!(parse_dose_timestamp("2025-10-21 08:30:15").is_none());
+        assert!(parse_dose_timestamp("").is_none());
     }
 }
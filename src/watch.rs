@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::get_config_file;
+use crate::database::get_data_file;
+
+/// Watches the database and config files for changes made by other `pharm`
+/// commands, using inotify on Linux (or the platform equivalent).
+///
+/// The containing directories are watched rather than the files themselves,
+/// because `save_database` replaces the file with an atomic rename.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    /// Sends `message` through `sender` every time a watched file changes.
+    /// One save can produce several events.
+    pub fn start<T: Clone + Send + 'static>(
+        sender: Sender<T>,
+        message: T,
+    ) -> Result<FileWatcher, String> {
+        let files: Vec<PathBuf> = vec![get_data_file(), get_config_file()];
+
+        let watched = files.clone();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            if let Ok(event) = result {
+                // Reads (including our own) show up as access events - skip them
+                let changed =
+                    event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove();
+                if changed && event.paths.iter().any(|path| watched.contains(path)) {
+                    let _ = sender.send(message.clone());
+                }
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+        let mut dirs: Vec<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(PathBuf::from))
+            .collect();
+        dirs.dedup();
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+
+        Ok(FileWatcher { _watcher: watcher })
+    }
+}